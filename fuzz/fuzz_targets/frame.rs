@@ -0,0 +1,6 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_guessing_game_over_net::fuzzing;
+
+fuzz_target!(|data: &[u8]| fuzzing::frame(data));
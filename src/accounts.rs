@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+/// A player name and the token its owner comes back with, the player id
+/// stays the same across connections and restarts.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Account {
+    pub id: String,
+    pub name: String,
+    pub token: String,
+}
+
+/// Registered players, a name belongs to the first one taking it. One Json
+/// account per line in an append only file, kept in memory only without one.
+#[derive(Default)]
+pub struct Accounts {
+    path: Option<PathBuf>,
+    accounts: HashMap<String, Account>,
+    /// Account ids by lower case name, "chico" is taken once "Chico" is.
+    names: HashMap<String, String>,
+}
+
+impl Accounts {
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Accounts, String> {
+        let path = path.into();
+        let mut accounts = Accounts::default();
+
+        if let Ok(file) = File::open(&path) {
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|error| format!("{}", error))?;
+                let account = serde_json::from_str(&line).map_err(|error| format!("{}", error))?;
+                accounts.insert(account);
+            }
+        }
+
+        Ok(Accounts {
+            path: Some(path),
+            ..accounts
+        })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Account> {
+        self.accounts.get(id)
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Account> {
+        self.names
+            .get(&name.to_lowercase())
+            .and_then(|id| self.accounts.get(id))
+    }
+
+    pub fn add(&mut self, account: Account) -> Result<(), String> {
+        if self.by_name(&account.name).is_some() {
+            return Err(String::from("Name is taken, resume with its token"));
+        }
+
+        if let Some(path) = &self.path {
+            let mut line = serde_json::to_string(&account).map_err(|error| format!("{}", error))?;
+            line.push('\n');
+
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|error| format!("{}", error))?;
+            file.write_all(line.as_bytes())
+                .and_then(|_| file.sync_data())
+                .map_err(|error| format!("{}", error))?;
+        }
+
+        self.insert(account);
+        Ok(())
+    }
+
+    fn insert(&mut self, account: Account) {
+        self.names
+            .insert(account.name.to_lowercase(), account.id.clone());
+        self.accounts.insert(account.id.clone(), account);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::accounts::{Account, Accounts};
+    use std::env;
+
+    fn account(id: &str, name: &str) -> Account {
+        Account {
+            id: String::from(id),
+            name: String::from(name),
+            token: format!("{}-token", id),
+        }
+    }
+
+    #[test]
+    fn test_accounts() {
+        let path = env::temp_dir().join(format!("accounts-{}.jsonl", uuid::Uuid::new_v4()));
+        let mut accounts = Accounts::open(&path).unwrap();
+
+        accounts.add(account("chico", "Chico")).unwrap();
+        assert_eq!(
+            Err(String::from("Name is taken, resume with its token")),
+            accounts.add(account("impostor", "CHICO"))
+        );
+        accounts.add(account("paloma", "Paloma")).unwrap();
+
+        // Accounts are back after a restart, names still taken
+        let accounts = Accounts::open(&path).unwrap();
+        assert_eq!(Some(&account("chico", "Chico")), accounts.get("chico"));
+        assert_eq!(
+            Some(&account("paloma", "Paloma")),
+            accounts.by_name("paloma")
+        );
+        assert_eq!(None, accounts.get("impostor"));
+    }
+}
@@ -0,0 +1,607 @@
+use crate::client::{handshake, Event, GuessResult, GUESS_ANSWERS};
+use crate::game::GameRules;
+use crate::protocol::{
+    Frame, GameInfo, Method, UserInfo, ERROR, GAME, GAMES, GAME_CREATE, GAME_JOIN, GAME_LIST,
+    GUESS, HEARTBEAT, HEARTBEAT_OK, REGISTRATION_OK, REGISTRATION_RESUME, SECRET_NUMBER, USER,
+};
+use crate::transport::Transport;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use futures::channel::oneshot;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::net::TcpStream;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Heartbeats left unanswered before the connection is considered lost.
+const HEARTBEAT_MISSES: u32 = 3;
+const RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Typed server pushes. The stream ends once the client is closed or gave
+/// up reconnecting, dropping it stops the client.
+pub type EventStream = UnboundedReceiver<Event>;
+
+type Connect<T> = Box<dyn Fn() -> Result<T, String> + Send>;
+
+struct Request {
+    frame: Frame,
+    answers: &'static [Method],
+    reply: oneshot::Sender<Result<Frame, String>>,
+}
+
+/// What the session thread reacts to, one at a time.
+enum Input {
+    Request(Request),
+    Send(Frame, oneshot::Sender<Result<(), String>>),
+    /// Frames and disconnections carry the generation of their connection,
+    /// the ones of a replaced connection are ignored.
+    Received(u32, Frame),
+    Disconnected(u32),
+    Tick,
+    Close,
+}
+
+/// Async client for bots reacting to server pushes while sending requests.
+/// Requests resolve with their answer and everything else goes to the
+/// `EventStream`. The connection runs on its own threads, so any executor
+/// can drive the futures.
+#[derive(Clone)]
+pub struct AsyncGuessingClient {
+    inputs: Sender<Input>,
+}
+
+impl AsyncGuessingClient {
+    pub fn connect(address: &str) -> Result<(Self, EventStream), String> {
+        let address = address.to_string();
+        AsyncGuessingClient::builder(move || {
+            TcpStream::connect(&address).map_err(|error| format!("{}", error))
+        })
+        .start()
+    }
+
+    /// `connect` opens a new stream, it is called again on every reconnection.
+    pub fn builder<T, F>(connect: F) -> AsyncClientBuilder<T>
+    where
+        T: Transport,
+        F: Fn() -> Result<T, String> + Send + 'static,
+    {
+        AsyncClientBuilder {
+            connect: Box::new(connect),
+            heartbeat_interval: HEARTBEAT_INTERVAL,
+            reconnect_attempts: RECONNECT_ATTEMPTS,
+            reconnect_delay: RECONNECT_DELAY,
+        }
+    }
+
+    pub async fn register(&self, name: &str) -> Result<UserInfo, String> {
+        self.request(Frame::text(REGISTRATION_OK, name), &[USER])
+            .await?
+            .json_payload()
+    }
+
+    /// Games waiting for a player.
+    pub async fn list_games(&self) -> Result<Vec<GameInfo>, String> {
+        self.request(Frame::empty(GAME_LIST), &[GAMES])
+            .await?
+            .json_payload()
+    }
+
+    pub async fn create_game(&self, rules: GameRules) -> Result<GameInfo, String> {
+        self.request(Frame::json(GAME_CREATE, &rules), &[GAME])
+            .await?
+            .json_payload()
+    }
+
+    pub async fn join(&self, game_id: &str) -> Result<GameInfo, String> {
+        self.request(Frame::text(GAME_JOIN, game_id), &[GAME])
+            .await?
+            .json_payload()
+    }
+
+    /// The started game and the first turn come as events.
+    pub async fn set_secret(&self, secret_number: u16) -> Result<(), String> {
+        self.send(Frame::number(SECRET_NUMBER, secret_number)).await
+    }
+
+    pub async fn guess(&self, number: u16) -> Result<GuessResult, String> {
+        let frame = self
+            .request(Frame::number(GUESS, number), &GUESS_ANSWERS)
+            .await?;
+        GuessResult::try_from(Event::try_from(frame)?)
+    }
+
+    /// Sends any frame without waiting for an answer, like chat messages.
+    pub async fn send(&self, frame: Frame) -> Result<(), String> {
+        let (reply, sent) = oneshot::channel();
+        self.inputs
+            .send(Input::Send(frame, reply))
+            .map_err(|_| String::from("Client closed"))?;
+        sent.await.map_err(|_| String::from("Client closed"))?
+    }
+
+    pub fn close(&self) {
+        self.inputs.send(Input::Close).ok();
+    }
+
+    async fn request(&self, frame: Frame, answers: &'static [Method]) -> Result<Frame, String> {
+        let (reply, answer) = oneshot::channel();
+        self.inputs
+            .send(Input::Request(Request {
+                frame,
+                answers,
+                reply,
+            }))
+            .map_err(|_| String::from("Client closed"))?;
+        answer.await.map_err(|_| String::from("Client closed"))?
+    }
+}
+
+pub struct AsyncClientBuilder<T: Transport> {
+    connect: Connect<T>,
+    heartbeat_interval: Duration,
+    reconnect_attempts: u32,
+    reconnect_delay: Duration,
+}
+
+impl<T: Transport> AsyncClientBuilder<T> {
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    pub fn with_reconnect(mut self, attempts: u32, delay: Duration) -> Self {
+        self.reconnect_attempts = attempts;
+        self.reconnect_delay = delay;
+        self
+    }
+
+    /// Connects once before returning, so an unreachable server fails here.
+    pub fn start(self) -> Result<(AsyncGuessingClient, EventStream), String> {
+        let mut stream = (self.connect)()?;
+        handshake(&mut stream)?;
+
+        let (inputs, input_rx) = channel::<Input>();
+        let (events_tx, events) = unbounded::<Event>();
+        let mut session = Session {
+            connect: self.connect,
+            stream,
+            generation: 0,
+            user: None,
+            pending: VecDeque::new(),
+            events: events_tx,
+            inputs: inputs.clone(),
+            last_received: Instant::now(),
+            heartbeat_interval: self.heartbeat_interval,
+            reconnect_attempts: self.reconnect_attempts,
+            reconnect_delay: self.reconnect_delay,
+        };
+        session.listen()?;
+
+        start_ticker(inputs.clone(), self.heartbeat_interval);
+        thread::spawn(move || session.run(input_rx));
+        Ok((AsyncGuessingClient { inputs }, events))
+    }
+}
+
+fn start_ticker(inputs: Sender<Input>, interval: Duration) {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+        if inputs.send(Input::Tick).is_err() {
+            break;
+        }
+    });
+}
+
+struct Session<T: Transport> {
+    connect: Connect<T>,
+    stream: T,
+    generation: u32,
+    user: Option<UserInfo>,
+    pending: VecDeque<Request>,
+    events: UnboundedSender<Event>,
+    inputs: Sender<Input>,
+    last_received: Instant,
+    heartbeat_interval: Duration,
+    reconnect_attempts: u32,
+    reconnect_delay: Duration,
+}
+
+impl<T: Transport> Session<T> {
+    fn run(mut self, inputs: Receiver<Input>) {
+        for input in inputs {
+            match input {
+                Input::Request(request) => self.request(request),
+                Input::Send(frame, reply) => {
+                    reply.send(self.write(frame)).ok();
+                }
+                Input::Received(generation, frame) if generation == self.generation => {
+                    self.receive(frame)
+                }
+                Input::Disconnected(generation) if generation == self.generation => {
+                    if !self.reconnect() {
+                        break;
+                    }
+                }
+                Input::Tick => {
+                    if self.events.is_closed() {
+                        break;
+                    }
+                    self.heartbeat();
+                }
+                Input::Close => break,
+                _ => {}
+            }
+        }
+        self.stream.close().ok();
+    }
+
+    /// Reads frames on its own thread until the stream fails.
+    fn listen(&mut self) -> Result<(), String> {
+        let mut reader = self
+            .stream
+            .try_clone()
+            .map_err(|error| format!("{}", error))?;
+        self.generation += 1;
+        self.last_received = Instant::now();
+
+        let generation = self.generation;
+        let inputs = self.inputs.clone();
+        thread::spawn(move || loop {
+            match Frame::read_from(&mut reader) {
+                Ok(frame) => {
+                    if inputs.send(Input::Received(generation, frame)).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => {
+                    inputs.send(Input::Disconnected(generation)).ok();
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn write(&mut self, frame: Frame) -> Result<(), String> {
+        write(&mut self.stream, frame)
+    }
+
+    fn request(&mut self, request: Request) {
+        match self.write(request.frame.clone()) {
+            Ok(()) => self.pending.push_back(request),
+            Err(error) => {
+                request.reply.send(Err(error)).ok();
+            }
+        }
+    }
+
+    /// Answers go to the oldest request waiting for them, an Error frame
+    /// fails the oldest request and anything else is an event.
+    fn receive(&mut self, frame: Frame) {
+        self.last_received = Instant::now();
+        match frame.method() {
+            HEARTBEAT_OK => return,
+            USER => self.user = frame.json_payload().ok(),
+            _ => {}
+        }
+
+        let answered = self
+            .pending
+            .iter()
+            .position(|request| request.answers.contains(&frame.method()));
+        let request = match answered {
+            Some(index) => self.pending.remove(index),
+            None if frame.method() == ERROR => self.pending.pop_front(),
+            None => None,
+        };
+
+        match request {
+            Some(request) if frame.method() == ERROR => {
+                request
+                    .reply
+                    .send(Err(frame.text_payload().unwrap_or_default()))
+                    .ok();
+            }
+            Some(request) => {
+                request.reply.send(Ok(frame)).ok();
+            }
+            None => match Event::try_from(frame) {
+                Ok(event) => {
+                    self.events.unbounded_send(event).ok();
+                }
+                Err(error) => println!("Frame not understood: {}", error),
+            },
+        }
+    }
+
+    /// A connection silent for too many heartbeats is closed, its reader
+    /// then reports the disconnection.
+    fn heartbeat(&mut self) {
+        if self.last_received.elapsed() > self.heartbeat_interval * HEARTBEAT_MISSES {
+            self.stream.close().ok();
+        } else {
+            self.write(Frame::empty(HEARTBEAT)).ok();
+        }
+    }
+
+    /// Requests waiting on the lost connection fail. Returns false once it
+    /// gave up, after telling the event stream.
+    fn reconnect(&mut self) -> bool {
+        self.stream.close().ok();
+        for request in self.pending.drain(..) {
+            request
+                .reply
+                .send(Err(String::from("Connection lost")))
+                .ok();
+        }
+
+        for attempt in 1..=self.reconnect_attempts {
+            thread::sleep(self.reconnect_delay);
+            match self.open() {
+                Ok(event) => {
+                    if let Some(event) = event {
+                        self.events.unbounded_send(event).ok();
+                    }
+                    return true;
+                }
+                Err(error) => println!("Reconnection attempt {} failed: {}", attempt, error),
+            }
+        }
+
+        self.events.unbounded_send(Event::Disconnected).ok();
+        false
+    }
+
+    /// Players the server does not resume register again under the same
+    /// name. Returns the event telling which one happened, if registered.
+    fn open(&mut self) -> Result<Option<Event>, String> {
+        let mut stream = (self.connect)()?;
+        handshake(&mut stream)?;
+
+        let mut resumed = true;
+        if let Some(user) = self.user.clone() {
+            write(&mut stream, Frame::json(REGISTRATION_RESUME, &user))?;
+            if let Err(error) = self.read_user(&mut stream) {
+                println!("Player not resumed: {}", error);
+                resumed = false;
+
+                stream = (self.connect)()?;
+                handshake(&mut stream)?;
+                write(&mut stream, Frame::text(REGISTRATION_OK, &user.name))?;
+                self.read_user(&mut stream)?;
+            }
+        }
+
+        self.stream = stream;
+        self.listen()?;
+        Ok(self.user.clone().map(|user| {
+            if resumed {
+                Event::Reconnected(user)
+            } else {
+                Event::Reregistered(user)
+            }
+        }))
+    }
+
+    fn read_user(&mut self, stream: &mut T) -> Result<(), String> {
+        loop {
+            let frame = Frame::read_from(stream)?;
+            match frame.method() {
+                USER => {
+                    self.user = Some(frame.json_payload()?);
+                    return Ok(());
+                }
+                ERROR => return Err(frame.text_payload()?),
+                _ => self.receive(frame),
+            }
+        }
+    }
+}
+
+fn write<T: Transport>(stream: &mut T, frame: Frame) -> Result<(), String> {
+    stream
+        .write_all(&Vec::<u8>::from(frame))
+        .map_err(|error| format!("{}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::async_client::AsyncGuessingClient;
+    use crate::client::{Event, GuessResult};
+    use crate::protocol::{
+        ChatChannel, ChatMessage, ConnectionConstraints, Frame, UserInfo, CHAT_MESSAGE, ERROR,
+        GUESS, HEARTBEAT, REGISTRATION, REGISTRATION_OK, REGISTRATION_RESUME, TURN, USER,
+        WRONG_ANSWER,
+    };
+    use crate::transport::{duplex, Duplex, Transport};
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::io::{Read, Write};
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::thread;
+    use std::time::Duration;
+
+    /// Every connection hands its server end to the test, which plays the
+    /// server on another thread.
+    fn connector(servers: Sender<Duplex>) -> impl Fn() -> Result<Duplex, String> + Send + 'static {
+        move || {
+            let (client, mut server) = duplex();
+            let registration = Frame::json(REGISTRATION, &ConnectionConstraints::default());
+            server.write_all(&Vec::<u8>::from(registration)).unwrap();
+            servers
+                .send(server)
+                .map_err(|_| String::from("Server is gone"))?;
+            Ok(client)
+        }
+    }
+
+    fn accept(servers: &Receiver<Duplex>) -> Duplex {
+        let mut server = servers.recv().unwrap();
+        let mut header = [0; 5];
+        server.read_exact(&mut header).unwrap();
+        assert_eq!(b"GG010", &header);
+        server
+    }
+
+    fn answer(server: &mut Duplex, frames: Vec<Frame>) {
+        for frame in frames {
+            server.write_all(&Vec::<u8>::from(frame)).unwrap();
+        }
+    }
+
+    /// Heartbeats are sent whenever a tick comes, requests are read past them.
+    fn read_request(server: &mut Duplex) -> Frame {
+        loop {
+            let frame = Frame::read_from(server).unwrap();
+            if frame.method() != HEARTBEAT {
+                return frame;
+            }
+        }
+    }
+
+    fn user(id: &str) -> UserInfo {
+        UserInfo {
+            id: String::from(id),
+            name: String::from("Chico"),
+            stats: None,
+            token: None,
+        }
+    }
+
+    #[test]
+    fn test_async_client() {
+        let (servers_tx, servers) = channel();
+        let (client, mut events) = AsyncGuessingClient::builder(connector(servers_tx))
+            .start()
+            .unwrap();
+        let chat = ChatMessage {
+            channel: ChatChannel::Game,
+            from: user("paloma"),
+            text: String::from("Good luck"),
+        };
+
+        let server_chat = chat.clone();
+        let server = thread::spawn(move || {
+            let mut server = accept(&servers);
+            assert_eq!(
+                Frame::text(REGISTRATION_OK, "Chico"),
+                read_request(&mut server)
+            );
+            answer(&mut server, vec![Frame::json(USER, &user("chico"))]);
+
+            assert_eq!(Frame::number(GUESS, 10), read_request(&mut server));
+            answer(
+                &mut server,
+                vec![
+                    Frame::json(CHAT_MESSAGE, &server_chat),
+                    Frame::text(WRONG_ANSWER, "Bigger"),
+                    Frame::empty(TURN),
+                ],
+            );
+        });
+
+        block_on(async {
+            assert_eq!(Ok(user("chico")), client.register("Chico").await);
+            assert_eq!(
+                Ok(GuessResult::WrongAnswer {
+                    hint: String::from("Bigger")
+                }),
+                client.guess(10).await
+            );
+            assert_eq!(Some(Event::Chat(chat)), events.next().await);
+            assert_eq!(Some(Event::Turn), events.next().await);
+
+            client.close();
+            assert_eq!(None, events.next().await);
+            assert_eq!(Err(String::from("Client closed")), client.guess(20).await);
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_client_resumes() {
+        let (servers_tx, servers) = channel();
+        let (client, mut events) = AsyncGuessingClient::builder(connector(servers_tx))
+            .with_reconnect(1, Duration::from_millis(0))
+            .start()
+            .unwrap();
+
+        let server = thread::spawn(move || {
+            let mut server = accept(&servers);
+            read_request(&mut server);
+            answer(&mut server, vec![Frame::json(USER, &user("chico"))]);
+            server.close().unwrap();
+
+            let mut server = accept(&servers);
+            assert_eq!(
+                Frame::json(REGISTRATION_RESUME, &user("chico")),
+                read_request(&mut server)
+            );
+            answer(&mut server, vec![Frame::json(USER, &user("chico"))]);
+            server
+        });
+
+        block_on(async {
+            assert_eq!(Ok(user("chico")), client.register("Chico").await);
+            assert_eq!(Some(Event::Reconnected(user("chico"))), events.next().await);
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_async_client_reconnects() {
+        let (servers_tx, servers) = channel();
+        let (client, mut events) = AsyncGuessingClient::builder(connector(servers_tx))
+            .with_heartbeat(Duration::from_millis(20))
+            .with_reconnect(1, Duration::from_millis(0))
+            .start()
+            .unwrap();
+
+        let server = thread::spawn(move || {
+            // Registers, then leaves every heartbeat unanswered
+            let mut server = accept(&servers);
+            read_request(&mut server);
+            answer(&mut server, vec![Frame::json(USER, &user("chico"))]);
+            assert_eq!(HEARTBEAT, Frame::read_from(&mut server).unwrap().method());
+            while Frame::read_from(&mut server).is_ok() {}
+
+            // Nothing to resume, the server did not restart
+            let mut server = accept(&servers);
+            assert_eq!(
+                Frame::json(REGISTRATION_RESUME, &user("chico")),
+                read_request(&mut server)
+            );
+            answer(
+                &mut server,
+                vec![Frame::text(ERROR, "Nothing to resume for this player")],
+            );
+            server.close().unwrap();
+
+            let mut server = accept(&servers);
+            assert_eq!(
+                Frame::text(REGISTRATION_OK, "Chico"),
+                read_request(&mut server)
+            );
+            answer(&mut server, vec![Frame::json(USER, &user("chico-2"))]);
+            server
+        });
+
+        block_on(async {
+            assert_eq!(Ok(user("chico")), client.register("Chico").await);
+            assert_eq!(
+                Some(Event::Reregistered(user("chico-2"))),
+                events.next().await
+            );
+        });
+
+        // The last server is gone too and there are no attempts left
+        server.join().unwrap().close().unwrap();
+        block_on(async {
+            assert_eq!(Some(Event::Disconnected), events.next().await);
+            assert_eq!(None, events.next().await);
+        });
+    }
+}
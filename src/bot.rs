@@ -0,0 +1,328 @@
+use crate::game::{Game, GameMessages, GameRules, Hint, Player, BOT_ID_PREFIX};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use uuid::Uuid;
+
+pub trait Strategy: Send {
+    fn next_guess(&mut self) -> u16;
+
+    fn learn(&mut self, hint: Hint);
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum StrategyKind {
+    BinarySearch,
+    Random,
+    Weak,
+}
+
+impl StrategyKind {
+    pub fn build(&self, rules: &GameRules, seed: u64) -> Box<dyn Strategy> {
+        match self {
+            StrategyKind::BinarySearch => Box::new(BinarySearch::new(rules)),
+            StrategyKind::Random => Box::new(RandomGuess::new(rules, seed)),
+            StrategyKind::Weak => Box::new(Weak::new(rules)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct BotChallenge {
+    pub rules: GameRules,
+    pub strategy: StrategyKind,
+}
+
+/// Numbers that can still be the secret one, given the hints received so far.
+struct Candidates {
+    rules: GameRules,
+    low: u16,
+    high: u16,
+    tried: HashSet<u16>,
+}
+
+impl Candidates {
+    fn new(rules: &GameRules) -> Candidates {
+        Candidates {
+            rules: rules.clone(),
+            low: rules.min,
+            high: rules.max,
+            tried: HashSet::new(),
+        }
+    }
+
+    fn narrow(&mut self, low: u16, high: u16) {
+        self.low = self.low.max(low);
+        self.high = self.high.min(high).max(self.low);
+    }
+
+    fn learn(&mut self, hint: Hint) {
+        let range = self.rules.max - self.rules.min;
+
+        match hint {
+            Hint::Less(number) => self.narrow(self.rules.min, number.saturating_sub(1)),
+            Hint::Greater(number) => self.narrow(number.saturating_add(1), self.rules.max),
+            Hint::Hot(number) | Hint::Warm(number) => {
+                let radius = if let Hint::Hot(_) = hint {
+                    range / 10
+                } else {
+                    range / 4
+                };
+                self.narrow(number.saturating_sub(radius), number.saturating_add(radius));
+            }
+            Hint::Cold(_) | Hint::TimeIsOver => {}
+        }
+    }
+
+    /// Closest number to `target` not guessed yet, inside the candidates range.
+    fn untried_near(&mut self, target: u16) -> u16 {
+        let guess = (0..=(self.high - self.low))
+            .flat_map(|offset| vec![target.checked_add(offset), target.checked_sub(offset)])
+            .flatten()
+            .find(|number| {
+                *number >= self.low && *number <= self.high && !self.tried.contains(number)
+            })
+            .unwrap_or(target);
+
+        self.tried.insert(guess);
+        guess
+    }
+}
+
+pub struct BinarySearch {
+    candidates: Candidates,
+}
+
+impl BinarySearch {
+    pub fn new(rules: &GameRules) -> BinarySearch {
+        BinarySearch {
+            candidates: Candidates::new(rules),
+        }
+    }
+}
+
+impl Strategy for BinarySearch {
+    fn next_guess(&mut self) -> u16 {
+        let middle = self.candidates.low + (self.candidates.high - self.candidates.low) / 2;
+        self.candidates.untried_near(middle)
+    }
+
+    fn learn(&mut self, hint: Hint) {
+        self.candidates.learn(hint);
+    }
+}
+
+pub struct RandomGuess {
+    candidates: Candidates,
+    rng: StdRng,
+}
+
+impl RandomGuess {
+    pub fn new(rules: &GameRules, seed: u64) -> RandomGuess {
+        RandomGuess {
+            candidates: Candidates::new(rules),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Strategy for RandomGuess {
+    fn next_guess(&mut self) -> u16 {
+        let target = self
+            .rng
+            .gen_range(self.candidates.low..=self.candidates.high);
+        self.candidates.untried_near(target)
+    }
+
+    fn learn(&mut self, hint: Hint) {
+        self.candidates.learn(hint);
+    }
+}
+
+/// Counts up from the smallest number and ignores every hint, meant for
+/// players that just want to win once in a while.
+pub struct Weak {
+    next: u16,
+    rules: GameRules,
+}
+
+impl Weak {
+    pub fn new(rules: &GameRules) -> Weak {
+        Weak {
+            next: rules.min,
+            rules: rules.clone(),
+        }
+    }
+}
+
+impl Strategy for Weak {
+    fn next_guess(&mut self) -> u16 {
+        let guess = self.next;
+        self.next = if guess >= self.rules.max {
+            self.rules.min
+        } else {
+            guess + 1
+        };
+        guess
+    }
+
+    fn learn(&mut self, _hint: Hint) {}
+}
+
+/// Keeps one strategy per opponent, hints about one secret number say
+/// nothing about the others.
+pub struct Bot {
+    pub player: Player,
+    kind: StrategyKind,
+    rules: GameRules,
+    seed: u64,
+    strategies: HashMap<String, Box<dyn Strategy>>,
+}
+
+impl Bot {
+    pub fn new(kind: StrategyKind, rules: &GameRules, seed: u64) -> Bot {
+        Bot {
+            player: Player::with_id(
+                format!("{}{:?}:{}", BOT_ID_PREFIX, kind, Uuid::new_v4()),
+                format!("{:?} Bot", kind),
+            ),
+            kind,
+            rules: rules.clone(),
+            seed,
+            strategies: HashMap::new(),
+        }
+    }
+
+    /// Time the bot waits before guessing, always well inside the turn time.
+    pub fn think_time(rules: &GameRules) -> Duration {
+        Duration::from_millis((rules.turn_seconds as u64 * 1000 / 2).min(1000))
+    }
+
+    pub fn play_turn(&mut self, game: &mut Game) -> Result<GameMessages, String> {
+        if game.turn_player() != Some(&self.player) {
+            return Ok(GameMessages::NotYourTurn);
+        }
+
+        let target_id = match game.next_target(&self.player) {
+            Some(target) => target.id.clone(),
+            None => return Err(String::from("No opponent left to guess")),
+        };
+
+        let (kind, rules, seed) = (self.kind, &self.rules, self.seed);
+        let strategy = self
+            .strategies
+            .entry(target_id.clone())
+            .or_insert_with(|| kind.build(rules, seed));
+
+        let guess = strategy.next_guess();
+        let message = game.guess_number_at(&self.player, &target_id, guess)?;
+
+        if let GameMessages::WrongAnswer(hint) = message {
+            strategy.learn(hint);
+        }
+
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bot::{BinarySearch, Bot, RandomGuess, Strategy, StrategyKind, Weak};
+    use crate::game::{Game, GameMessages, GameRules, HintStyle, Player};
+    use std::time::Duration;
+
+    fn guesses_to_win(strategy: &mut dyn Strategy, rules: &GameRules, secret_number: u16) -> u32 {
+        let mut guesses = 0;
+
+        loop {
+            guesses += 1;
+            let guess = strategy.next_guess();
+            if guess == secret_number {
+                return guesses;
+            }
+            strategy.learn(rules.hint(guess, secret_number));
+            assert!(guesses < 1000, "Strategy never finds {}", secret_number);
+        }
+    }
+
+    #[test]
+    fn test_binary_search() {
+        let rules = GameRules::default();
+
+        for secret_number in rules.min..=rules.max {
+            let mut strategy = BinarySearch::new(&rules);
+            assert!(guesses_to_win(&mut strategy, &rules, secret_number) <= 7);
+        }
+    }
+
+    #[test]
+    fn test_binary_search_hot_cold() {
+        let rules = GameRules {
+            hint_style: HintStyle::HotCold,
+            ..GameRules::default()
+        };
+
+        for secret_number in rules.min..=rules.max {
+            let mut strategy = BinarySearch::new(&rules);
+            assert!(guesses_to_win(&mut strategy, &rules, secret_number) <= 100);
+        }
+    }
+
+    #[test]
+    fn test_random_guess() {
+        let rules = GameRules::default();
+        let mut strategy = RandomGuess::new(&rules, 42);
+
+        assert!(guesses_to_win(&mut strategy, &rules, 42) <= 100);
+    }
+
+    #[test]
+    fn test_weak() {
+        let rules = GameRules::default();
+        let mut strategy = Weak::new(&rules);
+
+        assert_eq!(42, guesses_to_win(&mut strategy, &rules, 42));
+    }
+
+    #[test]
+    fn test_think_time() {
+        let rules = GameRules {
+            turn_seconds: 1,
+            ..GameRules::default()
+        };
+
+        assert_eq!(Duration::from_millis(500), Bot::think_time(&rules));
+        assert_eq!(
+            Duration::from_millis(1000),
+            Bot::think_time(&GameRules::default())
+        );
+    }
+
+    #[test]
+    fn test_bot_plays_through_game() {
+        let player = Player::new(String::from("Chico"));
+        let rules = GameRules {
+            lives: 10,
+            ..GameRules::default()
+        };
+        let mut bot = Bot::new(StrategyKind::BinarySearch, &rules, 42);
+
+        let mut game = Game::new(&player, rules).unwrap();
+        game.accept_challenge(&bot.player).unwrap();
+        game.set_secret_number(&player, 42).unwrap();
+        game.set_secret_number(&bot.player, 42).unwrap();
+        game.set_ready().unwrap();
+        game.start().unwrap();
+
+        assert_eq!(Ok(GameMessages::NotYourTurn), bot.play_turn(&mut game));
+
+        loop {
+            game.guess_number(&player, 1).unwrap();
+            if let GameMessages::YouWin = bot.play_turn(&mut game).unwrap() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Duration, Local};
+use std::collections::VecDeque;
+
+/// Server side hook every chat message goes through before being fanned out,
+/// it can rewrite the text or reject the message.
+pub trait ChatFilter: Send {
+    fn filter(&self, text: &str) -> Result<String, String>;
+}
+
+/// Masks blocked words with `*`, case insensitive.
+#[derive(Default)]
+pub struct Blocklist {
+    words: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn new(words: &[&str]) -> Blocklist {
+        Blocklist {
+            words: words.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+}
+
+impl ChatFilter for Blocklist {
+    fn filter(&self, text: &str) -> Result<String, String> {
+        let filtered = text
+            .split(' ')
+            .map(|word| {
+                let bare = word.trim_matches(|c: char| !c.is_alphanumeric());
+                if self.words.contains(&bare.to_lowercase()) {
+                    word.replace(bare, &"*".repeat(bare.chars().count()))
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        Ok(filtered)
+    }
+}
+
+/// Sliding window limit on how many messages a player can send.
+pub struct RateLimiter {
+    max: usize,
+    window: Duration,
+    sent: VecDeque<DateTime<Local>>,
+}
+
+impl RateLimiter {
+    pub fn new(max: usize, window: Duration) -> RateLimiter {
+        RateLimiter {
+            max,
+            window,
+            sent: VecDeque::new(),
+        }
+    }
+
+    pub fn allow(&mut self, now: DateTime<Local>) -> bool {
+        while let Some(sent_at) = self.sent.front() {
+            if now - *sent_at < self.window {
+                break;
+            }
+            self.sent.pop_front();
+        }
+
+        if self.sent.len() >= self.max {
+            return false;
+        }
+
+        self.sent.push_back(now);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chat::{Blocklist, ChatFilter, RateLimiter};
+    use chrono::{Duration, Local};
+
+    #[test]
+    fn test_blocklist() {
+        let blocklist = Blocklist::new(&["darn", "Heck"]);
+
+        assert_eq!(
+            Ok(String::from("**** it, what the ****!")),
+            blocklist.filter("Darn it, what the heck!")
+        );
+        assert_eq!(
+            Ok(String::from("darning is fine")),
+            blocklist.filter("darning is fine")
+        );
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2, Duration::seconds(10));
+        let now = Local::now();
+
+        assert!(limiter.allow(now));
+        assert!(limiter.allow(now + Duration::seconds(1)));
+        assert!(!limiter.allow(now + Duration::seconds(2)));
+        assert!(limiter.allow(now + Duration::seconds(10)));
+        assert!(!limiter.allow(now + Duration::seconds(10)));
+    }
+}
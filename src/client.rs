@@ -0,0 +1,353 @@
+use crate::game::GameRules;
+use crate::protocol::{
+    ChatMessage, ConnectionConstraints, Frame, GameInfo, Method, UserInfo, CHAT_MESSAGE, CRACKED,
+    ERROR, GAME, GAMES, GAME_CREATE, GAME_JOIN, GAME_LIST, GUESS, NOT_YOUR_TURN, PROTOCOL_HEADER,
+    REGISTRATION, REGISTRATION_OK, REGISTRATION_RESUME, SECRET_NUMBER, TURN, USER, WRONG_ANSWER,
+    YOU_LOSE, YOU_WIN,
+};
+use crate::tls::{self, TlsStream};
+use crate::transport::Transport;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::net::TcpStream;
+
+pub(crate) const GUESS_ANSWERS: [Method; 5] =
+    [WRONG_ANSWER, CRACKED, YOU_WIN, YOU_LOSE, NOT_YOUR_TURN];
+
+/// What the server answered to a guess.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuessResult {
+    WrongAnswer {
+        hint: String,
+    },
+    /// The secret number of another player was found, the game goes on
+    /// while more than one player is left.
+    Cracked {
+        name: String,
+    },
+    YouWin,
+    YouLose,
+    NotYourTurn,
+}
+
+impl TryFrom<Event> for GuessResult {
+    type Error = String;
+
+    fn try_from(event: Event) -> Result<Self, Self::Error> {
+        match event {
+            Event::WrongAnswer { hint } => Ok(GuessResult::WrongAnswer { hint }),
+            Event::Cracked { name } => Ok(GuessResult::Cracked { name }),
+            Event::YouWin => Ok(GuessResult::YouWin),
+            Event::YouLose => Ok(GuessResult::YouLose),
+            Event::NotYourTurn => Ok(GuessResult::NotYourTurn),
+            event => Err(format!("Unexpected answer to a guess {:?}", event)),
+        }
+    }
+}
+
+/// Frames the server sends on its own, like turns or the end of a game.
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Game(GameInfo),
+    Turn,
+    WrongAnswer {
+        hint: String,
+    },
+    Cracked {
+        name: String,
+    },
+    YouWin,
+    YouLose,
+    NotYourTurn,
+    Chat(ChatMessage),
+    Error(String),
+    /// The async client connected again after losing the connection and
+    /// resumed its player, with the user it got back. The game it played is
+    /// only kept across a server restart, it then comes in a Game event.
+    Reconnected(UserInfo),
+    /// The async client connected again but the server did not resume its
+    /// player, it registered as a new one under the same name.
+    Reregistered(UserInfo),
+    /// The async client gave up reconnecting, no more events follow.
+    Disconnected,
+    /// Frames this client has no type for, their payload is described in
+    /// the protocol.
+    Other(Frame),
+}
+
+impl TryFrom<Frame> for Event {
+    type Error = String;
+
+    fn try_from(frame: Frame) -> Result<Self, String> {
+        let event = match frame.method() {
+            GAME => Event::Game(frame.json_payload()?),
+            TURN => Event::Turn,
+            WRONG_ANSWER => Event::WrongAnswer {
+                hint: frame.text_payload()?,
+            },
+            CRACKED => Event::Cracked {
+                name: frame.text_payload()?,
+            },
+            YOU_WIN => Event::YouWin,
+            YOU_LOSE => Event::YouLose,
+            NOT_YOUR_TURN => Event::NotYourTurn,
+            CHAT_MESSAGE => Event::Chat(frame.json_payload()?),
+            ERROR => Event::Error(frame.text_payload()?),
+            _ => Event::Other(frame),
+        };
+        Ok(event)
+    }
+}
+
+/// Sends the protocol header and reads the registration constraints.
+pub(crate) fn handshake<T: Transport>(stream: &mut T) -> Result<ConnectionConstraints, String> {
+    stream
+        .write_all(PROTOCOL_HEADER.as_bytes())
+        .map_err(|error| format!("{}", error))?;
+
+    let frame = Frame::read_from(stream)?;
+    if frame.method() != REGISTRATION {
+        return Err(String::from("Registration expected"));
+    }
+    frame.json_payload()
+}
+
+/// Blocking client for bots and tools. Requests wait for their answer, an
+/// Error frame meanwhile fails them, and frames pushed by the server in the
+/// meantime are kept for `next_event`.
+pub struct GuessingClient<T: Transport> {
+    stream: T,
+    constraints: ConnectionConstraints,
+    user: Option<UserInfo>,
+    events: VecDeque<Frame>,
+}
+
+impl GuessingClient<TcpStream> {
+    pub fn connect(address: &str) -> Result<Self, String> {
+        let stream = TcpStream::connect(address).map_err(|error| format!("{}", error))?;
+        GuessingClient::new(stream)
+    }
+}
+
+impl GuessingClient<TlsStream> {
+    /// Servers started with `tls` usually run with a self signed certificate,
+    /// `ca_path` is the Pem file it is trusted from.
+    pub fn connect_tls(address: &str, server_name: &str, ca_path: &str) -> Result<Self, String> {
+        let config = tls::client_config(ca_path)?;
+        GuessingClient::new(tls::connect(address, server_name, config)?)
+    }
+}
+
+impl<T: Transport> GuessingClient<T> {
+    pub fn new(mut stream: T) -> Result<Self, String> {
+        let constraints = handshake(&mut stream)?;
+
+        Ok(GuessingClient {
+            stream,
+            constraints,
+            user: None,
+            events: VecDeque::new(),
+        })
+    }
+
+    pub fn constraints(&self) -> &ConnectionConstraints {
+        &self.constraints
+    }
+
+    pub fn user(&self) -> Option<&UserInfo> {
+        self.user.as_ref()
+    }
+
+    pub fn register(&mut self, name: &str) -> Result<UserInfo, String> {
+        self.send(Frame::text(REGISTRATION_OK, name))?;
+        let user: UserInfo = self.wait_for(&[USER])?.json_payload()?;
+
+        self.user = Some(user.clone());
+        Ok(user)
+    }
+
+    /// Comes back to the account of a User received before, its token included.
+    pub fn resume(&mut self, user: &UserInfo) -> Result<UserInfo, String> {
+        self.send(Frame::json(REGISTRATION_RESUME, user))?;
+        let user: UserInfo = self.wait_for(&[USER])?.json_payload()?;
+
+        self.user = Some(user.clone());
+        Ok(user)
+    }
+
+    /// Games waiting for a player.
+    pub fn list_games(&mut self) -> Result<Vec<GameInfo>, String> {
+        self.send(Frame::empty(GAME_LIST))?;
+        self.wait_for(&[GAMES])?.json_payload()
+    }
+
+    pub fn create_game(&mut self, rules: GameRules) -> Result<GameInfo, String> {
+        self.send(Frame::json(GAME_CREATE, &rules))?;
+        self.wait_for(&[GAME])?.json_payload()
+    }
+
+    pub fn join(&mut self, game_id: &str) -> Result<GameInfo, String> {
+        self.send(Frame::text(GAME_JOIN, game_id))?;
+        self.wait_for(&[GAME])?.json_payload()
+    }
+
+    /// The server only answers once every player set theirs, with the
+    /// started game and a turn for whoever plays first.
+    pub fn set_secret(&mut self, secret_number: u16) -> Result<(), String> {
+        self.send(Frame::number(SECRET_NUMBER, secret_number))
+    }
+
+    pub fn guess(&mut self, number: u16) -> Result<GuessResult, String> {
+        self.send(Frame::number(GUESS, number))?;
+        let frame = self.wait_for(&GUESS_ANSWERS)?;
+        GuessResult::try_from(Event::try_from(frame)?)
+    }
+
+    /// Blocks until the server sends something, frames kept while waiting
+    /// for answers come first.
+    pub fn next_event(&mut self) -> Result<Event, String> {
+        let frame = match self.events.pop_front() {
+            Some(frame) => frame,
+            None => Frame::read_from(&mut self.stream)?,
+        };
+        Event::try_from(frame)
+    }
+
+    pub fn close(&mut self) -> Result<(), String> {
+        self.stream.close().map_err(|error| format!("{}", error))
+    }
+
+    fn send(&mut self, frame: Frame) -> Result<(), String> {
+        self.stream
+            .write_all(&Vec::<u8>::from(frame))
+            .map_err(|error| format!("{}", error))
+    }
+
+    fn wait_for(&mut self, methods: &[Method]) -> Result<Frame, String> {
+        loop {
+            let frame = Frame::read_from(&mut self.stream)?;
+            if methods.contains(&frame.method()) {
+                return Ok(frame);
+            }
+            if frame.method() == ERROR {
+                return Err(frame.text_payload()?);
+            }
+            self.events.push_back(frame);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{Event, GuessResult, GuessingClient};
+    use crate::game::{Game, GameRules, Player};
+    use crate::protocol::{
+        ChatChannel, ChatMessage, ConnectionConstraints, Frame, GameInfo, UserInfo, CHAT_MESSAGE,
+        ERROR, GAME, GAMES, GAME_CREATE, GAME_LIST, GUESS, REGISTRATION, REGISTRATION_OK,
+        SECRET_NUMBER, TURN, USER, WRONG_ANSWER, YOU_WIN,
+    };
+    use crate::transport::{duplex, Duplex};
+    use std::io::{Read, Write};
+
+    /// Answers are written before the client asks, the duplex keeps them.
+    fn answer(server: &mut Duplex, frames: Vec<Frame>) {
+        for frame in frames {
+            server.write_all(&Vec::<u8>::from(frame)).unwrap();
+        }
+    }
+
+    fn start_client() -> (GuessingClient<Duplex>, Duplex) {
+        let (client, mut server) = duplex();
+        answer(
+            &mut server,
+            vec![Frame::json(REGISTRATION, &ConnectionConstraints::default())],
+        );
+        let client = GuessingClient::new(client).unwrap();
+
+        let mut header = [0; 5];
+        server.read_exact(&mut header).unwrap();
+        assert_eq!(b"GG010", &header);
+        (client, server)
+    }
+
+    #[test]
+    fn test_client_game() {
+        let (mut client, mut server) = start_client();
+        let user = UserInfo {
+            id: String::from("chico"),
+            name: String::from("Chico"),
+            stats: None,
+            token: None,
+        };
+        let game = Game::new(&Player::new(String::from("Chico")), GameRules::default()).unwrap();
+        let chat = ChatMessage {
+            channel: ChatChannel::Game,
+            from: user.clone(),
+            text: String::from("Good luck"),
+        };
+
+        answer(
+            &mut server,
+            vec![
+                Frame::json(USER, &user),
+                Frame::json(GAMES, &Vec::<GameInfo>::new()),
+                Frame::json(GAME, &GameInfo::from(&game)),
+                Frame::json(CHAT_MESSAGE, &chat),
+                Frame::empty(TURN),
+                Frame::text(WRONG_ANSWER, "Bigger"),
+                Frame::empty(YOU_WIN),
+            ],
+        );
+        assert_eq!(Ok(user.clone()), client.register("Chico"));
+        assert_eq!(Some(&user), client.user());
+        assert_eq!(Ok(Vec::new()), client.list_games());
+        assert_eq!(
+            Ok(GameInfo::from(&game)),
+            client.create_game(GameRules::default())
+        );
+        assert_eq!(Ok(()), client.set_secret(42));
+        assert_eq!(
+            Ok(GuessResult::WrongAnswer {
+                hint: String::from("Bigger")
+            }),
+            client.guess(10)
+        );
+
+        // Frames pushed while the guess waited come first
+        assert_eq!(Ok(Event::Chat(chat)), client.next_event());
+        assert_eq!(Ok(Event::Turn), client.next_event());
+        assert_eq!(Ok(Event::YouWin), client.next_event());
+
+        client.close().unwrap();
+        let sent: Vec<Frame> = (0..5)
+            .map(|_| Frame::read_from(&mut server).unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                Frame::text(REGISTRATION_OK, "Chico"),
+                Frame::empty(GAME_LIST),
+                Frame::json(GAME_CREATE, &GameRules::default()),
+                Frame::number(SECRET_NUMBER, 42),
+                Frame::number(GUESS, 10),
+            ],
+            sent
+        );
+    }
+
+    #[test]
+    fn test_client_error() {
+        let (mut client, mut server) = start_client();
+
+        answer(
+            &mut server,
+            vec![Frame::text(ERROR, "Name must have 1 to 10 bytes")],
+        );
+        assert_eq!(
+            Err(String::from("Name must have 1 to 10 bytes")),
+            client.register("Chico Buarque")
+        );
+
+        answer(&mut server, vec![Frame::json(GAME, &0)]);
+        assert!(client.next_event().is_err(), "Payload must be a game");
+    }
+}
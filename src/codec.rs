@@ -0,0 +1,129 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+/// How Json payloads are encoded on a connection, agreed on during the
+/// registration. Frames inside the server always carry Json.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Codec {
+    Json,
+    /// Structs as maps with their field names, like Json.
+    MessagePack,
+    Bincode,
+}
+
+pub const CODECS: [Codec; 3] = [Codec::Json, Codec::MessagePack, Codec::Bincode];
+
+impl Codec {
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Codec::Json => serde_json::to_vec(value).map_err(|error| format!("{}", error)),
+            Codec::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(|error| format!("{}", error))
+            }
+            Codec::Bincode => bincode::serialize(value).map_err(|error| format!("{}", error)),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Codec::Json => serde_json::from_slice(bytes).map_err(|error| format!("{}", error)),
+            Codec::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(|error| format!("{}", error))
+            }
+            Codec::Bincode => bincode::deserialize(bytes).map_err(|error| format!("{}", error)),
+        }
+    }
+}
+
+impl TryFrom<&str> for Codec {
+    type Error = String;
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        CODECS
+            .iter()
+            .find(|codec| format!("{:?}", codec) == name)
+            .copied()
+            .ok_or_else(|| format!("Unknown codec {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::{Codec, CODECS};
+    use crate::game::{Game, GameRules, Player};
+    use crate::protocol::{GameInfo, UserInfo};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_codec_round_trip() {
+        let user = UserInfo {
+            id: String::from("chico"),
+            name: String::from("Chico"),
+            stats: None,
+            token: None,
+        };
+        let player = Player::new(String::from("Chico"));
+        let game = Game::new(&player, GameRules::default()).unwrap();
+
+        for codec in CODECS.iter() {
+            let bytes = codec.encode(&user).unwrap();
+            assert_eq!(Ok(user.clone()), codec.decode::<UserInfo>(&bytes));
+
+            let bytes = codec.encode(&GameInfo::from(&game)).unwrap();
+            assert_eq!(Ok(GameInfo::from(&game)), codec.decode::<GameInfo>(&bytes));
+        }
+    }
+
+    #[test]
+    fn test_codec_try_from() {
+        assert_eq!(Ok(Codec::MessagePack), Codec::try_from("MessagePack"));
+        assert_eq!(
+            Err(String::from("Unknown codec Protobuf")),
+            Codec::try_from("Protobuf")
+        );
+    }
+}
+
+#[cfg(test)]
+mod benches {
+    extern crate test;
+
+    use crate::codec::Codec;
+    use crate::game::{Game, GameRules, Player};
+    use crate::protocol::GameInfo;
+    use test::Bencher;
+
+    fn game_info() -> GameInfo {
+        let chico = Player::new(String::from("Chico"));
+        let paloma = Player::new(String::from("Paloma"));
+        let mut game = Game::new(&chico, GameRules::default()).unwrap();
+        game.accept_challenge(&paloma).unwrap();
+        GameInfo::from(&game)
+    }
+
+    fn bench_codec(bencher: &mut Bencher, codec: Codec) {
+        let game_info = game_info();
+        bencher.bytes = codec.encode(&game_info).unwrap().len() as u64;
+
+        bencher.iter(|| {
+            let bytes = codec.encode(&game_info).unwrap();
+            codec.decode::<GameInfo>(&bytes).unwrap()
+        });
+    }
+
+    #[bench]
+    fn bench_json(bencher: &mut Bencher) {
+        bench_codec(bencher, Codec::Json);
+    }
+
+    #[bench]
+    fn bench_message_pack(bencher: &mut Bencher) {
+        bench_codec(bencher, Codec::MessagePack);
+    }
+
+    #[bench]
+    fn bench_bincode(bencher: &mut Bencher) {
+        bench_codec(bencher, Codec::Bincode);
+    }
+}
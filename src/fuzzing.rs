@@ -0,0 +1,138 @@
+use crate::codec::CODECS;
+use crate::protocol::{Connection, Frame, ProtocolHeader, WireFormat};
+use crate::server::Server;
+use crate::transport::Transport;
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Write};
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Plays recorded bytes to a connection, what it writes back is dropped.
+struct Replay {
+    bytes: Arc<Mutex<Cursor<Vec<u8>>>>,
+}
+
+impl Replay {
+    fn new(data: &[u8]) -> Replay {
+        Replay {
+            bytes: Arc::new(Mutex::new(Cursor::new(data.to_vec()))),
+        }
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.bytes
+            .lock()
+            .map_err(|_| io::Error::other("Replay poisoned"))?
+            .read(buf)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Replay {
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(Replay {
+            bytes: Arc::clone(&self.bytes),
+        })
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        let mut bytes = self
+            .bytes
+            .lock()
+            .map_err(|_| io::Error::other("Replay poisoned"))?;
+        let end = bytes.get_ref().len() as u64;
+        bytes.set_position(end);
+        Ok(())
+    }
+
+    fn set_read_timeout(&self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Fuzz target entry points, they get untrusted bytes and must never panic.
+pub fn header(data: &[u8]) {
+    if let Ok(header) = ProtocolHeader::try_from(data.to_vec()) {
+        header.wire_format();
+    }
+}
+
+/// Frames read by every wire format, the ones read must encode again.
+pub fn frame(data: &[u8]) {
+    if let Ok(frame) = Frame::try_from(data.to_vec()) {
+        assert_eq!(data, &Vec::<u8>::from(frame)[..]);
+    }
+
+    let wire_formats = CODECS
+        .iter()
+        .map(|codec| WireFormat::Binary(*codec))
+        .chain(Some(WireFormat::Text));
+    for wire_format in wire_formats {
+        if let Ok(frame) = wire_format.read_frame(&mut &data[..]) {
+            wire_format.encode(frame).ok();
+        }
+    }
+}
+
+/// A whole client session, from the protocol header to the last frame,
+/// played against a server.
+pub fn connection(data: &[u8]) {
+    let (main_tx, main_rx) = channel();
+    let stream = Replay::new(data);
+    let connection = thread::spawn(move || Connection::new(stream, main_tx).start());
+
+    let mut server = Server::new();
+    for server_command in main_rx {
+        server.handle_command(server_command);
+    }
+
+    if let Err(panic) = connection.join() {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fuzzing::{connection, frame, header};
+    use std::fs;
+    use std::panic;
+
+    /// Inputs the fuzzer once crashed on, in a directory per target.
+    const REGRESSIONS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/regressions");
+
+    #[test]
+    fn test_regressions() {
+        for target_dir in fs::read_dir(REGRESSIONS_PATH).unwrap() {
+            let target_dir = target_dir.unwrap().path();
+            let run: fn(&[u8]) = match target_dir.file_name().and_then(|name| name.to_str()) {
+                Some("header") => header,
+                Some("frame") => frame,
+                Some("connection") => connection,
+                name => panic!("Unknown fuzz target {:?}", name),
+            };
+
+            for input in fs::read_dir(&target_dir).unwrap() {
+                let path = input.unwrap().path();
+                let data = fs::read(&path).unwrap();
+                assert!(
+                    panic::catch_unwind(|| run(&data)).is_ok(),
+                    "Fuzz input {} crashed again",
+                    path.display()
+                );
+            }
+        }
+    }
+}
@@ -1,6 +1,115 @@
+use chrono::{DateTime, Duration, Local};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum HintStyle {
+    GreaterLess,
+    HotCold,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Hint {
+    Less(u16),
+    Greater(u16),
+    Hot(u16),
+    Warm(u16),
+    Cold(u16),
+    TimeIsOver,
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hint::Less(number) => write!(f, "Secret number is less than {}", number),
+            Hint::Greater(number) => write!(f, "Secret number is greater than {}", number),
+            Hint::Hot(number) => write!(f, "{} is hot", number),
+            Hint::Warm(number) => write!(f, "{} is warm", number),
+            Hint::Cold(number) => write!(f, "{} is cold", number),
+            Hint::TimeIsOver => write!(f, "Turn time is over"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameRules {
+    pub min: u16,
+    pub max: u16,
+    pub lives: u8,
+    pub hint_style: HintStyle,
+    pub turn_seconds: u32,
+    pub max_players: u8,
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules {
+            min: 1,
+            max: 100,
+            lives: 3,
+            hint_style: HintStyle::GreaterLess,
+            turn_seconds: 30,
+            max_players: 2,
+        }
+    }
+}
+
+impl GameRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min >= self.max {
+            Err(String::from("Invalid range, min must be less than max"))
+        } else if self.lives == 0 {
+            Err(String::from("Players must start with at least one life"))
+        } else if self.turn_seconds == 0 {
+            Err(String::from("Turn time must be at least one second"))
+        } else if self.max_players < 2 {
+            Err(String::from("A game needs at least two players"))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn validate_number(&self, number: u16) -> Result<(), String> {
+        if number < self.min || number > self.max {
+            Err(format!(
+                "Number must be between {} and {}",
+                self.min, self.max
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn hint(&self, number: u16, secret_number: u16) -> Hint {
+        match self.hint_style {
+            HintStyle::GreaterLess => {
+                if number > secret_number {
+                    Hint::Less(number)
+                } else {
+                    Hint::Greater(number)
+                }
+            }
+            HintStyle::HotCold => {
+                let distance = (number as i32 - secret_number as i32).unsigned_abs();
+                let range = (self.max - self.min) as u32;
+
+                if distance * 10 <= range {
+                    Hint::Hot(number)
+                } else if distance * 4 <= range {
+                    Hint::Warm(number)
+                } else {
+                    Hint::Cold(number)
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub struct PlayerData {
     pub name: String,
@@ -8,12 +117,21 @@ pub struct PlayerData {
     life: u8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Player {
     pub id: String,
     player_data: Arc<RwLock<PlayerData>>,
 }
 
+impl PartialEq for Player {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+/// Bot ids start with it, registered players never get one.
+pub const BOT_ID_PREFIX: &str = "bot:";
+
 impl Player {
     pub fn new(name: String) -> Player {
         let player_data = PlayerData {
@@ -28,16 +146,77 @@ impl Player {
         }
     }
 
-    pub fn set_secret_number(&self, secret_number: u16) {
+    pub fn with_id(id: String, name: String) -> Player {
+        let player = Player::new(name);
+        Player { id, ..player }
+    }
+
+    pub fn house() -> Player {
+        Player::new(String::from("House"))
+    }
+
+    pub fn is_bot(&self) -> bool {
+        self.id.starts_with(BOT_ID_PREFIX)
+    }
+
+    pub fn pick_secret_number<R: Rng>(&self, rules: &GameRules, rng: &mut R) -> Result<(), String> {
+        rules.validate()?;
+        let secret_number = rng.gen_range(rules.min..=rules.max);
+        self.set_secret_number(secret_number, rules)
+    }
+
+    pub fn set_secret_number(&self, secret_number: u16, rules: &GameRules) -> Result<(), String> {
+        rules.validate_number(secret_number)?;
+
         match self.player_data.write() {
-            Ok(mut player_data) => player_data.secret_number = Some(secret_number),
-            Err(_) => println!("Impossible to set secret number"),
+            Ok(mut player_data) => {
+                player_data.secret_number = Some(secret_number);
+                Ok(())
+            }
+            Err(_) => Err(String::from("Impossible to set secret number")),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self.player_data.read() {
+            Ok(player_data) => player_data.name.clone(),
+            Err(_) => String::new(),
+        }
+    }
+
+    fn secret_number(&self) -> Option<u16> {
+        match self.player_data.read() {
+            Ok(player_data) => player_data.secret_number,
+            Err(_) => None,
+        }
+    }
+
+    pub fn life(&self) -> u8 {
+        match self.player_data.read() {
+            Ok(player_data) => player_data.life,
+            Err(_) => 0,
+        }
+    }
+
+    fn set_life(&self, life: u8) {
+        match self.player_data.write() {
+            Ok(mut player_data) => player_data.life = life,
+            Err(_) => println!("Impossible to set life"),
         };
     }
-}
 
+    fn lose_life(&self) -> u8 {
+        match self.player_data.write() {
+            Ok(mut player_data) => {
+                player_data.life = player_data.life.saturating_sub(1);
+                player_data.life
+            }
+            Err(_) => 0,
+        }
+    }
+}
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum GameMode {
     WaitingForPlayer,
     Ready,
@@ -45,333 +224,1237 @@ pub enum GameMode {
     Finished,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GuessRecord {
+    pub player_id: String,
+    pub target_id: String,
+    pub number: u16,
+    pub hint: Option<Hint>,
+    pub guessed_at: DateTime<Local>,
+}
+
+/// Everything that changed the game once it was in progress, in order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Move {
+    Guess(GuessRecord),
+    TurnExpired {
+        player_id: String,
+        expired_at: DateTime<Local>,
+    },
+    Left {
+        player_id: String,
+        left_at: DateTime<Local>,
+    },
+}
+
 #[derive(Debug)]
-pub struct Game<'a> {
+pub struct Game {
     id: String,
-    player_one: Option<&'a Player>,
-    player_two: Option<&'a Player>,
-    turn_player: Option<&'a Player>,
+    rules: GameRules,
+    players: Vec<Player>,
+    turn_player: Option<Player>,
+    first_player: Option<Player>,
+    turn_started_at: Option<DateTime<Local>>,
+    cracked: HashMap<String, HashSet<String>>, // Opponents each player already found the secret
+    last_targets: HashMap<String, String>,
+    moves: Vec<Move>,
+    winner: Option<Player>,
+    against_house: bool,
     mode: GameMode,
 }
 
-impl<'a> Game<'a> {
-    pub fn new(player: &Player) -> Game {
-        Game {
+impl Game {
+    pub fn new(player: &Player, rules: GameRules) -> Result<Game, String> {
+        rules.validate()?;
+        player.set_life(rules.lives);
+
+        Ok(Game {
             id: Uuid::new_v4().to_string(),
-            player_one: Some(player),
-            player_two: None,
+            rules,
+            players: vec![player.clone()],
             turn_player: None,
+            first_player: None,
+            turn_started_at: None,
+            cracked: HashMap::new(),
+            last_targets: HashMap::new(),
+            moves: Vec::new(),
+            winner: None,
+            against_house: false,
             mode: GameMode::WaitingForPlayer,
+        })
+    }
+
+    /// Single player game, the house picks a secret number and the player
+    /// keeps guessing until finding it or running out of lives.
+    pub fn against_house<R: Rng>(
+        player: &Player,
+        rules: GameRules,
+        rng: &mut R,
+    ) -> Result<Game, String> {
+        let house = Player::house();
+        house.pick_secret_number(&rules, rng)?;
+        Game::against(player, &house, rules)
+    }
+
+    /// House game where the house already picked its secret number.
+    pub fn against(player: &Player, house: &Player, rules: GameRules) -> Result<Game, String> {
+        let rules = GameRules {
+            max_players: 2,
+            ..rules
+        };
+        let mut game = Game::new(player, rules)?;
+
+        game.against_house = true;
+        game.accept_challenge(house)?;
+        game.set_ready()?;
+        Ok(game)
+    }
+
+    pub fn is_against_house(&self) -> bool {
+        self.against_house
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn rules(&self) -> &GameRules {
+        &self.rules
+    }
+
+    pub fn mode(&self) -> &GameMode {
+        &self.mode
+    }
+
+    pub fn players(&self) -> Vec<&Player> {
+        self.players.iter().collect()
+    }
+
+    pub fn player(&self, id: &str) -> Option<&Player> {
+        self.players.iter().find(|player| player.id == id)
+    }
+
+    pub fn turn_player(&self) -> Option<&Player> {
+        self.turn_player.as_ref()
+    }
+
+    pub fn first_player(&self) -> Option<&Player> {
+        self.first_player.as_ref()
+    }
+
+    pub fn winner(&self) -> Option<&Player> {
+        self.winner.as_ref()
+    }
+
+    pub fn moves(&self) -> &[Move] {
+        &self.moves
+    }
+
+    pub fn guesses(&self) -> Vec<&GuessRecord> {
+        self.moves
+            .iter()
+            .filter_map(|game_move| match game_move {
+                Move::Guess(guess) => Some(guess),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Secret numbers stay hidden from everyone until the game is over.
+    pub fn revealed_secret_number(&self, player: &Player) -> Option<u16> {
+        match self.mode {
+            GameMode::Finished => player.secret_number(),
+            _ => None,
+        }
+    }
+
+    pub fn last_target(&self, player: &Player) -> Option<&Player> {
+        self.last_targets
+            .get(&player.id)
+            .and_then(|target_id| self.player(target_id))
+    }
+
+    /// Players still in the game, the house never takes turns.
+    fn alive_players(&self) -> Vec<&Player> {
+        self.players
+            .iter()
+            .enumerate()
+            .filter(|(seat, player)| player.life() > 0 && !(self.against_house && *seat == 1))
+            .map(|(_, player)| player)
+            .collect()
+    }
+
+    /// Opponents still in the game whose secret number the player did not find yet.
+    fn targets(&self, player: &Player) -> Vec<&Player> {
+        let cracked = self.cracked.get(&player.id);
+
+        self.players
+            .iter()
+            .filter(|opponent| *opponent != player && opponent.life() > 0)
+            .filter(|opponent| cracked.is_none_or(|cracked| !cracked.contains(&opponent.id)))
+            .collect()
+    }
+
+    pub fn start(&mut self) -> Result<(), String> {
+        match self.mode {
+            GameMode::Ready => {
+                self.mode = GameMode::InProgress;
+                self.turn_player = self
+                    .first_player
+                    .clone()
+                    .or_else(|| self.players.first().cloned());
+                self.turn_started_at = Some(Local::now());
+                Ok(())
+            }
+            _ => Err(String::from("Game is not ready to start")),
+        }
+    }
+
+    /// Who takes the first turn, the player that created the game by default.
+    pub fn set_first_player(&mut self, player: &Player) -> Result<(), String> {
+        self.validate_player(player)?;
+
+        match self.mode {
+            GameMode::WaitingForPlayer | GameMode::Ready => {
+                self.first_player = Some(player.clone());
+                Ok(())
+            }
+            _ => Err(String::from("Game already started")),
+        }
+    }
+
+    fn validate_players(&self) -> Result<(), String> {
+        if self.players.len() < self.rules.max_players as usize {
+            Err(format!("No player {} present", self.players.len() + 1))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn validate_secret_numbers(&self) -> Result<(), String> {
+        for (seat, player) in self.players.iter().enumerate() {
+            if self.against_house && seat == 0 {
+                continue;
+            }
+
+            if player.secret_number().is_none() {
+                return Err(format!("Player {} has no secret number", seat + 1));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn set_ready(&mut self) -> Result<(), String> {
+        self.validate_players()?;
+        self.validate_secret_numbers()?;
+        self.mode = GameMode::Ready;
+        Ok(())
+    }
+
+    pub fn accept_challenge(&mut self, joining_player: &Player) -> Result<(), String> {
+        if self.players.len() >= self.rules.max_players as usize {
+            return Err(String::from("This game is full"));
+        }
+
+        if self.players.contains(joining_player) {
+            return Err(String::from("Player already joined this game"));
+        }
+
+        joining_player.set_life(self.rules.lives);
+        self.players.push(joining_player.clone());
+        Ok(())
+    }
+
+    /// A player leaving before the game starts frees the seat, after that
+    /// the player is eliminated.
+    pub fn leave(&mut self, player: &Player) -> Result<(), String> {
+        self.validate_player(player)?;
+
+        match self.mode {
+            GameMode::WaitingForPlayer | GameMode::Ready => {
+                self.players.retain(|seated| seated != player);
+                self.mode = GameMode::WaitingForPlayer;
+            }
+            GameMode::InProgress => {
+                self.moves.push(Move::Left {
+                    player_id: player.id.clone(),
+                    left_at: Local::now(),
+                });
+                player.set_life(0);
+                if self.turn_player.as_ref() == Some(player) {
+                    self.next_turn();
+                }
+                self.check_winner();
+            }
+            GameMode::Finished => {}
+        }
+        Ok(())
+    }
+
+    fn validate_player(&self, player: &Player) -> Result<(), String> {
+        if self.players.contains(player) {
+            Ok(())
+        } else {
+            Err(String::from("Player is not part of this game"))
+        }
+    }
+
+    pub fn set_secret_number(&self, player: &Player, secret_number: u16) -> Result<(), String> {
+        self.validate_player(player)?;
+
+        match self.mode {
+            GameMode::WaitingForPlayer | GameMode::Ready => {
+                player.set_secret_number(secret_number, &self.rules)
+            }
+            _ => Err(String::from("Secret number can not be changed anymore")),
+        }
+    }
+
+    fn validate_wip_mode(&self) -> Result<(), String> {
+        match self.mode {
+            GameMode::InProgress => Ok(()),
+            _ => Err(String::from("This game is nor in progress")),
+        }
+    }
+
+    /// Next opponent to guess at, rotating through everyone still standing.
+    pub fn next_target(&self, player: &Player) -> Option<&Player> {
+        let targets = self.targets(player);
+        let last_seat = self
+            .last_targets
+            .get(&player.id)
+            .or(Some(&player.id))
+            .and_then(|id| self.players.iter().position(|seated| &seated.id == id))
+            .unwrap_or(0);
+
+        self.players
+            .iter()
+            .cycle()
+            .skip(last_seat + 1)
+            .take(self.players.len())
+            .find(|seated| targets.contains(seated))
+    }
+
+    pub fn guess_number(&mut self, player: &Player, number: u16) -> Result<GameMessages, String> {
+        self.validate_wip_mode()?;
+
+        match self.next_target(player).cloned() {
+            Some(target) => self.guess_number_at(player, &target.id, number),
+            None => Ok(GameMessages::UnexpectedError(String::from(
+                "No opponent left to guess",
+            ))),
+        }
+    }
+
+    pub fn guess_number_at(
+        &mut self,
+        player: &Player,
+        target_id: &str,
+        number: u16,
+    ) -> Result<GameMessages, String> {
+        self.validate_wip_mode()?;
+
+        if self.turn_player.as_ref() != Some(player) {
+            return Ok(GameMessages::NotYourTurn);
+        }
+
+        self.rules.validate_number(number)?;
+
+        let target = match self
+            .targets(player)
+            .into_iter()
+            .find(|target| target.id == target_id)
+        {
+            Some(target) => target.clone(),
+            None => return Err(String::from("Opponent is not a valid target")),
+        };
+        let secret_number = match target.secret_number() {
+            Some(secret_number) => secret_number,
+            None => {
+                return Ok(GameMessages::UnexpectedError(String::from(
+                    "Opponent has no secret number",
+                )))
+            }
+        };
+
+        self.last_targets
+            .insert(player.id.clone(), target.id.clone());
+
+        let hint = if number == secret_number {
+            None
+        } else {
+            Some(self.rules.hint(number, secret_number))
+        };
+        self.moves.push(Move::Guess(GuessRecord {
+            player_id: player.id.clone(),
+            target_id: target.id.clone(),
+            number,
+            hint,
+            guessed_at: Local::now(),
+        }));
+
+        if number == secret_number {
+            self.cracked
+                .entry(player.id.clone())
+                .or_default()
+                .insert(target.id.clone());
+            self.check_winner();
+
+            if self.winner.as_ref() == Some(player) {
+                return Ok(GameMessages::YouWin);
+            }
+
+            self.next_turn();
+            return Ok(GameMessages::Cracked(target.id));
+        }
+
+        let eliminated = player.lose_life() == 0;
+        self.next_turn();
+        self.check_winner();
+
+        match hint {
+            Some(hint) if !eliminated => Ok(GameMessages::WrongAnswer(hint)),
+            _ => Ok(GameMessages::YouLose),
+        }
+    }
+
+    /// Last one standing, or the first to find every other secret, wins.
+    fn check_winner(&mut self) {
+        if self.mode != GameMode::InProgress {
+            return;
+        }
+
+        let alive = self.alive_players();
+        let winner = if self.against_house {
+            self.players
+                .first()
+                .filter(|player| self.targets(player).is_empty())
+        } else if alive.len() == 1 {
+            alive.first().copied()
+        } else {
+            alive
+                .into_iter()
+                .find(|player| self.targets(player).is_empty())
+        };
+
+        if let Some(winner) = winner.cloned() {
+            self.winner = Some(winner);
+            self.mode = GameMode::Finished;
+        } else if self.alive_players().is_empty() {
+            self.mode = GameMode::Finished;
+        }
+    }
+
+    fn next_turn(&mut self) {
+        let alive = self.alive_players();
+        let current_seat = self
+            .turn_player
+            .as_ref()
+            .and_then(|turn_player| self.players.iter().position(|seated| seated == turn_player))
+            .unwrap_or(0);
+
+        let next_player = self
+            .players
+            .iter()
+            .cycle()
+            .skip(current_seat + 1)
+            .take(self.players.len())
+            .find(|seated| alive.contains(seated))
+            .cloned();
+
+        self.turn_player = next_player;
+        self.turn_started_at = Some(Local::now());
+    }
+
+    pub fn turn_expired(&self, now: DateTime<Local>) -> bool {
+        match (&self.mode, self.turn_started_at) {
+            (GameMode::InProgress, Some(turn_started_at)) => {
+                now - turn_started_at >= Duration::seconds(self.rules.turn_seconds as i64)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn restart_turn_clock(&mut self, now: DateTime<Local>) {
+        if self.mode == GameMode::InProgress {
+            self.turn_started_at = Some(now);
+        }
+    }
+
+    /// A player that lets the turn time run out loses a life and the turn.
+    pub fn expire_turn(&mut self, now: DateTime<Local>) -> Option<GameMessages> {
+        if !self.turn_expired(now) {
+            return None;
+        }
+
+        let player = self.turn_player.clone().unwrap();
+        self.moves.push(Move::TurnExpired {
+            player_id: player.id.clone(),
+            expired_at: now,
+        });
+        let eliminated = player.lose_life() == 0;
+        self.next_turn();
+        self.check_winner();
+
+        if eliminated {
+            Some(GameMessages::YouLose)
+        } else {
+            Some(GameMessages::WrongAnswer(Hint::TimeIsOver))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    pub id: String,
+    pub name: String,
+    pub secret_number: Option<u16>,
+    pub life: u8,
+}
+
+impl From<&Player> for PlayerSnapshot {
+    fn from(player: &Player) -> Self {
+        PlayerSnapshot {
+            id: player.id.clone(),
+            name: player.name(),
+            secret_number: player.secret_number(),
+            life: player.life(),
+        }
+    }
+}
+
+impl From<PlayerSnapshot> for Player {
+    fn from(snapshot: PlayerSnapshot) -> Self {
+        let player_data = PlayerData {
+            name: snapshot.name,
+            secret_number: snapshot.secret_number,
+            life: snapshot.life,
+        };
+
+        Player {
+            id: snapshot.id,
+            player_data: Arc::new(RwLock::new(player_data)),
+        }
+    }
+}
+
+/// Everything a game needs to carry on after a server restart, secret
+/// numbers included, so it must never reach the clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub id: String,
+    pub rules: GameRules,
+    pub players: Vec<PlayerSnapshot>,
+    pub turn_player: Option<String>,
+    pub first_player: Option<String>,
+    pub cracked: HashMap<String, HashSet<String>>,
+    pub last_targets: HashMap<String, String>,
+    pub moves: Vec<Move>,
+    pub winner: Option<String>,
+    pub against_house: bool,
+    pub mode: GameMode,
+}
+
+impl From<&Game> for GameSnapshot {
+    fn from(game: &Game) -> Self {
+        let id = |player: &Option<Player>| player.as_ref().map(|player| player.id.clone());
+
+        GameSnapshot {
+            id: game.id.clone(),
+            rules: game.rules.clone(),
+            players: game.players.iter().map(PlayerSnapshot::from).collect(),
+            turn_player: id(&game.turn_player),
+            first_player: id(&game.first_player),
+            cracked: game.cracked.clone(),
+            last_targets: game.last_targets.clone(),
+            moves: game.moves.clone(),
+            winner: id(&game.winner),
+            against_house: game.against_house,
+            mode: game.mode.clone(),
+        }
+    }
+}
+
+/// The turn clock starts over, players were not able to play while the
+/// server was down.
+impl From<GameSnapshot> for Game {
+    fn from(snapshot: GameSnapshot) -> Self {
+        let players: Vec<Player> = snapshot.players.into_iter().map(Player::from).collect();
+        let player = |id: Option<String>| {
+            id.and_then(|id| players.iter().find(|player| player.id == id).cloned())
+        };
+        let turn_started_at = match snapshot.mode {
+            GameMode::InProgress => Some(Local::now()),
+            _ => None,
+        };
+
+        Game {
+            id: snapshot.id,
+            rules: snapshot.rules,
+            turn_player: player(snapshot.turn_player),
+            first_player: player(snapshot.first_player),
+            winner: player(snapshot.winner),
+            players: players.clone(),
+            turn_started_at,
+            cracked: snapshot.cracked,
+            last_targets: snapshot.last_targets,
+            moves: snapshot.moves,
+            against_house: snapshot.against_house,
+            mode: snapshot.mode,
         }
     }
 }
 
-//     fn start(&mut self) -> Result<(), String> {
-//         match self.mode {
-//             GameMode::Ready => {
-//                 self.mode = GameMode::InProgress;
-//                 self.turn_player = self.player_one;
-//                 Ok(())
-//             }
-//             _ => Err(String::from("Game is not ready to start")),
-//         }
-//     }
-
-//     fn validate_players(&self) -> Result<(), String> {
-//         if self.player_one.is_none() {
-//             Err(String::from("No player 1 present"))
-//         } else if self.player_two.is_none() {
-//             Err(String::from("No player 2 present"))
-//         } else {
-//             Ok(())
-//         }
-//     }
-
-//     fn set_ready(&mut self) -> Result<(), String> {
-//         self.validate_players()?;
-//         self.mode = GameMode::Ready;
-//         Ok(())
-//     }
-
-//     fn get_free_position(&mut self) -> Result<&mut Option<&'a Box<Player>>, String> {
-//         if self.player_one.is_none() {
-//             Ok(&mut self.player_one)
-//         } else if self.player_two.is_none() {
-//             Ok(&mut self.player_two)
-//         } else {
-//             Err(String::from("This game is full"))
-//         }
-//     }
-
-//     fn accept_challenge(&mut self, joining_player: &'a Box<Player>) -> Result<(), String> {
-//         let free_position = self.get_free_position()?;
-//         *free_position = Some(joining_player);
-//         Ok(())
-//     }
-
-//     fn validate_wip_mode(&self) -> Result<(), String> {
-//         match self.mode {
-//             GameMode::InProgress => Ok(()),
-//             _ => Err(String::from("This game is nor in progress")),
-//         }
-//     }
-
-//     fn get_opponent(&self, player: &Box<Player>) -> &'a Box<Player> {
-//         if player == self.player_one.unwrap() {
-//             self.player_two.unwrap()
-//         } else {
-//             self.player_one.unwrap()
-//         }
-//     }
-
-//     fn guess_number(
-//         &mut self,
-//         player: &'a Box<Player>,
-//         number: u16,
-//     ) -> Result<GameMessages, String> {
-//         self.validate_wip_mode()?;
-
-//         if player != self.turn_player.unwrap() {
-//             return Ok(GameMessages::NotYourTurn);
-//         }
-
-//         let opponent = self.get_opponent(player);
-//         let secret_number = opponent.secret_number.unwrap();
-
-//         if number > secret_number {
-//             self.turn_player = Some(opponent);
-//             Ok(GameMessages::WrongAnswer(format!(
-//                 "Secret number is less than {}",
-//                 number
-//             )))
-//         } else if number < secret_number {
-//             self.turn_player = Some(opponent);
-//             Ok(GameMessages::WrongAnswer(format!(
-//                 "Secret number is greater than {}",
-//                 number
-//             )))
-//         } else {
-//             self.mode = GameMode::Finished;
-//             Ok(GameMessages::YouWin)
-//         }
-//     }
-// }
-
-// #[derive(Debug, PartialEq)]
-// pub enum GameMessages {
-//     YouWin,
-//     NotYourTurn,
-//     WrongAnswer(String),
-//     UnexpectedError(String),
-// }
-
-// #[cfg(test)]
-// mod tests {
-//     use crate::game::{Game, GameMessages, GameMode, Player};
-
-//     #[test]
-//     fn test_game_creation() {
-//         let player_one = box Player::new(String::from("Chico"));
-
-//         let game = Game::new(&player_one);
-
-//         assert_eq!(player_one.id, game.player_one.unwrap().id);
-//         assert_eq!(GameMode::WaitingForPlayer, game.mode);
-//     }
-
-//     #[test]
-//     fn test_set_ready_error() {
-//         let player_one = box Player::new(String::from("Chico"));
-
-//         let mut game = Game::new(&player_one);
-
-//         assert_eq!(Err(String::from("No player 2 present")), game.set_ready());
-//     }
-
-//     #[test]
-//     fn test_set_ready_error_player_one() {
-//         let player_one = box Player::new(String::from("Chico"));
-//         let player_two = box Player::new(String::from("Paloma"));
-
-//         let mut game = Game::new(&player_one);
-//         assert_eq!(Ok(()), game.accept_challenge(&player_two));
-//         game.player_one = None;
-
-//         assert_eq!(Err(String::from("No player 1 present")), game.set_ready());
-//     }
-
-//     #[test]
-//     fn test_set_ready() {
-//         let player_one = box Player::new(String::from("Chico"));
-//         let player_two = box Player::new(String::from("Paloma"));
-
-//         let mut game = Game::new(&player_one);
-//         assert_eq!(Ok(()), game.accept_challenge(&player_two));
-
-//         assert_eq!(Ok(()), game.set_ready());
-//     }
-
-//     #[test]
-//     fn test_accept_challenge_player_two() {
-//         let player_one = box Player::new(String::from("Chico"));
-//         let player_two = box Player::new(String::from("Paloma"));
-
-//         let mut game = Game::new(&player_one);
-//         assert_eq!(Ok(()), game.accept_challenge(&player_two));
-//         assert_eq!(player_one.id, game.player_one.unwrap().id);
-//         assert_eq!(player_two.id, game.player_two.unwrap().id);
-//     }
-
-//     #[test]
-//     fn test_accept_challenge_player_one() {
-//         let player_one = box Player::new(String::from("Chico"));
-//         let player_two = box Player::new(String::from("Paloma"));
-//         let player_three = box Player::new(String::from("Allan"));
-
-//         let mut game = Game::new(&player_one);
-
-//         assert_eq!(Ok(()), game.accept_challenge(&player_two));
-//         assert_eq!(player_two.id, game.player_two.unwrap().id);
-
-//         game.player_one = None;
-
-//         assert_eq!(Ok(()), game.accept_challenge(&player_three));
-//         assert_eq!(player_three.id, game.player_one.unwrap().id);
-//     }
-
-//     #[test]
-//     fn test_accept_challenge_error() {
-//         let player_one = box Player::new(String::from("Chico"));
-//         let player_two = box Player::new(String::from("Paloma"));
-//         let player_three = box Player::new(String::from("Allan"));
-
-//         let mut game = Game::new(&player_one);
-//         game.accept_challenge(&player_two).unwrap();
-//         assert_eq!(
-//             Err(String::from("This game is full")),
-//             game.accept_challenge(&player_three)
-//         );
-//     }
-
-//     #[test]
-//     fn test_start_error() {
-//         let player_one = box Player::new(String::from("Chico"));
-
-//         let mut game = Game::new(&player_one);
-
-//         assert_eq!(
-//             Err(String::from("Game is not ready to start")),
-//             game.start()
-//         );
-//     }
-
-//     #[test]
-//     fn test_start() {
-//         let player_one = box Player::new(String::from("Chico"));
-//         let player_two = box Player::new(String::from("Paloma"));
-
-//         let mut game = Game::new(&player_one);
-//         game.accept_challenge(&player_two);
-//         game.set_ready();
-
-//         assert_eq!(Ok(()), game.start());
-//     }
-
-//     #[test]
-//     fn test_guess_unexpected_error() {}
-
-//     #[test]
-//     fn test_guess_not_your_turn() {
-//         let player_one = box Player::new(String::from("Chico"));
-//         let player_two = box Player::new(String::from("Paloma"));
-
-//         let mut game = Game::new(&player_one);
-//         game.accept_challenge(&player_two);
-//         game.set_ready();
-//         game.start();
-
-//         assert_eq!(
-//             Ok(GameMessages::NotYourTurn),
-//             game.guess_number(&player_two, 42)
-//         );
-//     }
-
-//     #[test]
-//     fn test_guess_wrong_answer_bigger() {
-//         let mut player_one = box Player::new(String::from("Chico"));
-//         player_one.secret_number = Some(43);
-
-//         let mut player_two = box Player::new(String::from("Paloma"));
-//         player_two.secret_number = Some(42);
-
-//         let mut game = Game::new(&player_one);
-//         game.accept_challenge(&player_two);
-//         game.set_ready();
-//         game.start();
-
-//         assert_eq!(
-//             Ok(GameMessages::WrongAnswer(String::from(
-//                 "Secret number is less than 43"
-//             ))),
-//             game.guess_number(&player_one, 43)
-//         );
-//     }
-
-//     #[test]
-//     fn test_guess_wrong_answer_smaller() {
-//         let mut player_one = box Player::new(String::from("Chico"));
-//         player_one.secret_number = Some(42);
-
-//         let mut player_two = box Player::new(String::from("Paloma"));
-//         player_two.secret_number = Some(43);
-
-//         let mut game = Game::new(&player_one);
-//         game.accept_challenge(&player_two);
-//         game.set_ready();
-//         game.start();
-
-//         assert_eq!(
-//             Ok(GameMessages::WrongAnswer(String::from(
-//                 "Secret number is greater than 42"
-//             ))),
-//             game.guess_number(&player_one, 42)
-//         );
-//     }
-
-//     #[test]
-//     fn test_guess_you_win() {
-//         let mut player_one = box Player::new(String::from("Chico"));
-//         player_one.secret_number = Some(43);
-
-//         let mut player_two = box Player::new(String::from("Paloma"));
-//         player_two.secret_number = Some(42);
-
-//         let mut game = Game::new(&player_one);
-//         game.accept_challenge(&player_two);
-//         game.set_ready();
-//         game.start();
-
-//         assert_eq!(Ok(GameMessages::YouWin), game.guess_number(&player_one, 42));
-//         assert_eq!(GameMode::Finished, game.mode);
-//     }
-
-//     #[test]
-//     fn test_player_switch() {
-//         let mut player_one = box Player::new(String::from("Chico"));
-//         player_one.secret_number = Some(42024);
-
-//         let mut player_two = box Player::new(String::from("Paloma"));
-//         player_two.secret_number = Some(42024);
-
-//         let mut game = Game::new(&player_one);
-//         game.accept_challenge(&player_two);
-//         game.set_ready();
-//         game.start();
-
-//         game.guess_number(&player_one, 42);
-//         assert_eq!(**game.turn_player.unwrap(), *player_two);
-
-//         game.guess_number(&player_two, 42);
-//         assert_eq!(**game.turn_player.unwrap(), *player_one);
-//     }
-// }
+#[derive(Debug, PartialEq)]
+pub enum GameMessages {
+    YouWin,
+    YouLose,
+    NotYourTurn,
+    WrongAnswer(Hint),
+    Cracked(String),
+    UnexpectedError(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{
+        Game, GameMessages, GameMode, GameRules, GameSnapshot, Hint, HintStyle, Player,
+    };
+    use chrono::{Duration, Local};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn ready_game(player_one: &Player, player_two: &Player) -> Game {
+        let mut game = Game::new(player_one, GameRules::default()).unwrap();
+        game.accept_challenge(player_two).unwrap();
+        game.set_secret_number(player_one, 43).unwrap();
+        game.set_secret_number(player_two, 42).unwrap();
+        game.set_ready().unwrap();
+        game
+    }
+
+    #[test]
+    fn test_game_creation() {
+        let player_one = Player::new(String::from("Chico"));
+
+        let game = Game::new(&player_one, GameRules::default()).unwrap();
+
+        assert_eq!(player_one.id, game.players[0].id);
+        assert_eq!(GameMode::WaitingForPlayer, game.mode);
+    }
+
+    #[test]
+    fn test_game_creation_invalid_rules() {
+        let player_one = Player::new(String::from("Chico"));
+        let rules = GameRules {
+            min: 10,
+            max: 10,
+            ..GameRules::default()
+        };
+
+        assert_eq!(
+            Err(String::from("Invalid range, min must be less than max")),
+            Game::new(&player_one, rules).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_set_ready_error() {
+        let player_one = Player::new(String::from("Chico"));
+
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+
+        assert_eq!(Err(String::from("No player 2 present")), game.set_ready());
+    }
+
+    #[test]
+    fn test_set_ready_error_player_one() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+        assert_eq!(Ok(()), game.accept_challenge(&player_two));
+        assert_eq!(Ok(()), game.leave(&player_one));
+
+        assert_eq!(Err(String::from("No player 2 present")), game.set_ready());
+    }
+
+    #[test]
+    fn test_set_ready_error_secret_number() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+        game.accept_challenge(&player_two).unwrap();
+        game.set_secret_number(&player_one, 42).unwrap();
+
+        assert_eq!(
+            Err(String::from("Player 2 has no secret number")),
+            game.set_ready()
+        );
+    }
+
+    #[test]
+    fn test_set_ready() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+        assert_eq!(Ok(()), game.accept_challenge(&player_two));
+        game.set_secret_number(&player_one, 42).unwrap();
+        game.set_secret_number(&player_two, 42).unwrap();
+
+        assert_eq!(Ok(()), game.set_ready());
+    }
+
+    #[test]
+    fn test_set_secret_number_out_of_range() {
+        let player_one = Player::new(String::from("Chico"));
+
+        let game = Game::new(&player_one, GameRules::default()).unwrap();
+
+        assert_eq!(
+            Err(String::from("Number must be between 1 and 100")),
+            game.set_secret_number(&player_one, 101)
+        );
+        assert_eq!(
+            Err(String::from("Number must be between 1 and 100")),
+            game.set_secret_number(&player_one, 0)
+        );
+    }
+
+    #[test]
+    fn test_set_secret_number_not_in_game() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let game = Game::new(&player_one, GameRules::default()).unwrap();
+
+        assert_eq!(
+            Err(String::from("Player is not part of this game")),
+            game.set_secret_number(&player_two, 42)
+        );
+    }
+
+    #[test]
+    fn test_accept_challenge_player_two() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+        assert_eq!(Ok(()), game.accept_challenge(&player_two));
+        assert_eq!(player_one.id, game.players[0].id);
+        assert_eq!(player_two.id, game.players[1].id);
+    }
+
+    #[test]
+    fn test_accept_challenge_player_one() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+        let player_three = Player::new(String::from("Allan"));
+
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+
+        assert_eq!(Ok(()), game.accept_challenge(&player_two));
+        assert_eq!(player_two.id, game.players[1].id);
+
+        game.leave(&player_one).unwrap();
+
+        assert_eq!(Ok(()), game.accept_challenge(&player_three));
+        assert_eq!(player_two.id, game.players[0].id);
+        assert_eq!(player_three.id, game.players[1].id);
+    }
+
+    #[test]
+    fn test_accept_challenge_error() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+        let player_three = Player::new(String::from("Allan"));
+
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+        game.accept_challenge(&player_two).unwrap();
+        assert_eq!(
+            Err(String::from("This game is full")),
+            game.accept_challenge(&player_three)
+        );
+    }
+
+    #[test]
+    fn test_accept_challenge_sets_lives() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+        let rules = GameRules {
+            lives: 7,
+            ..GameRules::default()
+        };
+
+        let mut game = Game::new(&player_one, rules).unwrap();
+        game.accept_challenge(&player_two).unwrap();
+
+        assert_eq!(7, player_one.life());
+        assert_eq!(7, player_two.life());
+    }
+
+    #[test]
+    fn test_start_error() {
+        let player_one = Player::new(String::from("Chico"));
+
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+
+        assert_eq!(
+            Err(String::from("Game is not ready to start")),
+            game.start()
+        );
+    }
+
+    #[test]
+    fn test_start() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+
+        assert_eq!(Ok(()), game.start());
+        assert_eq!(Some(&player_one), game.turn_player());
+    }
+
+    #[test]
+    fn test_start_with_first_player() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.set_first_player(&player_two).unwrap();
+        game.start().unwrap();
+
+        assert_eq!(Some(&player_two), game.turn_player());
+        assert_eq!(
+            Err(String::from("Game already started")),
+            game.set_first_player(&player_one)
+        );
+    }
+
+    #[test]
+    fn test_guess_not_your_turn() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+
+        assert_eq!(
+            Ok(GameMessages::NotYourTurn),
+            game.guess_number(&player_two, 42)
+        );
+    }
+
+    #[test]
+    fn test_guess_out_of_range() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+
+        assert_eq!(
+            Err(String::from("Number must be between 1 and 100")),
+            game.guess_number(&player_one, 420)
+        );
+        assert_eq!(player_one, *game.turn_player.as_ref().unwrap());
+    }
+
+    #[test]
+    fn test_guess_wrong_answer_bigger() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+
+        assert_eq!(
+            Ok(GameMessages::WrongAnswer(Hint::Less(43))),
+            game.guess_number(&player_one, 43)
+        );
+    }
+
+    #[test]
+    fn test_guess_wrong_answer_smaller() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+
+        assert_eq!(
+            Ok(GameMessages::WrongAnswer(Hint::Greater(41))),
+            game.guess_number(&player_one, 41)
+        );
+    }
+
+    #[test]
+    fn test_guess_hot_cold() {
+        let rules = GameRules {
+            hint_style: HintStyle::HotCold,
+            ..GameRules::default()
+        };
+
+        assert_eq!(Hint::Hot(40), rules.hint(40, 42));
+        assert_eq!(Hint::Warm(60), rules.hint(60, 42));
+        assert_eq!(Hint::Cold(99), rules.hint(99, 42));
+        assert_eq!("60 is warm", rules.hint(60, 42).to_string());
+    }
+
+    #[test]
+    fn test_guess_you_win() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+
+        assert_eq!(Ok(GameMessages::YouWin), game.guess_number(&player_one, 42));
+        assert_eq!(GameMode::Finished, game.mode);
+    }
+
+    #[test]
+    fn test_guess_you_lose() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+        let rules = GameRules {
+            lives: 1,
+            ..GameRules::default()
+        };
+
+        let mut game = Game::new(&player_one, rules).unwrap();
+        game.accept_challenge(&player_two).unwrap();
+        game.set_secret_number(&player_one, 43).unwrap();
+        game.set_secret_number(&player_two, 42).unwrap();
+        game.set_ready().unwrap();
+        game.start().unwrap();
+
+        assert_eq!(Ok(GameMessages::YouLose), game.guess_number(&player_one, 1));
+        assert_eq!(GameMode::Finished, game.mode);
+    }
+
+    #[test]
+    fn test_player_switch() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+
+        game.guess_number(&player_one, 1).unwrap();
+        assert_eq!(*game.turn_player.as_ref().unwrap(), player_two);
+
+        game.guess_number(&player_two, 1).unwrap();
+        assert_eq!(*game.turn_player.as_ref().unwrap(), player_one);
+    }
+
+    #[test]
+    fn test_against_house() {
+        let player = Player::new(String::from("Chico"));
+
+        let mut game = Game::against_house(
+            &player,
+            GameRules::default(),
+            &mut StdRng::seed_from_u64(42),
+        )
+        .unwrap();
+
+        assert!(game.is_against_house());
+        assert_eq!(GameMode::Ready, game.mode);
+        assert_eq!(Ok(()), game.start());
+        assert_eq!(player, *game.turn_player.as_ref().unwrap());
+    }
+
+    #[test]
+    fn test_against_house_seeded_secret() {
+        let player = Player::new(String::from("Chico"));
+
+        let game = Game::against_house(
+            &player,
+            GameRules::default(),
+            &mut StdRng::seed_from_u64(42),
+        )
+        .unwrap();
+        let other_game = Game::against_house(
+            &player,
+            GameRules::default(),
+            &mut StdRng::seed_from_u64(42),
+        )
+        .unwrap();
+
+        let secret_number = game.players[1].secret_number();
+        assert_eq!(secret_number, other_game.players[1].secret_number());
+        assert!(GameRules::default()
+            .validate_number(secret_number.unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_against_house_invalid_rules() {
+        let player = Player::new(String::from("Chico"));
+        let rules = GameRules {
+            max: 0,
+            ..GameRules::default()
+        };
+
+        assert_eq!(
+            Err(String::from("Invalid range, min must be less than max")),
+            Game::against_house(&player, rules, &mut StdRng::seed_from_u64(42)).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_against_house_keeps_turn() {
+        let player = Player::new(String::from("Chico"));
+        let rules = GameRules {
+            min: 1,
+            max: 2,
+            ..GameRules::default()
+        };
+
+        let mut game = Game::against_house(&player, rules, &mut StdRng::seed_from_u64(7)).unwrap();
+        game.start().unwrap();
+
+        let secret_number = game.players[1].secret_number().unwrap();
+        let wrong_number = if secret_number == 1 { 2 } else { 1 };
+
+        assert!(matches!(
+            game.guess_number(&player, wrong_number),
+            Ok(GameMessages::WrongAnswer(_))
+        ));
+        assert_eq!(player, *game.turn_player.as_ref().unwrap());
+        assert_eq!(
+            Ok(GameMessages::YouWin),
+            game.guess_number(&player, secret_number)
+        );
+    }
+
+    #[test]
+    fn test_turn_expired() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        assert!(!game.turn_expired(Local::now() + Duration::seconds(60)));
+
+        game.start().unwrap();
+
+        assert!(!game.turn_expired(Local::now()));
+        assert!(game.turn_expired(Local::now() + Duration::seconds(30)));
+    }
+
+    #[test]
+    fn test_expire_turn() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+
+        assert_eq!(None, game.expire_turn(Local::now()));
+        assert_eq!(
+            Some(GameMessages::WrongAnswer(Hint::TimeIsOver)),
+            game.expire_turn(Local::now() + Duration::seconds(30))
+        );
+        assert_eq!(player_two, *game.turn_player.as_ref().unwrap());
+        assert_eq!(2, player_one.life());
+    }
+
+    fn three_player_game(players: &[Player]) -> Game {
+        let rules = GameRules {
+            lives: 2,
+            max_players: 3,
+            ..GameRules::default()
+        };
+
+        let mut game = Game::new(&players[0], rules).unwrap();
+        for (seat, player) in players.iter().enumerate() {
+            if seat > 0 {
+                game.accept_challenge(player).unwrap();
+            }
+            game.set_secret_number(player, 10 + seat as u16).unwrap();
+        }
+        game.set_ready().unwrap();
+        game.start().unwrap();
+        game
+    }
+
+    fn three_players() -> Vec<Player> {
+        vec![
+            Player::new(String::from("Chico")),
+            Player::new(String::from("Paloma")),
+            Player::new(String::from("Allan")),
+        ]
+    }
+
+    #[test]
+    fn test_multiplayer_waits_for_every_seat() {
+        let players = three_players();
+        let rules = GameRules {
+            max_players: 3,
+            ..GameRules::default()
+        };
+
+        let mut game = Game::new(&players[0], rules).unwrap();
+        game.accept_challenge(&players[1]).unwrap();
+
+        assert_eq!(Err(String::from("No player 3 present")), game.set_ready());
+        assert_eq!(
+            Err(String::from("Player already joined this game")),
+            game.accept_challenge(&players[1])
+        );
+    }
+
+    #[test]
+    fn test_multiplayer_turn_rotation() {
+        let players = three_players();
+        let mut game = three_player_game(&players);
+
+        game.guess_number(&players[0], 1).unwrap();
+        assert_eq!(players[1], *game.turn_player().unwrap());
+        game.guess_number(&players[1], 1).unwrap();
+        assert_eq!(players[2], *game.turn_player().unwrap());
+        game.guess_number(&players[2], 1).unwrap();
+        assert_eq!(players[0], *game.turn_player().unwrap());
+    }
+
+    #[test]
+    fn test_multiplayer_rotating_target() {
+        let players = three_players();
+        let mut game = three_player_game(&players);
+
+        assert_eq!(players[1], *game.next_target(&players[0]).unwrap());
+        game.guess_number(&players[0], 1).unwrap();
+        assert_eq!(players[1], *game.last_target(&players[0]).unwrap());
+        assert_eq!(players[2], *game.next_target(&players[0]).unwrap());
+    }
+
+    #[test]
+    fn test_multiplayer_chosen_target() {
+        let players = three_players();
+        let mut game = three_player_game(&players);
+
+        assert_eq!(
+            Ok(GameMessages::Cracked(players[2].id.clone())),
+            game.guess_number_at(&players[0], &players[2].id, 12)
+        );
+        assert_eq!(
+            Err(String::from("Opponent is not a valid target")),
+            game.guess_number_at(&players[1], &players[1].id, 12)
+        );
+    }
+
+    #[test]
+    fn test_multiplayer_crack_every_secret_wins() {
+        let players = three_players();
+        let mut game = three_player_game(&players);
+
+        game.guess_number_at(&players[0], &players[1].id, 11)
+            .unwrap();
+        game.guess_number(&players[1], 1).unwrap();
+        game.guess_number(&players[2], 1).unwrap();
+
+        assert_eq!(
+            Ok(GameMessages::YouWin),
+            game.guess_number_at(&players[0], &players[2].id, 12)
+        );
+        assert_eq!(GameMode::Finished, game.mode);
+        assert_eq!(players[0], *game.winner().unwrap());
+    }
+
+    #[test]
+    fn test_multiplayer_elimination() {
+        let players = three_players();
+        let mut game = three_player_game(&players);
+
+        game.guess_number(&players[0], 1).unwrap();
+        game.guess_number(&players[1], 1).unwrap();
+        game.guess_number(&players[2], 1).unwrap();
+
+        assert_eq!(Ok(GameMessages::YouLose), game.guess_number(&players[0], 1));
+        assert_eq!(GameMode::InProgress, game.mode);
+        assert_eq!(players[1], *game.turn_player().unwrap());
+
+        assert_eq!(vec![&players[1]], game.targets(&players[2]));
+        assert_eq!(
+            Err(String::from("Opponent is not a valid target")),
+            game.guess_number_at(&players[1], &players[0].id, 10)
+        );
+    }
+
+    #[test]
+    fn test_multiplayer_last_survivor_wins() {
+        let players = three_players();
+        let mut game = three_player_game(&players);
+
+        game.leave(&players[0]).unwrap();
+        assert_eq!(players[1], *game.turn_player().unwrap());
+        assert_eq!(GameMode::InProgress, game.mode);
+
+        game.leave(&players[2]).unwrap();
+        assert_eq!(GameMode::Finished, game.mode);
+        assert_eq!(players[1], *game.winner().unwrap());
+    }
+
+    #[test]
+    fn test_guesses_are_recorded() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+        game.guess_number(&player_one, 1).unwrap();
+        game.guess_number(&player_two, 43).unwrap();
+
+        let guesses = game.guesses();
+        assert_eq!(2, guesses.len());
+        assert_eq!(player_one.id, guesses[0].player_id);
+        assert_eq!(player_two.id, guesses[0].target_id);
+        assert_eq!(Some(Hint::Greater(1)), guesses[0].hint);
+        assert_eq!(None, guesses[1].hint);
+    }
+
+    #[test]
+    fn test_revealed_secret_number() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+        assert_eq!(None, game.revealed_secret_number(&player_two));
+
+        game.guess_number(&player_one, 42).unwrap();
+        assert_eq!(Some(42), game.revealed_secret_number(&player_two));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+
+        let mut game = ready_game(&player_one, &player_two);
+        game.start().unwrap();
+        game.guess_number(&player_one, 1).unwrap();
+
+        let json = serde_json::to_string(&GameSnapshot::from(&game)).unwrap();
+        let mut restored = Game::from(serde_json::from_str::<GameSnapshot>(&json).unwrap());
+        let player_one = restored.players[0].clone();
+        let player_two = restored.players[1].clone();
+
+        assert_eq!(GameMode::InProgress, restored.mode);
+        assert_eq!(Some(&player_two), restored.turn_player());
+        assert_eq!(1, restored.guesses().len());
+        assert_eq!(
+            Ok(GameMessages::WrongAnswer(Hint::Less(50))),
+            restored.guess_number(&player_two, 50)
+        );
+        assert_eq!(
+            Ok(GameMessages::YouWin),
+            restored.guess_number(&player_one, 42)
+        );
+        assert_eq!(Some(&player_one), restored.winner());
+    }
+}
@@ -0,0 +1,304 @@
+use crate::game::{Game, GameMessages, GameMode, GameRules, Move, Player};
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerRecord {
+    pub id: String,
+    pub name: String,
+    pub secret_number: Option<u16>,
+    #[serde(default)]
+    pub bot: bool,
+}
+
+/// Everything needed to tell, and verify, how a finished game went.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    pub id: String,
+    pub rules: GameRules,
+    pub against_house: bool,
+    pub players: Vec<PlayerRecord>,
+    /// Who took the first turn when not the player that created the game.
+    #[serde(default)]
+    pub first_player: Option<String>,
+    pub moves: Vec<Move>,
+    pub winner: Option<String>,
+    pub finished_at: DateTime<Local>,
+}
+
+impl From<&Game> for GameRecord {
+    fn from(game: &Game) -> Self {
+        GameRecord {
+            id: game.id().to_string(),
+            rules: game.rules().clone(),
+            against_house: game.is_against_house(),
+            players: game
+                .players()
+                .into_iter()
+                .map(|player| PlayerRecord {
+                    id: player.id.clone(),
+                    name: player.name(),
+                    secret_number: game.revealed_secret_number(player),
+                    bot: player.is_bot(),
+                })
+                .collect(),
+            first_player: game.first_player().map(|player| player.id.clone()),
+            moves: game.moves().to_vec(),
+            winner: game.winner().map(|winner| winner.id.clone()),
+            finished_at: Local::now(),
+        }
+    }
+}
+
+impl GameRecord {
+    /// Bots are easy to beat over and over, their games are neither rated
+    /// nor counted in stats.
+    pub fn has_bot(&self) -> bool {
+        self.players.iter().any(|player| player.bot)
+    }
+
+    /// Players of the game, the house and bots do not count.
+    pub fn ranked_players(&self) -> impl Iterator<Item = &PlayerRecord> {
+        self.players
+            .iter()
+            .enumerate()
+            .filter(move |(seat, player)| !player.bot && !(self.against_house && *seat == 1))
+            .map(|(_, player)| player)
+    }
+
+    /// Plays the recorded moves again on a new game, failing on the first
+    /// one that does not match what the game says.
+    pub fn replay(&self) -> Result<(), String> {
+        let players: Vec<Player> = self
+            .players
+            .iter()
+            .map(|player| Player::with_id(player.id.clone(), player.name.clone()))
+            .collect();
+        let player = |id: &str| {
+            players
+                .iter()
+                .find(|player| player.id == id)
+                .ok_or_else(|| format!("Unknown player {}", id))
+        };
+
+        for (player, record) in players.iter().zip(self.players.iter()) {
+            if let Some(secret_number) = record.secret_number {
+                player.set_secret_number(secret_number, &self.rules)?;
+            }
+        }
+
+        let mut game = match players.as_slice() {
+            [human, house] if self.against_house => {
+                Game::against(human, house, self.rules.clone())?
+            }
+            [first, others @ ..] => {
+                let mut game = Game::new(first, self.rules.clone())?;
+                for other in others {
+                    game.accept_challenge(other)?;
+                }
+                game.set_ready()?;
+                if let Some(first_player) = &self.first_player {
+                    game.set_first_player(player(first_player)?)?;
+                }
+                game
+            }
+            [] => return Err(String::from("Game record has no players")),
+        };
+        game.start()?;
+
+        for (index, game_move) in self.moves.iter().enumerate() {
+            match game_move {
+                Move::Guess(guess) => {
+                    let message = game.guess_number_at(
+                        player(&guess.player_id)?,
+                        &guess.target_id,
+                        guess.number,
+                    )?;
+                    let hint = game.guesses().last().and_then(|replayed| replayed.hint);
+
+                    if message == GameMessages::NotYourTurn || hint != guess.hint {
+                        return Err(format!("Move {} does not match the game", index + 1));
+                    }
+                }
+                Move::TurnExpired { player_id, .. } => {
+                    if game.turn_player().map(|player| &player.id) != Some(player_id) {
+                        return Err(format!("Move {} does not match the game", index + 1));
+                    }
+                    let expired_at =
+                        Local::now() + Duration::seconds(self.rules.turn_seconds as i64);
+                    game.expire_turn(expired_at);
+                }
+                Move::Left { player_id, .. } => game.leave(player(player_id)?)?,
+            }
+        }
+
+        if game.mode() != &GameMode::Finished {
+            return Err(String::from("Game did not finish"));
+        }
+        if game.winner().map(|winner| winner.id.clone()) != self.winner {
+            return Err(String::from("Game winner does not match"));
+        }
+        Ok(())
+    }
+}
+
+/// Finished games, one Json record per line in an append only file.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn open<P: Into<PathBuf>>(path: P) -> History {
+        History { path: path.into() }
+    }
+
+    pub fn save(&self, record: &GameRecord) -> Result<(), String> {
+        let mut line = serde_json::to_string(record).map_err(|error| format!("{}", error))?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|error| format!("{}", error))?;
+        file.write_all(line.as_bytes())
+            .and_then(|_| file.sync_data())
+            .map_err(|error| format!("{}", error))
+    }
+
+    pub fn records(&self) -> Result<Vec<GameRecord>, String> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        BufReader::new(file)
+            .lines()
+            .map(|line| {
+                let line = line.map_err(|error| format!("{}", error))?;
+                serde_json::from_str(&line).map_err(|error| format!("{}", error))
+            })
+            .collect()
+    }
+
+    pub fn find(&self, game_id: &str) -> Result<GameRecord, String> {
+        self.records()?
+            .into_iter()
+            .find(|record| record.id == game_id)
+            .ok_or_else(|| format!("Game {} not found", game_id))
+    }
+
+    pub fn export<W: Write>(&self, writer: &mut W) -> Result<(), String> {
+        for record in self.records()? {
+            let line = serde_json::to_string(&record).map_err(|error| format!("{}", error))?;
+            writeln!(writer, "{}", line).map_err(|error| format!("{}", error))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Game, GameRules, Move, Player};
+    use crate::history::{GameRecord, History};
+    use chrono::{Duration, Local};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::env;
+    use std::fs;
+
+    fn finished_game() -> Game {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+        game.accept_challenge(&player_two).unwrap();
+        game.set_secret_number(&player_one, 43).unwrap();
+        game.set_secret_number(&player_two, 42).unwrap();
+        game.set_ready().unwrap();
+        game.start().unwrap();
+
+        game.guess_number(&player_one, 1).unwrap();
+        game.expire_turn(Local::now() + Duration::seconds(30));
+        game.guess_number(&player_one, 42).unwrap();
+        game
+    }
+
+    #[test]
+    fn test_record() {
+        let game = finished_game();
+        let record = GameRecord::from(&game);
+
+        assert_eq!(Some(43), record.players[0].secret_number);
+        assert_eq!(Some(42), record.players[1].secret_number);
+        assert_eq!(Some(record.players[0].id.clone()), record.winner);
+        assert_eq!(3, record.moves.len());
+        assert_eq!(Ok(()), record.replay());
+    }
+
+    #[test]
+    fn test_replay_game_started_by_second_player() {
+        let player_one = Player::new(String::from("Chico"));
+        let player_two = Player::new(String::from("Paloma"));
+        let mut game = Game::new(&player_one, GameRules::default()).unwrap();
+        game.accept_challenge(&player_two).unwrap();
+        game.set_secret_number(&player_one, 43).unwrap();
+        game.set_secret_number(&player_two, 42).unwrap();
+        game.set_ready().unwrap();
+        game.set_first_player(&player_two).unwrap();
+        game.start().unwrap();
+
+        game.guess_number(&player_two, 43).unwrap();
+        let record = GameRecord::from(&game);
+
+        assert_eq!(Some(player_two.id.clone()), record.first_player);
+        assert_eq!(Some(player_two.id.clone()), record.winner);
+        assert_eq!(Ok(()), record.replay());
+    }
+
+    #[test]
+    fn test_replay_house_game() {
+        let player = Player::new(String::from("Chico"));
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut game = Game::against_house(&player, GameRules::default(), &mut rng).unwrap();
+        game.start().unwrap();
+        game.leave(&player).unwrap();
+
+        assert_eq!(Ok(()), GameRecord::from(&game).replay());
+    }
+
+    #[test]
+    fn test_replay_tampered_record() {
+        let mut record = GameRecord::from(&finished_game());
+        if let Move::Guess(guess) = &mut record.moves[0] {
+            guess.number = 2;
+        }
+
+        assert_eq!(
+            Err(String::from("Move 1 does not match the game")),
+            record.replay()
+        );
+    }
+
+    #[test]
+    fn test_history() {
+        let path = env::temp_dir().join(format!("history-{}.jsonl", uuid::Uuid::new_v4()));
+        let history = History::open(&path);
+        let record = GameRecord::from(&finished_game());
+
+        assert_eq!(Ok(Vec::new()), history.records());
+        history.save(&record).unwrap();
+        history.save(&GameRecord::from(&finished_game())).unwrap();
+
+        assert_eq!(Ok(record.clone()), history.find(&record.id));
+        assert!(history.find("unknown").is_err());
+
+        let mut export = Vec::new();
+        history.export(&mut export).unwrap();
+        assert_eq!(2, String::from_utf8(export).unwrap().lines().count());
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,27 @@
+#![feature(box_syntax)]
+#![cfg_attr(test, feature(test))]
+
+pub mod accounts;
+pub mod async_client;
+pub mod bot;
+pub mod chat;
+pub mod client;
+pub mod codec;
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+pub mod game;
+pub mod history;
+pub mod matchmaking;
+pub mod messages;
+pub mod protocol;
+pub mod rating;
+pub mod series;
+pub mod server;
+pub mod snapshot;
+pub mod spec;
+pub mod stats;
+pub mod throttle;
+pub mod tls;
+pub mod tournament;
+pub mod transport;
+pub mod websocket;
@@ -1,14 +1,80 @@
-#![feature(box_syntax)]
+use rust_guessing_game_over_net::accounts::Accounts;
+use rust_guessing_game_over_net::chat::Blocklist;
+use rust_guessing_game_over_net::history::History;
+use rust_guessing_game_over_net::protocol::ConnectionLimits;
+use rust_guessing_game_over_net::server::Server;
+use rust_guessing_game_over_net::snapshot::Snapshot;
+use rust_guessing_game_over_net::tls;
+use rustls::ServerConfig;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{env, fs, io, process};
 
-mod game;
-mod server;
-mod protocol;
-mod messages;
+const HISTORY_PATH: &str = "history.jsonl";
+const ACCOUNTS_PATH: &str = "accounts.jsonl";
+const SNAPSHOT_PATH: &str = "snapshot.json";
 
-use server::Server;
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let history = History::open(HISTORY_PATH);
 
+    let result = match args.get(1).map(String::as_str) {
+        Some("export") => history.export(&mut io::stdout()),
+        Some("replay") => match args.get(2) {
+            Some(game_id) => history
+                .find(game_id)
+                .and_then(|record| record.replay())
+                .map(|_| println!("Game {} replayed, it matches the record", game_id)),
+            None => Err(String::from("Usage: replay <game id>")),
+        },
+        Some("tls") => match (args.get(2), args.get(3)) {
+            (Some(cert_path), Some(key_path)) => tls::server_config(cert_path, key_path)
+                .and_then(|config| serve(history, Some(config))),
+            _ => Err(String::from("Usage: tls <certificate path> <key path>")),
+        },
+        Some(command) => Err(format!("Unknown command {}", command)),
+        None => serve(history, None),
+    };
 
+    if let Err(error) = result {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
 
-fn main() {
-    Server::new().run();
+fn serve(history: History, tls: Option<Arc<ServerConfig>>) -> Result<(), String> {
+    let blocklist = fs::read_to_string("blocklist.txt").unwrap_or_default();
+    let words: Vec<&str> = blocklist.split_whitespace().collect();
+
+    let mut server = Server::new()
+        .with_chat_filter(Box::new(Blocklist::new(&words)))
+        .with_history(history)
+        .with_accounts(Accounts::open(ACCOUNTS_PATH)?)
+        .with_snapshot(Snapshot::open(SNAPSHOT_PATH))
+        .with_connection_limits(connection_limits()?);
+
+    if let Some(config) = tls {
+        server = server.with_tls(config);
+    }
+    server.run()
+}
+
+/// Limits left out of the environment keep their default.
+fn connection_limits() -> Result<ConnectionLimits, String> {
+    let defaults = ConnectionLimits::default();
+
+    Ok(ConnectionLimits {
+        max_connections: env_var("MAX_CONNECTIONS", defaults.max_connections)?,
+        max_connections_per_ip: env_var("MAX_CONNECTIONS_PER_IP", defaults.max_connections_per_ip)?,
+        handshake_seconds: env_var("HANDSHAKE_SECONDS", defaults.handshake_seconds)?,
+    })
+}
+
+fn env_var<T: FromStr>(name: &str, default: T) -> Result<T, String> {
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| format!("{} must be a number, not {}", name, value)),
+        Err(_) => Ok(default),
+    }
 }
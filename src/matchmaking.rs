@@ -0,0 +1,143 @@
+use chrono::{DateTime, Local};
+
+/// Rating difference accepted right away, it grows while a player waits.
+const BASE_WINDOW: f64 = 100.0;
+const WINDOW_GROWTH_PER_SECOND: f64 = 10.0;
+
+struct QueuedPlayer {
+    player_id: String,
+    rating: f64,
+    queued_at: DateTime<Local>,
+}
+
+impl QueuedPlayer {
+    fn window(&self, now: DateTime<Local>) -> f64 {
+        let waited = (now - self.queued_at).num_seconds().max(0) as f64;
+        BASE_WINDOW + waited * WINDOW_GROWTH_PER_SECOND
+    }
+}
+
+#[derive(Default)]
+pub struct Matchmaker {
+    queue: Vec<QueuedPlayer>,
+}
+
+impl Matchmaker {
+    pub fn join(
+        &mut self,
+        player_id: &str,
+        rating: f64,
+        now: DateTime<Local>,
+    ) -> Result<(), String> {
+        if self.contains(player_id) {
+            return Err(String::from("Player is already waiting for an opponent"));
+        }
+
+        self.queue.push(QueuedPlayer {
+            player_id: player_id.to_string(),
+            rating,
+            queued_at: now,
+        });
+        Ok(())
+    }
+
+    pub fn leave(&mut self, player_id: &str) -> bool {
+        let queued = self.queue.len();
+        self.queue.retain(|queued| queued.player_id != player_id);
+        queued != self.queue.len()
+    }
+
+    pub fn contains(&self, player_id: &str) -> bool {
+        self.queue
+            .iter()
+            .any(|queued| queued.player_id == player_id)
+    }
+
+    /// Pairs players, longest waiting first, with the closest rated opponent
+    /// inside the window of either one.
+    pub fn find_matches(&mut self, now: DateTime<Local>) -> Vec<(String, String)> {
+        let mut matches = Vec::new();
+        let mut seat = 0;
+
+        while seat < self.queue.len() {
+            let player = &self.queue[seat];
+            let opponent = self
+                .queue
+                .iter()
+                .enumerate()
+                .skip(seat + 1)
+                .map(|(opponent_seat, opponent)| {
+                    (
+                        opponent_seat,
+                        (opponent.rating - player.rating).abs(),
+                        opponent,
+                    )
+                })
+                .filter(|(_, difference, opponent)| {
+                    *difference <= player.window(now).max(opponent.window(now))
+                })
+                .min_by(|(_, one, _), (_, other, _)| one.partial_cmp(other).unwrap())
+                .map(|(opponent_seat, _, _)| opponent_seat);
+
+            match opponent {
+                Some(opponent_seat) => {
+                    let opponent = self.queue.remove(opponent_seat);
+                    let player = self.queue.remove(seat);
+                    matches.push((player.player_id, opponent.player_id));
+                }
+                None => seat += 1,
+            }
+        }
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::matchmaking::Matchmaker;
+    use chrono::{Duration, Local};
+
+    #[test]
+    fn test_closest_rating_is_preferred() {
+        let mut matchmaker = Matchmaker::default();
+        let now = Local::now();
+        matchmaker.join("chico", 1500.0, now).unwrap();
+        matchmaker.join("paloma", 1580.0, now).unwrap();
+        matchmaker.join("allan", 1520.0, now).unwrap();
+
+        assert_eq!(
+            vec![(String::from("chico"), String::from("allan"))],
+            matchmaker.find_matches(now)
+        );
+        assert!(matchmaker.contains("paloma"));
+    }
+
+    #[test]
+    fn test_window_widens_while_waiting() {
+        let mut matchmaker = Matchmaker::default();
+        let now = Local::now();
+        matchmaker.join("chico", 1500.0, now).unwrap();
+        matchmaker.join("paloma", 1800.0, now).unwrap();
+
+        assert!(matchmaker.find_matches(now).is_empty());
+        assert!(matchmaker
+            .find_matches(now + Duration::seconds(10))
+            .is_empty());
+        assert_eq!(
+            1,
+            matchmaker.find_matches(now + Duration::seconds(20)).len()
+        );
+    }
+
+    #[test]
+    fn test_join_and_leave() {
+        let mut matchmaker = Matchmaker::default();
+        let now = Local::now();
+        matchmaker.join("chico", 1500.0, now).unwrap();
+
+        assert!(matchmaker.join("chico", 1500.0, now).is_err());
+        assert!(matchmaker.leave("chico"));
+        assert!(!matchmaker.leave("chico"));
+        assert!(matchmaker.find_matches(now).is_empty());
+    }
+}
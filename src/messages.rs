@@ -1,7 +1,17 @@
+use crate::protocol::{Frame, UserInfo};
 use std::sync::mpsc::Sender;
-use std::net::TcpStream;
+use std::net::{IpAddr, TcpStream};
 
 pub enum ServerCommand {
     Message(String),
     AcceptedConnection(TcpStream, Box<Sender<ServerCommand>>),
+    AcceptedWebSocket(TcpStream, Box<Sender<ServerCommand>>),
+    ConnectionClosed(IpAddr),
+    PlayerRegistered(UserInfo, Sender<Frame>, Sender<Result<(), String>>),
+    PlayerResumed(UserInfo, Sender<Frame>, Sender<Result<(), String>>),
+    PlayerFrame(String, Frame),
+    PlayerDisconnected(String),
+    BotTurn(String),
+    Tick,
+    Shutdown,
 }
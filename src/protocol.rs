@@ -1,19 +1,31 @@
+extern crate byteorder;
 extern crate serde;
 extern crate serde_json;
-extern crate byteorder;
 
-use std::sync::mpsc::Sender;
+use crate::bot::BotChallenge;
+use crate::codec::{Codec, CODECS};
+use crate::game::{Game, GameMode, GameRules, GuessRecord};
 use crate::messages::ServerCommand;
-use std::io::{BufReader, BufRead};
+use crate::series::{MatchResult, MatchRules, SeriesInfo};
+use crate::stats::{LeaderboardEntry, Period, PlayerStats};
+use crate::throttle::Throttle;
+use crate::tournament::{Tournament, TournamentRules};
+use crate::transport::Transport;
 use std::convert::{From, TryFrom};
-use std::net::{TcpStream, Shutdown};
-use std::io::{Write, Read};
+use std::io::{self, ErrorKind, Read};
+use std::sync::mpsc::{channel, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
-use serde::{Serialize, Deserialize};
+use byteorder::{NetworkEndian, ReadBytesExt, WriteBytesExt};
+use uuid::Uuid;
 
-use byteorder::{NetworkEndian, WriteBytesExt};
+pub type Method = (u8, u8);
 
-pub const FRAME_END: u8 = 0x4;
+include!("protocol_spec.rs");
 
 #[derive(Debug, PartialEq)]
 pub struct ProtocolHeader {
@@ -23,7 +35,24 @@ pub struct ProtocolHeader {
 impl Default for ProtocolHeader {
     fn default() -> Self {
         ProtocolHeader {
-            header: String::from("GG010"),
+            header: String::from(PROTOCOL_HEADER),
+        }
+    }
+}
+
+impl ProtocolHeader {
+    /// Same frames as Json lines, for netcat and scripts.
+    pub fn text() -> Self {
+        ProtocolHeader {
+            header: String::from(TEXT_HEADER),
+        }
+    }
+
+    pub fn wire_format(&self) -> WireFormat {
+        if self.header == TEXT_HEADER {
+            WireFormat::Text
+        } else {
+            WireFormat::Binary(Codec::Json)
         }
     }
 }
@@ -36,6 +65,8 @@ impl TryFrom<Vec<u8>> for ProtocolHeader {
             Ok(incoming_header) => {
                 if ProtocolHeader::default().header == incoming_header {
                     Ok(ProtocolHeader::default())
+                } else if incoming_header == TEXT_HEADER {
+                    Ok(ProtocolHeader::text())
                 } else {
                     Err(ProtocolHeader::default().header)
                 }
@@ -45,7 +76,78 @@ impl TryFrom<Vec<u8>> for ProtocolHeader {
     }
 }
 
-struct Frame {
+/// How frames travel on a connection, picked by the protocol header. Binary
+/// frames carry their Json payloads with the codec agreed on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WireFormat {
+    Binary(Codec),
+    Text,
+}
+
+impl WireFormat {
+    pub fn read_frame<R: Read>(&self, reader: &mut R) -> Result<Frame, String> {
+        match self {
+            WireFormat::Binary(codec) => Frame::read_from(reader)?.transcode(*codec, Codec::Json),
+            WireFormat::Text => Frame::read_line_from(reader),
+        }
+    }
+
+    pub fn encode(&self, frame: Frame) -> Result<Vec<u8>, String> {
+        match self {
+            WireFormat::Binary(codec) => Ok(Vec::<u8>::from(frame.transcode(Codec::Json, *codec)?)),
+            WireFormat::Text => {
+                let mut line = frame.to_text()?;
+                line.push('\n');
+                Ok(line.into_bytes())
+            }
+        }
+    }
+}
+
+/// How the payload of each method is laid out, the frame end can also show
+/// up inside a payload so the reader must know how many bytes to expect.
+#[derive(Debug, PartialEq)]
+enum PayloadKind {
+    Empty,
+    ShortString,
+    LongString,
+    Number,
+}
+
+/// Longest line read in the text format, a frame never gets close.
+const MAX_LINE_SIZE: usize = 64 * 1024;
+
+/// Largest payload read in the binary format, the size a frame announces is
+/// checked before reading any of it.
+pub const MAX_PAYLOAD_SIZE: usize = 1024 * 1024;
+
+/// Error of a frame announcing more than `MAX_PAYLOAD_SIZE`.
+pub const FRAME_TOO_LARGE: &str = "Frame too large";
+
+/// A frame in the text format, the payload goes in the field matching its
+/// layout: `text` for short strings, `n` for numbers and `data` for Json.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct TextFrame {
+    class: String,
+    method: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    n: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+}
+
+fn convert<T: Serialize + DeserializeOwned>(
+    content: &[u8],
+    from: Codec,
+    to: Codec,
+) -> Result<Vec<u8>, String> {
+    to.encode(&from.decode::<T>(content)?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
     class: u8,
     method: u8,
     payload: Vec<u8>,
@@ -56,9 +158,198 @@ impl Frame {
         Frame {
             class,
             method,
-            payload
+            payload,
+        }
+    }
+
+    pub fn empty(method: Method) -> Self {
+        Frame::new(method.0, method.1, Vec::new())
+    }
+
+    /// Texts over 255 bytes are cut, on a character boundary.
+    pub fn text(method: Method, text: &str) -> Self {
+        let size = text
+            .char_indices()
+            .map(|(index, character)| index + character.len_utf8())
+            .take_while(|end| *end <= u8::MAX as usize)
+            .last()
+            .unwrap_or(0);
+        let bytes = &text.as_bytes()[..size];
+        let mut payload = Vec::new();
+        payload.write_u8(bytes.len() as u8).unwrap();
+        payload.extend(bytes);
+        Frame::new(method.0, method.1, payload)
+    }
+
+    pub fn json<T: Serialize>(method: Method, value: &T) -> Self {
+        let json = serde_json::to_vec(value).unwrap();
+        let mut payload = Vec::new();
+        payload
+            .write_u32::<NetworkEndian>(json.len() as u32)
+            .unwrap();
+        payload.extend(json);
+        Frame::new(method.0, method.1, payload)
+    }
+
+    pub fn number(method: Method, number: u16) -> Self {
+        let mut payload = Vec::new();
+        payload.write_u16::<NetworkEndian>(number).unwrap();
+        Frame::new(method.0, method.1, payload)
+    }
+
+    pub fn method(&self) -> Method {
+        (self.class, self.method)
+    }
+
+    pub fn text_payload(&self) -> Result<String, String> {
+        match self.payload.split_first() {
+            Some((_, bytes)) => {
+                String::from_utf8(bytes.to_vec()).map_err(|_| String::from("Invalid text payload"))
+            }
+            None => Err(String::from("Invalid text payload")),
         }
     }
+
+    pub fn json_payload<T: DeserializeOwned>(&self) -> Result<T, String> {
+        if self.payload.len() < 4 {
+            return Err(String::from("Invalid json payload"));
+        }
+        serde_json::from_slice(&self.payload[4..]).map_err(|error| format!("{}", error))
+    }
+
+    pub fn number_payload(&self) -> Result<u16, String> {
+        (&self.payload[..])
+            .read_u16::<NetworkEndian>()
+            .map_err(|_| String::from("Invalid number payload"))
+    }
+
+    /// Same frame with its Json payload encoded by another codec.
+    pub fn transcode(self, from: Codec, to: Codec) -> Result<Frame, String> {
+        if from == to || payload_kind(self.method())? != PayloadKind::LongString {
+            return Ok(self);
+        }
+        if self.payload.len() < 4 {
+            return Err(String::from("Invalid json payload"));
+        }
+
+        let content = transcode_payload(self.method(), &self.payload[4..], from, to)?;
+        let mut payload = Vec::new();
+        payload
+            .write_u32::<NetworkEndian>(content.len() as u32)
+            .unwrap();
+        payload.extend(content);
+        Ok(Frame::new(self.class, self.method, payload))
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Frame, String> {
+        let class = reader.read_u8().map_err(|error| format!("{}", error))?;
+        let method = reader.read_u8().map_err(|error| format!("{}", error))?;
+
+        let mut payload = Vec::new();
+        let content_size = match payload_kind((class, method))? {
+            PayloadKind::Empty => 0,
+            PayloadKind::Number => 2,
+            PayloadKind::ShortString => {
+                let size = reader.read_u8().map_err(|error| format!("{}", error))?;
+                payload.write_u8(size).unwrap();
+                size as usize
+            }
+            PayloadKind::LongString => {
+                let size = reader
+                    .read_u32::<NetworkEndian>()
+                    .map_err(|error| format!("{}", error))?;
+                if size as usize > MAX_PAYLOAD_SIZE {
+                    return Err(String::from(FRAME_TOO_LARGE));
+                }
+                payload.write_u32::<NetworkEndian>(size).unwrap();
+                size as usize
+            }
+        };
+
+        let mut content = Vec::new();
+        reader
+            .take(content_size as u64)
+            .read_to_end(&mut content)
+            .map_err(|error| format!("{}", error))?;
+
+        if content.len() != content_size {
+            return Err(String::from("Malformed frame"));
+        }
+        payload.extend(content);
+
+        match reader.read_u8() {
+            Ok(FRAME_END) => Ok(Frame::new(class, method, payload)),
+            _ => Err(String::from("Malformed frame")),
+        }
+    }
+
+    /// Reads the next non empty line of the text format.
+    pub fn read_line_from<R: Read>(reader: &mut R) -> Result<Frame, String> {
+        let mut line = Vec::new();
+        loop {
+            let byte = reader.read_u8().map_err(|error| format!("{}", error))?;
+            match byte {
+                b'\n' if line.iter().all(u8::is_ascii_whitespace) => line.clear(),
+                b'\n' => break,
+                _ if line.len() == MAX_LINE_SIZE => return Err(String::from("Line too long")),
+                _ => line.push(byte),
+            }
+        }
+
+        let line = String::from_utf8(line).map_err(|_| String::from("Invalid line"))?;
+        Frame::from_text(&line)
+    }
+
+    pub fn from_text(line: &str) -> Result<Frame, String> {
+        let text_frame: TextFrame =
+            serde_json::from_str(line).map_err(|error| format!("{}", error))?;
+        let method = METHOD_NAMES
+            .iter()
+            .find(|(_, class, method)| *class == text_frame.class && *method == text_frame.method)
+            .map(|(method, _, _)| *method)
+            .ok_or_else(|| format!("Unknown method {}.{}", text_frame.class, text_frame.method))?;
+
+        match payload_kind(method)? {
+            PayloadKind::Empty => Ok(Frame::empty(method)),
+            PayloadKind::ShortString => match text_frame.text {
+                Some(text) if text.len() > u8::MAX as usize => {
+                    Err(String::from("Text too long, 255 bytes at most"))
+                }
+                Some(text) => Ok(Frame::text(method, &text)),
+                None => Err(String::from("Missing text")),
+            },
+            PayloadKind::Number => match text_frame.n {
+                Some(number) => Ok(Frame::number(method, number)),
+                None => Err(String::from("Missing n")),
+            },
+            PayloadKind::LongString => match text_frame.data {
+                Some(data) => Ok(Frame::json(method, &data)),
+                None => Err(String::from("Missing data")),
+            },
+        }
+    }
+
+    pub fn to_text(&self) -> Result<String, String> {
+        let (_, class, method) = METHOD_NAMES
+            .iter()
+            .find(|(method, _, _)| *method == self.method())
+            .ok_or_else(|| format!("Unknown method {}.{}", self.class, self.method))?;
+        let mut text_frame = TextFrame {
+            class: class.to_string(),
+            method: method.to_string(),
+            text: None,
+            n: None,
+            data: None,
+        };
+
+        match payload_kind(self.method())? {
+            PayloadKind::Empty => {}
+            PayloadKind::ShortString => text_frame.text = Some(self.text_payload()?),
+            PayloadKind::Number => text_frame.n = Some(self.number_payload()?),
+            PayloadKind::LongString => text_frame.data = Some(self.json_payload()?),
+        }
+        serde_json::to_string(&text_frame).map_err(|error| format!("{}", error))
+    }
 }
 
 impl From<Frame> for Vec<u8> {
@@ -74,31 +365,104 @@ impl From<Frame> for Vec<u8> {
 
 impl TryFrom<Vec<u8>> for Frame {
     type Error = String;
-    
-    fn try_from(mut payload: Vec<u8>) -> Result<Self, String> {
-        let frame_end = payload.pop();
 
-        if frame_end.is_none() || frame_end.unwrap() != FRAME_END {
-            return Err(String::from("Malformed frame"))
+    fn try_from(payload: Vec<u8>) -> Result<Self, String> {
+        let mut reader = &payload[..];
+        let frame = Frame::read_from(&mut reader)?;
+
+        if !reader.is_empty() {
+            return Err(String::from("Malformed frame"));
         }
 
-        Ok(Frame {
-            class: 1,
-            method: 1,
-            payload: Vec::new()
-        })
+        Ok(frame)
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-struct ConnectionConstraints {
-    max_name_size: u8
+pub struct ConnectionConstraints {
+    pub max_name_size: u8,
+    pub max_message_size: u8,
+    /// Chat messages a player can send every `chat_window_seconds`.
+    pub max_chat_messages: u8,
+    pub chat_window_seconds: u8,
+    /// Payload codecs a client can pick with Registration.Codec.
+    pub codecs: Vec<Codec>,
+    /// Frames over a limit are answered with an Error and dropped.
+    pub frame_limits: Vec<FrameLimit>,
+    /// Frames dropped in a row before the connection is closed.
+    pub max_throttled_frames: u8,
 }
 
 impl Default for ConnectionConstraints {
     fn default() -> Self {
         ConnectionConstraints {
-            max_name_size: 10u8
+            max_name_size: 10u8,
+            max_message_size: 200u8,
+            max_chat_messages: 5u8,
+            chat_window_seconds: 10u8,
+            codecs: CODECS.to_vec(),
+            frame_limits: vec![
+                FrameLimit {
+                    class: None,
+                    per_second: 20,
+                    burst: 40,
+                },
+                FrameLimit {
+                    class: Some(1),
+                    per_second: 1,
+                    burst: 5,
+                },
+                FrameLimit {
+                    class: Some(2),
+                    per_second: 5,
+                    burst: 10,
+                },
+                FrameLimit {
+                    class: Some(3),
+                    per_second: 2,
+                    burst: 5,
+                },
+                FrameLimit {
+                    class: Some(4),
+                    per_second: 1,
+                    burst: 5,
+                },
+                FrameLimit {
+                    class: Some(5),
+                    per_second: 2,
+                    burst: 5,
+                },
+            ],
+            max_throttled_frames: 10u8,
+        }
+    }
+}
+
+/// Token bucket a connection gets for the frames of a protocol class.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FrameLimit {
+    /// Every class shares the limit when missing.
+    pub class: Option<u8>,
+    /// Frames a client can send every second once its burst is spent.
+    pub per_second: u16,
+    pub burst: u16,
+}
+
+/// Limits on the connections a server takes, they stay on the server.
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionLimits {
+    pub max_connections: usize,
+    pub max_connections_per_ip: usize,
+    /// Time a client has to send its header and register.
+    pub handshake_seconds: u64,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        ConnectionLimits {
+            max_connections: 1000,
+            max_connections_per_ip: 10,
+            handshake_seconds: 10,
         }
     }
 }
@@ -110,76 +474,442 @@ impl From<ConnectionConstraints> for Vec<u8> {
     }
 }
 
-pub struct Connection {
-    stream: TcpStream,
-    main_tx: Sender<ServerCommand>
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UserInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub stats: Option<PlayerStats>,
+    /// Secret the player resumes with, only the player gets it in User.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Guess {
+    pub target: Option<String>,
+    pub number: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct PlayerInfo {
+    pub id: String,
+    pub name: String,
+    pub life: u8,
+    pub secret_number: Option<u16>,
+    #[serde(default)]
+    pub bot: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct GameInfo {
+    pub id: String,
+    pub mode: GameMode,
+    pub rules: GameRules,
+    pub players: Vec<PlayerInfo>,
+}
+
+impl From<&Game> for GameInfo {
+    fn from(game: &Game) -> Self {
+        GameInfo {
+            id: game.id().to_string(),
+            mode: game.mode().clone(),
+            rules: game.rules().clone(),
+            players: game
+                .players()
+                .iter()
+                .map(|player| PlayerInfo {
+                    id: player.id.clone(),
+                    name: player.name(),
+                    life: player.life(),
+                    secret_number: game.revealed_secret_number(player),
+                    bot: player.is_bot(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What spectators get pushed while a game is running.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum GameEvent {
+    Turn {
+        player: String,
+    },
+    Guess {
+        player: String,
+        target: String,
+        number: u16,
+        hint: Option<String>,
+    },
+}
+
+impl From<&GuessRecord> for GameEvent {
+    fn from(guess: &GuessRecord) -> Self {
+        GameEvent::Guess {
+            player: guess.player_id.clone(),
+            target: guess.target_id.clone(),
+            number: guess.number,
+            hint: guess.hint.map(|hint| hint.to_string()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ChatChannel {
+    Lobby,
+    Game,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub channel: ChatChannel,
+    pub from: UserInfo,
+    pub text: String,
 }
 
-impl Connection {
-    pub fn new(stream: TcpStream, main_tx: Sender<ServerCommand>) -> Self {
+impl From<GameInfo> for Vec<u8> {
+    fn from(game_info: GameInfo) -> Vec<u8> {
+        let json = serde_json::to_string(&game_info).unwrap();
+        json.as_bytes().to_vec()
+    }
+}
+
+/// Reads from a stream until a deadline, however slowly the bytes come.
+struct Deadline<'a, T: Transport> {
+    stream: &'a mut T,
+    deadline: Instant,
+}
+
+impl<T: Transport> Read for Deadline<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self
+            .deadline
+            .checked_duration_since(Instant::now())
+            .filter(|left| !left.is_zero())
+            .ok_or_else(|| io::Error::new(ErrorKind::TimedOut, "Deadline passed"))?;
+        self.stream.set_read_timeout(Some(left))?;
+        self.stream.read(buf)
+    }
+}
+
+pub struct Connection<T: Transport> {
+    stream: T,
+    main_tx: Sender<ServerCommand>,
+    wire_format: WireFormat,
+    handshake_timeout: Duration,
+}
+
+impl<T: Transport> Connection<T> {
+    pub fn new(stream: T, main_tx: Sender<ServerCommand>) -> Self {
+        let handshake_seconds = ConnectionLimits::default().handshake_seconds;
+
         Connection {
             stream,
-            main_tx
+            main_tx,
+            wire_format: WireFormat::Binary(Codec::Json),
+            handshake_timeout: Duration::from_secs(handshake_seconds),
         }
     }
 
+    pub fn with_handshake_timeout(mut self, handshake_timeout: Duration) -> Self {
+        self.handshake_timeout = handshake_timeout;
+        self
+    }
+
     pub fn start(&mut self) -> Result<(), String> {
-        let mut reader = BufReader::new(&self.stream);
+        let deadline = Instant::now() + self.handshake_timeout;
+
+        // Clients stalling before they registered are let go
+        let (user, resume) = match self.handshake(deadline) {
+            Err(_) if Instant::now() >= deadline => {
+                self.write_frame(Frame::text(ERROR, "Registration took too long"))
+                    .ok();
+                self.close_connection();
+                return Err(String::from(
+                    "Connection closed - Registration took too long",
+                ));
+            }
+            result => result?,
+        };
+
+        self.stream
+            .set_read_timeout(None)
+            .map_err(|error| format!("{}", error))?;
+        self.listen(user, resume)
+    }
+
+    /// Turns a client down without waiting on it, the reason is sent in the
+    /// binary wire format before its header comes in.
+    pub fn reject(&mut self, reason: &str) -> Result<(), String> {
+        self.write_frame(Frame::text(ERROR, reason)).ok();
+        self.close_connection();
+        Err(format!("Connection closed - {}", reason))
+    }
+
+    fn read_header(&mut self, deadline: Instant) -> Result<Vec<u8>, String> {
         let mut buffer_protocol_header = [0; 5];
-        reader.read_exact(&mut buffer_protocol_header).unwrap();
+        Deadline {
+            stream: &mut self.stream,
+            deadline,
+        }
+        .read_exact(&mut buffer_protocol_header)
+        .map_err(|error| format!("Connection closed - {}", error))?;
+
+        Ok(buffer_protocol_header.to_vec())
+    }
 
-        match ProtocolHeader::try_from(buffer_protocol_header.to_vec()) {
-            Ok(_) => self.registration(),
+    fn handshake(&mut self, deadline: Instant) -> Result<(UserInfo, bool), String> {
+        let buffer_protocol_header = self.read_header(deadline)?;
+
+        match ProtocolHeader::try_from(buffer_protocol_header) {
+            Ok(protocol_header) => {
+                self.wire_format = protocol_header.wire_format();
+                self.registration(deadline)
+            }
             Err(protocol_header) => {
-                self.reply_header(protocol_header);
+                // Rejected clients are told which header is expected, if still there
+                self.reply_header(protocol_header).ok();
                 self.close_connection();
                 Err(String::from("Connection closed - Invalid header"))
             }
         }
     }
 
-    pub fn registration(&mut self) -> Result<(), String> {
+    fn registration(&mut self, deadline: Instant) -> Result<(UserInfo, bool), String> {
         let constraints = ConnectionConstraints::default();
         let constraints = Vec::<u8>::from(constraints);
-        
+
         let mut registration_payload = Vec::new();
 
         let bytes_length = constraints.len() as u32;
 
-        registration_payload.write_u32::<NetworkEndian>(bytes_length).unwrap();
+        registration_payload
+            .write_u32::<NetworkEndian>(bytes_length)
+            .unwrap();
         registration_payload.extend(constraints);
 
         let frame = Frame::new(1, 1, registration_payload);
-        let frame = &Vec::<u8>::from(frame);
 
-        match self.stream.write(frame) {
-            Ok(_) => self.registration_ok(),
-            Err(error) => Err(format!("{}", error))
+        self.write_frame(frame)?;
+        self.registration_ok(deadline)
+    }
+
+    fn registration_ok(&mut self, deadline: Instant) -> Result<(UserInfo, bool), String> {
+        let mut frame = self.read_handshake_frame(deadline)?;
+
+        // Clients wanting another payload codec say so before registering
+        if frame.method() == REGISTRATION_CODEC {
+            if let Err(error) = self.choose_codec(&frame.text_payload()?) {
+                self.write_frame(Frame::text(ERROR, &error)).ok();
+                self.close_connection();
+                return Err(format!("Connection closed - {}", error));
+            }
+            frame = self.read_handshake_frame(deadline)?;
+        }
+
+        // Players from before a server restart come back with their user
+        let (user, resume) = match frame.method() {
+            REGISTRATION_OK => {
+                let user = UserInfo {
+                    id: Uuid::new_v4().to_string(),
+                    name: frame.text_payload()?,
+                    stats: None,
+                    token: None,
+                };
+                (user, false)
+            }
+            REGISTRATION_RESUME => (frame.json_payload::<UserInfo>()?, true),
+            _ => {
+                self.close_connection();
+                return Err(String::from("Connection closed - Registration.Ok expected"));
+            }
+        };
+
+        let max_name_size = ConnectionConstraints::default().max_name_size as usize;
+
+        if user.name.is_empty() || user.name.len() > max_name_size {
+            let error = format!("Name must have 1 to {} bytes", max_name_size);
+            self.write_frame(Frame::text(ERROR, &error)).ok();
+            self.close_connection();
+            return Err(String::from("Connection closed - Invalid name"));
+        }
+
+        // The server answers with the User frame, it knows the player stats
+        Ok((user, resume))
+    }
+
+    fn read_handshake_frame(&mut self, deadline: Instant) -> Result<Frame, String> {
+        self.wire_format.read_frame(&mut Deadline {
+            stream: &mut self.stream,
+            deadline,
+        })
+    }
+
+    fn choose_codec(&mut self, name: &str) -> Result<(), String> {
+        let codec = Codec::try_from(name)?;
+
+        match self.wire_format {
+            WireFormat::Binary(_) => {
+                self.wire_format = WireFormat::Binary(codec);
+                Ok(())
+            }
+            WireFormat::Text if codec == Codec::Json => Ok(()),
+            WireFormat::Text => Err(String::from("Text mode only carries Json")),
         }
     }
 
-    pub fn registration_ok(&mut self) -> Result<(), String> {
+    /// Frames sent by the server are written by a dedicated thread, this one
+    /// keeps reading frames from the client until it goes away.
+    fn listen(&mut self, user: UserInfo, resume: bool) -> Result<(), String> {
+        let (outbox, inbox) = channel::<Frame>();
+        let (written_tx, written_rx) = channel::<()>();
+        let mut writer = self
+            .stream
+            .try_clone()
+            .map_err(|error| format!("{}", error))?;
+
+        let wire_format = self.wire_format;
+
+        thread::spawn(move || {
+            for frame in inbox {
+                let bytes = match wire_format.encode(frame) {
+                    Ok(bytes) => bytes,
+                    Err(error) => {
+                        println!("Frame not sent: {}", error);
+                        continue;
+                    }
+                };
+                if writer.write_all(&bytes).is_err() {
+                    break;
+                }
+            }
+            written_tx.send(()).ok();
+        });
+
+        // Throttle errors go out with the frames from the server
+        let replies = outbox.clone();
+        let player_id = user.id.clone();
+        let (reply_tx, reply_rx) = channel::<Result<(), String>>();
+        let server_command = if resume {
+            ServerCommand::PlayerResumed(user, outbox, reply_tx)
+        } else {
+            ServerCommand::PlayerRegistered(user, outbox, reply_tx)
+        };
+        self.main_tx
+            .send(server_command)
+            .map_err(|error| format!("{}", error))?;
+
+        // Names already taken and sessions not resumed end the connection
+        if let Err(error) = reply_rx.recv().map_err(|error| format!("{}", error))? {
+            self.write_frame(Frame::text(ERROR, &error)).ok();
+            self.close_connection();
+            return Err(format!("Connection closed - {}", error));
+        }
+
+        let constraints = ConnectionConstraints::default();
+        let mut throttle = Throttle::new(&constraints.frame_limits, Instant::now());
+        let mut throttled = 0;
+
         loop {
-            let mut reader = BufReader::new(&self.stream);
-            let mut frame = Vec::new();
-            reader.read_until(FRAME_END, &mut frame).unwrap();
+            match self.wire_format.read_frame(&mut self.stream) {
+                Ok(frame) => {
+                    let (class, _) = frame.method();
+                    if throttle.allow(class, Instant::now()) {
+                        throttled = 0;
+                        if self
+                            .main_tx
+                            .send(ServerCommand::PlayerFrame(player_id.clone(), frame))
+                            .is_err()
+                        {
+                            return Err(String::from("Server is gone"));
+                        }
+                        continue;
+                    }
+
+                    throttled += 1;
+                    if throttled <= constraints.max_throttled_frames {
+                        replies
+                            .send(Frame::text(ERROR, "Too many frames, slow down"))
+                            .ok();
+                        continue;
+                    }
+
+                    // The writer ends once the server dropped the player, with the error sent
+                    replies
+                        .send(Frame::text(ERROR, "Too many frames, disconnected"))
+                        .ok();
+                    drop(replies);
+                    self.main_tx
+                        .send(ServerCommand::PlayerDisconnected(player_id))
+                        .ok();
+                    written_rx.recv_timeout(Duration::from_secs(1)).ok();
+                    self.close_connection();
+                    return Err(String::from("Connection closed - Too many frames"));
+                }
+                Err(error) => {
+                    self.main_tx
+                        .send(ServerCommand::PlayerDisconnected(player_id))
+                        .ok();
+                    self.close_connection();
+                    return Err(error);
+                }
+            }
         }
     }
 
-    fn reply_header(&mut self, protocol_header: String) {
-        self.stream.write(protocol_header.as_bytes()).unwrap();
+    fn write_frame(&mut self, frame: Frame) -> Result<(), String> {
+        let bytes = self.wire_format.encode(frame)?;
+        self.stream
+            .write_all(&bytes)
+            .map_err(|error| format!("{}", error))
+    }
+
+    fn reply_header(&mut self, protocol_header: String) -> Result<(), String> {
+        self.stream
+            .write_all(protocol_header.as_bytes())
+            .map_err(|error| format!("{}", error))
     }
 
+    /// The client may be gone already, closing never fails.
     fn close_connection(&mut self) {
-        self.stream.flush().unwrap();
-        self.stream.shutdown(Shutdown::Both).unwrap();
+        self.stream.flush().ok();
+        self.stream.close().ok();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::ProtocolHeader;
+    use super::{
+        Connection, ConnectionConstraints, Frame, GameInfo, ProtocolHeader, UserInfo, WireFormat,
+        CHAT_LOBBY, ERROR, FRAME_TOO_LARGE, GAME_CREATE, GAME_LIST, GUESS, MAX_PAYLOAD_SIZE,
+        REGISTRATION, REGISTRATION_CODEC, REGISTRATION_OK, USER,
+    };
+    use crate::codec::Codec;
+    use crate::game::{Game, GameRules, HintStyle, Player};
+    use crate::messages::ServerCommand;
+    use crate::transport::{duplex, Duplex, Transport};
     use std::convert::TryFrom;
+    use std::io::{self, Read, Write};
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    fn start_connection() -> (
+        Duplex,
+        Receiver<ServerCommand>,
+        JoinHandle<Result<(), String>>,
+    ) {
+        let (client, server) = duplex();
+        let (main_tx, main_rx) = channel();
+        let connection = thread::spawn(move || Connection::new(server, main_tx).start());
+
+        (client, main_rx, connection)
+    }
+
+    fn send(client: &mut Duplex, frame: Frame) {
+        client.write_all(&Vec::<u8>::from(frame)).unwrap();
+    }
 
     #[test]
     fn test_protocol_header_try_from() {
@@ -205,4 +935,473 @@ mod tests {
             protocol_header.unwrap_err()
         );
     }
+
+    #[test]
+    fn test_protocol_header_text() {
+        let protocol_header = ProtocolHeader::try_from(b"GGTXT".to_vec()).unwrap();
+
+        assert_eq!(WireFormat::Text, protocol_header.wire_format());
+        assert_eq!(
+            WireFormat::Binary(Codec::Json),
+            ProtocolHeader::default().wire_format()
+        );
+    }
+
+    #[test]
+    fn test_frame_text() {
+        let frames = vec![
+            Frame::empty(GAME_LIST),
+            Frame::number(GUESS, 42),
+            Frame::text(CHAT_LOBBY, "Hello"),
+        ];
+
+        for frame in frames {
+            assert_eq!(
+                Ok(frame.clone()),
+                Frame::from_text(&frame.to_text().unwrap())
+            );
+        }
+
+        // Json payloads keep their value, not their key order
+        let frame = Frame::json(GAME_CREATE, &GameRules::default());
+        let decoded = Frame::from_text(&frame.to_text().unwrap()).unwrap();
+        assert_eq!(GAME_CREATE, decoded.method());
+        assert_eq!(
+            Ok(GameRules::default()),
+            decoded.json_payload::<GameRules>()
+        );
+        assert_eq!(
+            Ok(Frame::number(GUESS, 42)),
+            Frame::from_text(r#"{"class":"game","method":"guess","n":42}"#)
+        );
+        assert_eq!(
+            Ok(String::from(
+                r#"{"class":"chat","method":"lobby","text":"Hello"}"#
+            )),
+            Frame::text(CHAT_LOBBY, "Hello").to_text()
+        );
+    }
+
+    #[test]
+    fn test_frame_text_error() {
+        assert_eq!(
+            Err(String::from("Unknown method game.fold")),
+            Frame::from_text(r#"{"class":"game","method":"fold"}"#)
+        );
+        assert_eq!(
+            Err(String::from("Missing n")),
+            Frame::from_text(r#"{"class":"game","method":"guess","text":"42"}"#)
+        );
+        assert!(Frame::from_text("guess 42").is_err());
+
+        let line = format!(
+            r#"{{"class":"chat","method":"lobby","text":"{}"}}"#,
+            "a".repeat(256)
+        );
+        assert_eq!(
+            Err(String::from("Text too long, 255 bytes at most")),
+            Frame::from_text(&line)
+        );
+    }
+
+    #[test]
+    fn test_frame_text_cut_on_char_boundary() {
+        let frame = Frame::text(CHAT_LOBBY, &"é".repeat(200));
+
+        assert_eq!(Ok("é".repeat(127)), frame.text_payload());
+    }
+
+    #[test]
+    fn test_frame_try_from() {
+        let rules = GameRules::default();
+        let frame = Frame::json(GAME_CREATE, &rules);
+
+        let decoded = Frame::try_from(Vec::<u8>::from(frame.clone())).unwrap();
+
+        assert_eq!(frame, decoded);
+        assert_eq!(rules, decoded.json_payload::<GameRules>().unwrap());
+    }
+
+    #[test]
+    fn test_frame_try_from_frame_end_in_payload() {
+        let frame = Frame::number(GUESS, 4);
+
+        let decoded = Frame::try_from(Vec::<u8>::from(frame)).unwrap();
+
+        assert_eq!(Ok(4), decoded.number_payload());
+    }
+
+    #[test]
+    fn test_frame_try_from_text() {
+        let frame = Frame::text(ERROR, "This game is full");
+
+        let decoded = Frame::try_from(Vec::<u8>::from(frame)).unwrap();
+
+        assert_eq!(
+            Ok(String::from("This game is full")),
+            decoded.text_payload()
+        );
+    }
+
+    #[test]
+    fn test_frame_try_from_error() {
+        let mut bytes = Vec::<u8>::from(Frame::empty(GAME_LIST));
+        bytes.pop();
+
+        assert_eq!(Err(String::from("Malformed frame")), Frame::try_from(bytes));
+        assert_eq!(
+            Err(String::from("Unknown method 9.9")),
+            Frame::try_from(vec![9, 9, super::FRAME_END])
+        );
+    }
+
+    #[test]
+    fn test_frame_try_from_truncated_payload() {
+        let mut bytes = Vec::<u8>::from(Frame::json(REGISTRATION, &GameRules::default()));
+        bytes.truncate(10);
+
+        assert_eq!(Err(String::from("Malformed frame")), Frame::try_from(bytes));
+    }
+
+    #[test]
+    fn test_frame_too_large() {
+        let (class, method) = GAME_CREATE;
+        let size = (MAX_PAYLOAD_SIZE as u32 + 1).to_be_bytes();
+        let header = [&[class, method][..], &size[..]].concat();
+
+        // Refused before reading the payload, the sender could go on for ever
+        let mut reader = header.chain(io::repeat(0));
+        assert_eq!(
+            Err(String::from(FRAME_TOO_LARGE)),
+            Frame::read_from(&mut reader)
+        );
+    }
+
+    #[test]
+    fn test_frame_transcode() {
+        let rules = GameRules {
+            lives: 5,
+            ..GameRules::default()
+        };
+        let frame = Frame::json(GAME_CREATE, &rules);
+
+        let encoded = frame
+            .clone()
+            .transcode(Codec::Json, Codec::Bincode)
+            .unwrap();
+        assert_ne!(frame, encoded);
+        assert_eq!(Ok(frame), encoded.transcode(Codec::Bincode, Codec::Json));
+        assert_eq!(
+            Ok(Frame::number(GUESS, 4)),
+            Frame::number(GUESS, 4).transcode(Codec::Json, Codec::MessagePack)
+        );
+    }
+
+    #[test]
+    fn test_game_info_carries_rules() {
+        let player = Player::new(String::from("Chico"));
+        let rules = GameRules {
+            min: 10,
+            max: 20,
+            lives: 5,
+            hint_style: HintStyle::HotCold,
+            ..GameRules::default()
+        };
+        let game = Game::new(&player, rules.clone()).unwrap();
+
+        let payload = Vec::<u8>::from(GameInfo::from(&game));
+        let game_info: GameInfo = serde_json::from_slice(&payload).unwrap();
+
+        assert_eq!(game.id(), game_info.id);
+        assert_eq!(rules, game_info.rules);
+        assert_eq!(1, game_info.players.len());
+        assert_eq!("Chico", game_info.players[0].name);
+        assert_eq!(5, game_info.players[0].life);
+        assert_eq!(None, game_info.players[0].secret_number);
+    }
+
+    #[test]
+    fn test_connection_invalid_header() {
+        let (mut client, _main_rx, connection) = start_connection();
+        client.write_all(b"GG011").unwrap();
+
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).unwrap();
+        assert_eq!(b"GG010".to_vec(), reply);
+        assert_eq!(
+            Err(String::from("Connection closed - Invalid header")),
+            connection.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_connection_registration() {
+        let (mut client, main_rx, connection) = start_connection();
+        client.write_all(b"GG010").unwrap();
+
+        let registration = Frame::read_from(&mut client).unwrap();
+        assert_eq!(REGISTRATION, registration.method());
+        let constraints: ConnectionConstraints = registration.json_payload().unwrap();
+        assert_eq!(10, constraints.max_name_size);
+
+        send(&mut client, Frame::text(REGISTRATION_OK, "Chico"));
+        let user = match main_rx.recv().unwrap() {
+            ServerCommand::PlayerRegistered(user, _, reply) => {
+                reply.send(Ok(())).unwrap();
+                user
+            }
+            _ => panic!("Player registration expected"),
+        };
+        assert_eq!("Chico", user.name);
+
+        send(&mut client, Frame::empty(GAME_LIST));
+        match main_rx.recv().unwrap() {
+            ServerCommand::PlayerFrame(player_id, frame) => {
+                assert_eq!(user.id, player_id);
+                assert_eq!(Frame::empty(GAME_LIST), frame);
+            }
+            _ => panic!("Player frame expected"),
+        }
+
+        client.close().unwrap();
+        match main_rx.recv().unwrap() {
+            ServerCommand::PlayerDisconnected(player_id) => assert_eq!(user.id, player_id),
+            _ => panic!("Player disconnection expected"),
+        }
+        assert!(connection.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_connection_text_mode() {
+        let (mut client, main_rx, connection) = start_connection();
+        client.write_all(b"GGTXT\n").unwrap();
+
+        let registration = Frame::read_line_from(&mut client).unwrap();
+        assert_eq!(REGISTRATION, registration.method());
+
+        client
+            .write_all(
+                b"{\"class\":\"connection\",\"method\":\"registration.ok\",\"text\":\"Chico\"}\r\n",
+            )
+            .unwrap();
+        let outbox = match main_rx.recv().unwrap() {
+            ServerCommand::PlayerRegistered(user, outbox, reply) => {
+                assert_eq!("Chico", user.name);
+                reply.send(Ok(())).unwrap();
+                outbox
+            }
+            _ => panic!("Player registration expected"),
+        };
+
+        client
+            .write_all(b"\n{\"class\":\"game\",\"method\":\"guess\",\"n\":42}\n")
+            .unwrap();
+        match main_rx.recv().unwrap() {
+            ServerCommand::PlayerFrame(_, frame) => assert_eq!(Frame::number(GUESS, 42), frame),
+            _ => panic!("Player frame expected"),
+        }
+
+        outbox.send(Frame::text(ERROR, "Not your turn")).unwrap();
+        assert_eq!(
+            Frame::text(ERROR, "Not your turn"),
+            Frame::read_line_from(&mut client).unwrap()
+        );
+
+        client.close().unwrap();
+        assert!(connection.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_connection_codec() {
+        let (mut client, main_rx, connection) = start_connection();
+        client.write_all(b"GG010").unwrap();
+        let registration = Frame::read_from(&mut client).unwrap();
+        let constraints: ConnectionConstraints = registration.json_payload().unwrap();
+        assert!(constraints.codecs.contains(&Codec::MessagePack));
+
+        send(&mut client, Frame::text(REGISTRATION_CODEC, "MessagePack"));
+        send(&mut client, Frame::text(REGISTRATION_OK, "Chico"));
+        let (user, outbox) = match main_rx.recv().unwrap() {
+            ServerCommand::PlayerRegistered(user, outbox, reply) => {
+                reply.send(Ok(())).unwrap();
+                (user, outbox)
+            }
+            _ => panic!("Player registration expected"),
+        };
+
+        outbox.send(Frame::json(USER, &user)).unwrap();
+        let frame = Frame::read_from(&mut client).unwrap();
+        assert_eq!(
+            Ok(Frame::json(USER, &user)),
+            frame.clone().transcode(Codec::MessagePack, Codec::Json)
+        );
+        assert!(
+            frame.json_payload::<UserInfo>().is_err(),
+            "Payload must not be Json"
+        );
+
+        let rules = Frame::json(GAME_CREATE, &GameRules::default());
+        send(
+            &mut client,
+            rules
+                .clone()
+                .transcode(Codec::Json, Codec::MessagePack)
+                .unwrap(),
+        );
+        match main_rx.recv().unwrap() {
+            ServerCommand::PlayerFrame(_, frame) => assert_eq!(rules, frame),
+            _ => panic!("Player frame expected"),
+        }
+
+        client.close().unwrap();
+        assert!(connection.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_connection_unknown_codec() {
+        let (mut client, _main_rx, connection) = start_connection();
+        client.write_all(b"GG010").unwrap();
+        Frame::read_from(&mut client).unwrap();
+
+        send(&mut client, Frame::text(REGISTRATION_CODEC, "Protobuf"));
+        assert_eq!(
+            Frame::text(ERROR, "Unknown codec Protobuf"),
+            Frame::read_from(&mut client).unwrap()
+        );
+        assert_eq!(
+            Err(String::from("Connection closed - Unknown codec Protobuf")),
+            connection.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_connection_disconnects_during_handshake() {
+        let codec = Vec::<u8>::from(Frame::text(REGISTRATION_CODEC, "Bincode"));
+        let registration_ok = Vec::<u8>::from(Frame::text(REGISTRATION_OK, "Chico"));
+        let steps = vec![
+            b"GG0".to_vec(),
+            // Nobody reads the expected header sent back
+            b"GG100".to_vec(),
+            b"GG010".to_vec(),
+            [&b"GG010"[..], &codec].concat(),
+            [&b"GG010"[..], &registration_ok[..4]].concat(),
+            [&b"GG010"[..], &codec, &registration_ok].concat(),
+        ];
+
+        for bytes in steps {
+            let (mut client, _main_rx, connection) = start_connection();
+            client.write_all(&bytes).unwrap();
+            client.close().unwrap();
+            assert!(connection.join().unwrap().is_err());
+        }
+    }
+
+    #[test]
+    fn test_connection_invalid_name() {
+        let (mut client, main_rx, connection) = start_connection();
+        client.write_all(b"GG010").unwrap();
+        Frame::read_from(&mut client).unwrap();
+
+        send(&mut client, Frame::text(REGISTRATION_OK, "Constantine"));
+        assert_eq!(
+            Frame::text(ERROR, "Name must have 1 to 10 bytes"),
+            Frame::read_from(&mut client).unwrap()
+        );
+        assert_eq!(
+            Err(String::from("Connection closed - Invalid name")),
+            connection.join().unwrap()
+        );
+        assert!(main_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_connection_handshake_timeout() {
+        let (mut client, server) = duplex();
+        let (main_tx, main_rx) = channel();
+        let connection = thread::spawn(move || {
+            Connection::new(server, main_tx)
+                .with_handshake_timeout(Duration::from_millis(100))
+                .start()
+        });
+
+        // Half a frame keeps the connection reading, not past the deadline
+        client.write_all(b"GG010").unwrap();
+        Frame::read_from(&mut client).unwrap();
+        let registration_ok = Vec::<u8>::from(Frame::text(REGISTRATION_OK, "Chico"));
+        client.write_all(&registration_ok[..4]).unwrap();
+
+        assert_eq!(
+            Frame::text(ERROR, "Registration took too long"),
+            Frame::read_from(&mut client).unwrap()
+        );
+        assert_eq!(
+            Err(String::from(
+                "Connection closed - Registration took too long"
+            )),
+            connection.join().unwrap()
+        );
+        assert!(main_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_connection_reject() {
+        let (mut client, server) = duplex();
+        let (main_tx, main_rx) = channel();
+
+        // The reason is written without the client sending anything
+        assert_eq!(
+            Err(String::from("Connection closed - Server is full")),
+            Connection::new(server, main_tx).reject("Server is full")
+        );
+        assert_eq!(
+            Frame::text(ERROR, "Server is full"),
+            Frame::read_from(&mut client).unwrap()
+        );
+        assert!(main_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_connection_throttled() {
+        let (mut client, main_rx, connection) = start_connection();
+        client.write_all(b"GG010").unwrap();
+        Frame::read_from(&mut client).unwrap();
+        send(&mut client, Frame::text(REGISTRATION_OK, "Chico"));
+        let outbox = match main_rx.recv().unwrap() {
+            ServerCommand::PlayerRegistered(_, outbox, reply) => {
+                reply.send(Ok(())).unwrap();
+                outbox
+            }
+            _ => panic!("Player registration expected"),
+        };
+
+        // A burst of guesses goes through, the next ones are dropped
+        for _ in 0..21 {
+            send(&mut client, Frame::number(GUESS, 42));
+        }
+        for _ in 0..10 {
+            match main_rx.recv().unwrap() {
+                ServerCommand::PlayerFrame(_, frame) => assert_eq!(Frame::number(GUESS, 42), frame),
+                _ => panic!("Player frame expected"),
+            }
+        }
+        match main_rx.recv().unwrap() {
+            ServerCommand::PlayerDisconnected(_) => drop(outbox),
+            _ => panic!("Player disconnection expected"),
+        }
+
+        for _ in 0..10 {
+            assert_eq!(
+                Frame::text(ERROR, "Too many frames, slow down"),
+                Frame::read_from(&mut client).unwrap()
+            );
+        }
+        assert_eq!(
+            Frame::text(ERROR, "Too many frames, disconnected"),
+            Frame::read_from(&mut client).unwrap()
+        );
+        assert_eq!(
+            Err(String::from("Connection closed - Too many frames")),
+            connection.join().unwrap()
+        );
+    }
 }
@@ -0,0 +1,190 @@
+// Generated from protocol.json by `UPDATE_SPEC=1 cargo test spec`, do not edit.
+
+pub const PROTOCOL_HEADER: &str = "GG010";
+pub const TEXT_HEADER: &str = "GGTXT";
+pub const FRAME_END: u8 = 0x4;
+
+pub const REGISTRATION: Method = (1, 1);
+pub const REGISTRATION_OK: Method = (1, 2);
+pub const USER: Method = (1, 3);
+pub const ERROR: Method = (1, 4);
+pub const REGISTRATION_RESUME: Method = (1, 5);
+pub const REGISTRATION_CODEC: Method = (1, 6);
+pub const HEARTBEAT: Method = (1, 7);
+pub const HEARTBEAT_OK: Method = (1, 8);
+
+pub const GAME_CREATE: Method = (2, 1);
+pub const GAME: Method = (2, 2);
+pub const GAME_LIST: Method = (2, 3);
+pub const GAMES: Method = (2, 4);
+pub const GAME_JOIN: Method = (2, 5);
+pub const GAME_AGAINST_HOUSE: Method = (2, 6);
+pub const GAME_AGAINST_BOT: Method = (2, 7);
+pub const SECRET_NUMBER: Method = (2, 8);
+pub const GUESS: Method = (2, 9);
+pub const TURN: Method = (2, 10);
+pub const WRONG_ANSWER: Method = (2, 11);
+pub const YOU_WIN: Method = (2, 12);
+pub const YOU_LOSE: Method = (2, 13);
+pub const NOT_YOUR_TURN: Method = (2, 14);
+pub const GUESS_AT: Method = (2, 15);
+pub const CRACKED: Method = (2, 16);
+pub const WATCH: Method = (2, 17);
+pub const UNWATCH: Method = (2, 18);
+pub const GAME_EVENT: Method = (2, 19);
+pub const MATCHMAKING: Method = (2, 20);
+pub const MATCHMAKING_CANCEL: Method = (2, 21);
+pub const REMATCH: Method = (2, 22);
+pub const REMATCH_OFFER: Method = (2, 23);
+pub const SERIES: Method = (2, 24);
+pub const MATCH_CREATE: Method = (2, 25);
+pub const MATCH_RESULT: Method = (2, 26);
+
+pub const CHAT_LOBBY: Method = (3, 1);
+pub const CHAT_GAME: Method = (3, 2);
+pub const CHAT_MESSAGE: Method = (3, 3);
+
+pub const LEADERBOARD: Method = (4, 1);
+pub const LEADERBOARD_ENTRIES: Method = (4, 2);
+
+pub const TOURNAMENT_CREATE: Method = (5, 1);
+pub const TOURNAMENT_LIST: Method = (5, 2);
+pub const TOURNAMENTS: Method = (5, 3);
+pub const TOURNAMENT_JOIN: Method = (5, 4);
+pub const TOURNAMENT_LEAVE: Method = (5, 5);
+pub const TOURNAMENT_STATE: Method = (5, 6);
+pub const TOURNAMENT: Method = (5, 7);
+
+fn payload_kind(method: Method) -> Result<PayloadKind, String> {
+    match method {
+        REGISTRATION => Ok(PayloadKind::LongString),
+        REGISTRATION_OK => Ok(PayloadKind::ShortString),
+        USER => Ok(PayloadKind::LongString),
+        ERROR => Ok(PayloadKind::ShortString),
+        REGISTRATION_RESUME => Ok(PayloadKind::LongString),
+        REGISTRATION_CODEC => Ok(PayloadKind::ShortString),
+        HEARTBEAT => Ok(PayloadKind::Empty),
+        HEARTBEAT_OK => Ok(PayloadKind::Empty),
+        GAME_CREATE => Ok(PayloadKind::LongString),
+        GAME => Ok(PayloadKind::LongString),
+        GAME_LIST => Ok(PayloadKind::Empty),
+        GAMES => Ok(PayloadKind::LongString),
+        GAME_JOIN => Ok(PayloadKind::ShortString),
+        GAME_AGAINST_HOUSE => Ok(PayloadKind::LongString),
+        GAME_AGAINST_BOT => Ok(PayloadKind::LongString),
+        SECRET_NUMBER => Ok(PayloadKind::Number),
+        GUESS => Ok(PayloadKind::Number),
+        TURN => Ok(PayloadKind::Empty),
+        WRONG_ANSWER => Ok(PayloadKind::ShortString),
+        YOU_WIN => Ok(PayloadKind::Empty),
+        YOU_LOSE => Ok(PayloadKind::Empty),
+        NOT_YOUR_TURN => Ok(PayloadKind::Empty),
+        GUESS_AT => Ok(PayloadKind::LongString),
+        CRACKED => Ok(PayloadKind::ShortString),
+        WATCH => Ok(PayloadKind::ShortString),
+        UNWATCH => Ok(PayloadKind::Empty),
+        GAME_EVENT => Ok(PayloadKind::LongString),
+        MATCHMAKING => Ok(PayloadKind::Empty),
+        MATCHMAKING_CANCEL => Ok(PayloadKind::Empty),
+        REMATCH => Ok(PayloadKind::Empty),
+        REMATCH_OFFER => Ok(PayloadKind::ShortString),
+        SERIES => Ok(PayloadKind::LongString),
+        MATCH_CREATE => Ok(PayloadKind::LongString),
+        MATCH_RESULT => Ok(PayloadKind::LongString),
+        CHAT_LOBBY => Ok(PayloadKind::ShortString),
+        CHAT_GAME => Ok(PayloadKind::ShortString),
+        CHAT_MESSAGE => Ok(PayloadKind::LongString),
+        LEADERBOARD => Ok(PayloadKind::LongString),
+        LEADERBOARD_ENTRIES => Ok(PayloadKind::LongString),
+        TOURNAMENT_CREATE => Ok(PayloadKind::LongString),
+        TOURNAMENT_LIST => Ok(PayloadKind::Empty),
+        TOURNAMENTS => Ok(PayloadKind::LongString),
+        TOURNAMENT_JOIN => Ok(PayloadKind::ShortString),
+        TOURNAMENT_LEAVE => Ok(PayloadKind::Empty),
+        TOURNAMENT_STATE => Ok(PayloadKind::ShortString),
+        TOURNAMENT => Ok(PayloadKind::LongString),
+        (class, method) => Err(format!("Unknown method {}.{}", class, method)),
+    }
+}
+
+/// Class and method names of the text format.
+const METHOD_NAMES: &[(Method, &str, &str)] = &[
+    (REGISTRATION, "connection", "registration"),
+    (REGISTRATION_OK, "connection", "registration.ok"),
+    (USER, "connection", "user"),
+    (ERROR, "connection", "error"),
+    (REGISTRATION_RESUME, "connection", "registration.resume"),
+    (REGISTRATION_CODEC, "connection", "registration.codec"),
+    (HEARTBEAT, "connection", "heartbeat"),
+    (HEARTBEAT_OK, "connection", "heartbeat.ok"),
+    (GAME_CREATE, "game", "create"),
+    (GAME, "game", "game"),
+    (GAME_LIST, "game", "list"),
+    (GAMES, "game", "games"),
+    (GAME_JOIN, "game", "join"),
+    (GAME_AGAINST_HOUSE, "game", "create.house"),
+    (GAME_AGAINST_BOT, "game", "create.bot"),
+    (SECRET_NUMBER, "game", "secret_number"),
+    (GUESS, "game", "guess"),
+    (TURN, "game", "turn"),
+    (WRONG_ANSWER, "game", "wrong_answer"),
+    (YOU_WIN, "game", "you_win"),
+    (YOU_LOSE, "game", "you_lose"),
+    (NOT_YOUR_TURN, "game", "not_your_turn"),
+    (GUESS_AT, "game", "guess_at"),
+    (CRACKED, "game", "cracked"),
+    (WATCH, "game", "watch"),
+    (UNWATCH, "game", "unwatch"),
+    (GAME_EVENT, "game", "game_event"),
+    (MATCHMAKING, "game", "matchmaking"),
+    (MATCHMAKING_CANCEL, "game", "matchmaking.cancel"),
+    (REMATCH, "game", "rematch"),
+    (REMATCH_OFFER, "game", "rematch.offer"),
+    (SERIES, "game", "series"),
+    (MATCH_CREATE, "game", "match.create"),
+    (MATCH_RESULT, "game", "match.result"),
+    (CHAT_LOBBY, "chat", "lobby"),
+    (CHAT_GAME, "chat", "game"),
+    (CHAT_MESSAGE, "chat", "message"),
+    (LEADERBOARD, "stats", "leaderboard"),
+    (LEADERBOARD_ENTRIES, "stats", "leaderboard.entries"),
+    (TOURNAMENT_CREATE, "tournament", "create"),
+    (TOURNAMENT_LIST, "tournament", "list"),
+    (TOURNAMENTS, "tournament", "tournaments"),
+    (TOURNAMENT_JOIN, "tournament", "join"),
+    (TOURNAMENT_LEAVE, "tournament", "leave"),
+    (TOURNAMENT_STATE, "tournament", "state"),
+    (TOURNAMENT, "tournament", "tournament"),
+];
+
+/// Encodes a payload with another codec, through the type its method carries,
+/// not every codec can be read without knowing it.
+fn transcode_payload(
+    method: Method,
+    content: &[u8],
+    from: Codec,
+    to: Codec,
+) -> Result<Vec<u8>, String> {
+    match method {
+        REGISTRATION => convert::<ConnectionConstraints>(content, from, to),
+        USER => convert::<UserInfo>(content, from, to),
+        REGISTRATION_RESUME => convert::<UserInfo>(content, from, to),
+        GAME_CREATE => convert::<GameRules>(content, from, to),
+        GAME => convert::<GameInfo>(content, from, to),
+        GAMES => convert::<Vec<GameInfo>>(content, from, to),
+        GAME_AGAINST_HOUSE => convert::<GameRules>(content, from, to),
+        GAME_AGAINST_BOT => convert::<BotChallenge>(content, from, to),
+        GUESS_AT => convert::<Guess>(content, from, to),
+        GAME_EVENT => convert::<GameEvent>(content, from, to),
+        SERIES => convert::<SeriesInfo>(content, from, to),
+        MATCH_CREATE => convert::<MatchRules>(content, from, to),
+        MATCH_RESULT => convert::<MatchResult>(content, from, to),
+        CHAT_MESSAGE => convert::<ChatMessage>(content, from, to),
+        LEADERBOARD => convert::<Period>(content, from, to),
+        LEADERBOARD_ENTRIES => convert::<Vec<LeaderboardEntry>>(content, from, to),
+        TOURNAMENT_CREATE => convert::<TournamentRules>(content, from, to),
+        TOURNAMENTS => convert::<Vec<Tournament>>(content, from, to),
+        TOURNAMENT => convert::<Tournament>(content, from, to),
+        (class, method) => Err(format!("Unknown method {}.{}", class, method)),
+    }
+}
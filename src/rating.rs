@@ -0,0 +1,157 @@
+use crate::history::GameRecord;
+use std::collections::HashMap;
+
+pub const INITIAL_RATING: f64 = 1500.0;
+const K_FACTOR: f64 = 32.0;
+
+/// Elo rating of every player that finished a game, by player id, players
+/// keep it with their account.
+#[derive(Default)]
+pub struct Ratings {
+    ratings: HashMap<String, f64>,
+}
+
+/// Chances, from 0 to 1, of a player beating the opponent.
+pub fn expected_score(rating: f64, opponent_rating: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent_rating - rating) / 400.0))
+}
+
+impl Ratings {
+    pub fn from_records(records: &[GameRecord]) -> Ratings {
+        let mut ratings = Ratings::default();
+        for record in records {
+            ratings.record(record);
+        }
+        ratings
+    }
+
+    pub fn rating(&self, player_id: &str) -> f64 {
+        self.ratings
+            .get(player_id)
+            .copied()
+            .unwrap_or(INITIAL_RATING)
+    }
+
+    /// The winner beat every other player, games nobody won or played
+    /// against a bot change nothing.
+    pub fn record(&mut self, record: &GameRecord) {
+        if record.has_bot() {
+            return;
+        }
+        let winner = match record
+            .ranked_players()
+            .find(|player| Some(&player.id) == record.winner.as_ref())
+        {
+            Some(winner) => winner.id.clone(),
+            None => return,
+        };
+        let losers: Vec<String> = record
+            .ranked_players()
+            .filter(|player| player.id != winner)
+            .map(|player| player.id.clone())
+            .collect();
+
+        let winner_rating = self.rating(&winner);
+        let mut winner_change = 0.0;
+
+        for loser in losers {
+            let loser_rating = self.rating(&loser);
+            let change = K_FACTOR * (1.0 - expected_score(winner_rating, loser_rating));
+
+            winner_change += change;
+            self.ratings.insert(loser, loser_rating - change);
+        }
+        self.ratings.insert(winner, winner_rating + winner_change);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Game, GameRules, Player};
+    use crate::history::GameRecord;
+    use crate::rating::{expected_score, Ratings, INITIAL_RATING};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn record(winner: &str, loser: &str) -> GameRecord {
+        let winner = Player::with_id(winner.to_lowercase(), String::from(winner));
+        let loser = Player::with_id(loser.to_lowercase(), String::from(loser));
+        let mut game = Game::new(&winner, GameRules::default()).unwrap();
+        game.accept_challenge(&loser).unwrap();
+        game.set_secret_number(&winner, 43).unwrap();
+        game.set_secret_number(&loser, 42).unwrap();
+        game.set_ready().unwrap();
+        game.start().unwrap();
+        game.guess_number(&winner, 42).unwrap();
+        GameRecord::from(&game)
+    }
+
+    #[test]
+    fn test_expected_score() {
+        assert_eq!(0.5, expected_score(1500.0, 1500.0));
+        assert!(expected_score(1900.0, 1500.0) > 0.9);
+        assert!(expected_score(1500.0, 1900.0) < 0.1);
+    }
+
+    #[test]
+    fn test_ratings() {
+        let mut ratings = Ratings::from_records(&[record("Chico", "Paloma")]);
+
+        assert_eq!(INITIAL_RATING + 16.0, ratings.rating("chico"));
+        assert_eq!(INITIAL_RATING - 16.0, ratings.rating("paloma"));
+        assert_eq!(INITIAL_RATING, ratings.rating("allan"));
+
+        ratings.record(&record("Paloma", "Chico"));
+        assert!(ratings.rating("paloma") > INITIAL_RATING);
+        assert!(ratings.rating("chico") < INITIAL_RATING);
+    }
+
+    #[test]
+    fn test_ratings_follow_the_player_id() {
+        let mut ratings = Ratings::from_records(&[record("Chico", "Paloma")]);
+
+        // Losing on purpose under the name of another player only costs the impostor
+        let paloma = Player::with_id(String::from("paloma"), String::from("Paloma"));
+        let impostor = Player::with_id(String::from("impostor"), String::from("Chico"));
+        let mut game = Game::new(&paloma, GameRules::default()).unwrap();
+        game.accept_challenge(&impostor).unwrap();
+        game.set_secret_number(&paloma, 43).unwrap();
+        game.set_secret_number(&impostor, 42).unwrap();
+        game.set_ready().unwrap();
+        game.start().unwrap();
+        game.guess_number(&paloma, 42).unwrap();
+        ratings.record(&GameRecord::from(&game));
+
+        assert_eq!(INITIAL_RATING + 16.0, ratings.rating("chico"));
+        assert!(ratings.rating("impostor") < INITIAL_RATING);
+    }
+
+    #[test]
+    fn test_bot_games_are_not_rated() {
+        let chico = Player::with_id(String::from("chico"), String::from("Chico"));
+        let bot = Player::with_id(String::from("bot:Weak:1"), String::from("Weak Bot"));
+        let mut game = Game::new(&chico, GameRules::default()).unwrap();
+        game.accept_challenge(&bot).unwrap();
+        game.set_secret_number(&chico, 43).unwrap();
+        game.set_secret_number(&bot, 42).unwrap();
+        game.set_ready().unwrap();
+        game.start().unwrap();
+        game.guess_number(&chico, 42).unwrap();
+
+        let ratings = Ratings::from_records(&[GameRecord::from(&game)]);
+        assert_eq!(INITIAL_RATING, ratings.rating("chico"));
+        assert_eq!(INITIAL_RATING, ratings.rating("bot:Weak:1"));
+    }
+
+    #[test]
+    fn test_house_games_are_not_rated() {
+        let player = Player::with_id(String::from("chico"), String::from("Chico"));
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut game = Game::against_house(&player, GameRules::default(), &mut rng).unwrap();
+        game.start().unwrap();
+        game.leave(&player).unwrap();
+
+        let ratings = Ratings::from_records(&[GameRecord::from(&game)]);
+        assert_eq!(INITIAL_RATING, ratings.rating("chico"));
+    }
+}
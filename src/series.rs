@@ -0,0 +1,275 @@
+use crate::game::{Game, GameRules};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeriesScore {
+    pub id: String,
+    pub name: String,
+    pub wins: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SeriesInfo {
+    pub id: String,
+    pub games: u32,
+    #[serde(default)]
+    pub best_of: Option<u8>,
+    pub scores: Vec<SeriesScore>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MatchRules {
+    pub best_of: u8,
+    #[serde(default)]
+    pub rules: GameRules,
+}
+
+impl MatchRules {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.best_of {
+            3 | 5 | 7 => self.rules.validate(),
+            _ => Err(String::from("Matches are best of 3, 5 or 7")),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MatchResult {
+    pub winner: SeriesScore,
+    pub series: SeriesInfo,
+}
+
+/// Games played one after the other by the same players, through rematches,
+/// or a best of N match.
+pub struct Series {
+    id: String,
+    players: Vec<(String, String)>,
+    rules: GameRules,
+    best_of: Option<u8>,
+    wins: HashMap<String, u32>,
+    games: u32,
+    rematch_offers: HashSet<String>,
+}
+
+impl Series {
+    pub fn new(game: &Game) -> Series {
+        Series {
+            id: Uuid::new_v4().to_string(),
+            players: game
+                .players()
+                .into_iter()
+                .map(|player| (player.id.clone(), player.name()))
+                .collect(),
+            rules: game.rules().clone(),
+            best_of: None,
+            wins: HashMap::new(),
+            games: 0,
+            rematch_offers: HashSet::new(),
+        }
+    }
+
+    pub fn best_of(game: &Game, best_of: u8) -> Series {
+        Series {
+            best_of: Some(best_of),
+            ..Series::new(game)
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn rules(&self) -> &GameRules {
+        &self.rules
+    }
+
+    pub fn player_ids(&self) -> Vec<&str> {
+        self.players.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    pub fn record(&mut self, game: &Game) {
+        self.games += 1;
+        self.rematch_offers.clear();
+
+        if let Some(winner) = game.winner() {
+            *self.wins.entry(winner.id.clone()).or_insert(0) += 1;
+        }
+    }
+
+    /// True once every player asked for the rematch.
+    pub fn offer_rematch(&mut self, player_id: &str) -> Result<bool, String> {
+        if !self.players.iter().any(|(id, _)| id == player_id) {
+            return Err(String::from("Player is not part of this series"));
+        }
+
+        self.rematch_offers.insert(player_id.to_string());
+        Ok(self.rematch_offers.len() == self.players.len())
+    }
+
+    pub fn is_match(&self) -> bool {
+        self.best_of.is_some()
+    }
+
+    /// Match winner, games nobody won do not count. The first to win most
+    /// of the N games takes the match, when nobody did after N games the
+    /// one with most wins does, and players tied for most wins play until
+    /// one of them wins one more game.
+    pub fn winner(&self) -> Option<SeriesScore> {
+        let best_of = self.best_of? as u32;
+        let info = SeriesInfo::from(self);
+        let decided: u32 = info.scores.iter().map(|score| score.wins).sum();
+        let most_wins = info.scores.iter().map(|score| score.wins).max()?;
+        let mut leaders = info
+            .scores
+            .into_iter()
+            .filter(|score| score.wins == most_wins);
+
+        let leader = leaders.next()?;
+        let tied = leaders.next().is_some();
+
+        if most_wins > best_of / 2 || (decided >= best_of && !tied) {
+            Some(leader)
+        } else {
+            None
+        }
+    }
+
+    /// The first turn goes around the players, one game each.
+    pub fn first_player(&self) -> &str {
+        &self.players[self.games as usize % self.players.len()].0
+    }
+}
+
+impl From<&Series> for SeriesInfo {
+    fn from(series: &Series) -> Self {
+        SeriesInfo {
+            id: series.id.clone(),
+            games: series.games,
+            best_of: series.best_of,
+            scores: series
+                .players
+                .iter()
+                .map(|(id, name)| SeriesScore {
+                    id: id.clone(),
+                    name: name.clone(),
+                    wins: series.wins.get(id).copied().unwrap_or(0),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Game, GameRules, Player};
+    use crate::series::{MatchRules, Series, SeriesInfo};
+
+    fn finished_game(winner: &Player, loser: &Player) -> Game {
+        let mut game = Game::new(winner, GameRules::default()).unwrap();
+        game.accept_challenge(loser).unwrap();
+        game.set_secret_number(winner, 43).unwrap();
+        game.set_secret_number(loser, 42).unwrap();
+        game.set_ready().unwrap();
+        game.start().unwrap();
+        game.guess_number(winner, 42).unwrap();
+        game
+    }
+
+    #[test]
+    fn test_series() {
+        let chico = Player::new(String::from("Chico"));
+        let paloma = Player::new(String::from("Paloma"));
+        let game = finished_game(&chico, &paloma);
+        let mut series = Series::new(&game);
+        series.record(&game);
+
+        assert_eq!(paloma.id, series.first_player());
+        assert_eq!(Ok(false), series.offer_rematch(&paloma.id));
+        assert_eq!(Ok(false), series.offer_rematch(&paloma.id));
+        assert_eq!(Ok(true), series.offer_rematch(&chico.id));
+        assert!(series.offer_rematch("allan").is_err());
+
+        series.record(&finished_game(&chico, &paloma));
+        assert_eq!(chico.id, series.first_player());
+        assert_eq!(Ok(false), series.offer_rematch(&chico.id));
+
+        let info = SeriesInfo::from(&series);
+        assert_eq!(2, info.games);
+        assert_eq!(2, info.scores[0].wins);
+        assert_eq!(0, info.scores[1].wins);
+        assert_eq!(None, series.winner());
+    }
+
+    #[test]
+    fn test_match_rules() {
+        let match_rules = |best_of| MatchRules {
+            best_of,
+            rules: GameRules::default(),
+        };
+
+        assert_eq!(Ok(()), match_rules(5).validate());
+        assert_eq!(
+            Err(String::from("Matches are best of 3, 5 or 7")),
+            match_rules(4).validate()
+        );
+    }
+
+    #[test]
+    fn test_best_of_three() {
+        let chico = Player::new(String::from("Chico"));
+        let paloma = Player::new(String::from("Paloma"));
+        let game = finished_game(&chico, &paloma);
+        let mut series = Series::best_of(&game, 3);
+
+        series.record(&game);
+        assert_eq!(None, series.winner());
+        series.record(&finished_game(&paloma, &chico));
+        assert_eq!(None, series.winner());
+        series.record(&finished_game(&paloma, &chico));
+        assert_eq!(
+            Some(String::from("Paloma")),
+            series.winner().map(|winner| winner.name)
+        );
+    }
+
+    #[test]
+    fn test_best_of_seven_without_majority() {
+        let chico = Player::new(String::from("Chico"));
+        let paloma = Player::new(String::from("Paloma"));
+        let allan = Player::new(String::from("Allan"));
+        let three_player_game = |winner: &Player| {
+            let rules = GameRules {
+                max_players: 3,
+                ..GameRules::default()
+            };
+            let mut game = Game::new(&chico, rules).unwrap();
+            game.accept_challenge(&paloma).unwrap();
+            game.accept_challenge(&allan).unwrap();
+            for (player, secret_number) in [(&chico, 10), (&paloma, 11), (&allan, 12)] {
+                game.set_secret_number(player, secret_number).unwrap();
+            }
+            game.set_ready().unwrap();
+            game.start().unwrap();
+            for player in [&chico, &paloma, &allan] {
+                if player != winner {
+                    game.leave(player).unwrap();
+                }
+            }
+            game
+        };
+        let mut series = Series::best_of(&three_player_game(&chico), 7);
+
+        for winner in [&chico, &paloma, &allan, &chico, &paloma, &allan] {
+            series.record(&three_player_game(winner));
+        }
+        assert_eq!(None, series.winner());
+
+        series.record(&three_player_game(&chico));
+        assert_eq!(
+            Some(String::from("Chico")),
+            series.winner().map(|winner| winner.name)
+        );
+    }
+}
@@ -1,15 +1,39 @@
+use crate::accounts::{Account, Accounts};
+use crate::bot::{Bot, BotChallenge};
+use crate::chat::{Blocklist, ChatFilter, RateLimiter};
+use crate::game::{Game, GameMessages, GameMode, GameRules, GameSnapshot};
+use crate::history::{GameRecord, History};
+use crate::matchmaking::Matchmaker;
 use crate::messages::ServerCommand;
-use crate::protocol::{Connection, ProtocolHeader, FRAME_END};
+use crate::protocol::{
+    ChatChannel, ChatMessage, Connection, ConnectionConstraints, ConnectionLimits, Frame,
+    GameEvent, GameInfo, Guess, UserInfo, CHAT_GAME, CHAT_LOBBY, CHAT_MESSAGE, CRACKED, ERROR,
+    GAME, GAMES, GAME_AGAINST_BOT, GAME_AGAINST_HOUSE, GAME_CREATE, GAME_EVENT, GAME_JOIN,
+    GAME_LIST, GUESS, GUESS_AT, HEARTBEAT, HEARTBEAT_OK, LEADERBOARD, LEADERBOARD_ENTRIES,
+    MATCHMAKING, MATCHMAKING_CANCEL, MATCH_CREATE, MATCH_RESULT, NOT_YOUR_TURN, REMATCH,
+    REMATCH_OFFER, SECRET_NUMBER, SERIES, TOURNAMENT, TOURNAMENTS, TOURNAMENT_CREATE,
+    TOURNAMENT_JOIN, TOURNAMENT_LEAVE, TOURNAMENT_LIST, TOURNAMENT_STATE, TURN, UNWATCH, USER,
+    WATCH, WRONG_ANSWER, YOU_LOSE, YOU_WIN,
+};
+use crate::rating::Ratings;
+use crate::series::{MatchResult, MatchRules, Series, SeriesInfo};
+use crate::snapshot::{PlayerState, ServerState, Snapshot};
+use crate::stats::{self, Period, PlayerStats};
+use crate::tls::TlsStream;
+use crate::tournament::{Tournament, TournamentRules, TournamentState};
+use crate::transport::Transport;
+use crate::websocket::WebSocketStream;
 use chrono::{DateTime, Local};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rustls::{ServerConfig, ServerConnection};
 use std::collections::HashMap;
-use std::convert::TryFrom;
-use std::io::prelude::*;
-use std::io::{BufRead, BufReader};
-use std::net::{Shutdown, TcpListener, TcpStream};
+use std::io::Write;
+use std::net::{IpAddr, Shutdown, TcpListener, TcpStream};
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::Arc;
 use std::thread;
-use std::thread::JoinHandle;
+use std::time::Duration;
 use uuid::Uuid;
 
 struct Player {
@@ -20,7 +44,13 @@ struct Player {
 
 struct PlayerSession {
     player: Player,
-    thread: JoinHandle<()>,
+    token: String,
+    outbox: Sender<Frame>,
+    game_id: Option<String>,
+    watching: Option<String>,
+    series_id: Option<String>,
+    tournament_id: Option<String>,
+    chat_limiter: RateLimiter,
 }
 
 struct ServerStats {
@@ -28,13 +58,122 @@ struct ServerStats {
     total_game_sessions: u32,
 }
 
-struct GameSession {}
+struct GameSession {
+    game: Game,
+    bot: Option<Bot>,
+    spectators: Vec<String>,
+    reported_guesses: usize,
+    series_id: Option<String>,
+    best_of: Option<u8>,
+    tournament_id: Option<String>,
+    /// Restored games wait for their players until then, the turn clock
+    /// stands still meanwhile.
+    resume_until: Option<DateTime<Local>>,
+}
+
+impl GameSession {
+    fn new(game: Game, bot: Option<Bot>) -> GameSession {
+        GameSession {
+            game,
+            bot,
+            spectators: Vec::new(),
+            reported_guesses: 0,
+            series_id: None,
+            best_of: None,
+            tournament_id: None,
+            resume_until: None,
+        }
+    }
+}
+
+const MAX_SPECTATORS: usize = 10;
+const RESUME_GRACE_SECONDS: i64 = 120;
 
 struct GameMessage {}
 
+/// A failed accept only loses that connection, the loop ends once the
+/// command loop is gone.
+fn accept_connections<F>(listener: TcpListener, main_tx: Sender<ServerCommand>, accepted: F)
+where
+    F: Fn(TcpStream, Box<Sender<ServerCommand>>) -> ServerCommand,
+{
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                println!("Connection not accepted: {}", error);
+                continue;
+            }
+        };
+
+        if main_tx.send(accepted(stream, box main_tx.clone())).is_err() {
+            break;
+        }
+    }
+}
+
+/// Counts a connection against the limits until its thread drops it.
+struct ConnectionSlot {
+    ip: IpAddr,
+    main_tx: Sender<ServerCommand>,
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.main_tx
+            .send(ServerCommand::ConnectionClosed(self.ip))
+            .ok();
+    }
+}
+
+type Admission = Result<ConnectionSlot, String>;
+
+fn spawn_connection<T: Transport>(
+    stream: T,
+    main_tx: Sender<ServerCommand>,
+    slot: ConnectionSlot,
+    handshake_timeout: Duration,
+) {
+    thread::spawn(move || run_connection(stream, main_tx, slot, handshake_timeout));
+}
+
+/// Admitted connections hold their slot while they run.
+fn run_connection<T: Transport>(
+    stream: T,
+    main_tx: Sender<ServerCommand>,
+    _slot: ConnectionSlot,
+    handshake_timeout: Duration,
+) {
+    let mut connection = Connection::new(stream, main_tx).with_handshake_timeout(handshake_timeout);
+    match connection.start() {
+        Ok(_) => println!("Connection finished"),
+        Err(err_message) => println!("Connection error: {}", err_message),
+    }
+}
+
 pub struct Server {
-    players: HashMap<String, Player>,
+    players: HashMap<String, PlayerSession>,
     sessions: HashMap<String, GameSession>,
+    main_tx: Option<Sender<ServerCommand>>,
+    rng: StdRng,
+    chat_filter: Box<dyn ChatFilter>,
+    history: Option<History>,
+    ratings: Ratings,
+    matchmaker: Matchmaker,
+    series: HashMap<String, Series>,
+    tournaments: HashMap<String, Tournament>,
+    snapshot: Option<Snapshot>,
+    restored: HashMap<String, PlayerState>,
+    tls: Option<Arc<ServerConfig>>,
+    connection_limits: ConnectionLimits,
+    connections: HashMap<IpAddr, usize>,
+    accounts: Accounts,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Server::new()
+    }
 }
 
 impl Server {
@@ -42,53 +181,2570 @@ impl Server {
         Server {
             sessions: HashMap::new(),
             players: HashMap::new(),
+            main_tx: None,
+            rng: StdRng::from_entropy(),
+            chat_filter: Box::new(Blocklist::default()),
+            history: None,
+            ratings: Ratings::default(),
+            matchmaker: Matchmaker::default(),
+            series: HashMap::new(),
+            tournaments: HashMap::new(),
+            snapshot: None,
+            restored: HashMap::new(),
+            tls: None,
+            connection_limits: ConnectionLimits::default(),
+            connections: HashMap::new(),
+            accounts: Accounts::default(),
+        }
+    }
+
+    pub fn with_chat_filter(self, chat_filter: Box<dyn ChatFilter>) -> Server {
+        Server {
+            chat_filter,
+            ..self
+        }
+    }
+
+    pub fn with_history(self, history: History) -> Server {
+        let ratings = match history.records() {
+            Ok(records) => Ratings::from_records(&records),
+            Err(error) => {
+                println!(
+                    "Ratings start from scratch, history not available: {}",
+                    error
+                );
+                Ratings::default()
+            }
+        };
+
+        Server {
+            history: Some(history),
+            ratings,
+            ..self
+        }
+    }
+
+    pub fn with_tls(self, config: Arc<ServerConfig>) -> Server {
+        Server {
+            tls: Some(config),
+            ..self
+        }
+    }
+
+    pub fn with_accounts(self, accounts: Accounts) -> Server {
+        Server { accounts, ..self }
+    }
+
+    pub fn with_connection_limits(self, connection_limits: ConnectionLimits) -> Server {
+        Server {
+            connection_limits,
+            ..self
+        }
+    }
+
+    /// Restores the state saved by the last run, players get it back by
+    /// resuming their registration.
+    pub fn with_snapshot(self, snapshot: Snapshot) -> Server {
+        let state = match snapshot.read() {
+            Ok(state) => state.unwrap_or_default(),
+            Err(error) => {
+                println!("Server state not restored: {}", error);
+                ServerState::default()
+            }
+        };
+
+        let mut server = Server {
+            snapshot: Some(snapshot),
+            ..self
+        };
+        server.restore(state);
+        server
+    }
+
+    fn restore(&mut self, state: ServerState) {
+        if !state.players.is_empty() || !state.games.is_empty() {
+            println!(
+                "Restored {} players and {} games",
+                state.players.len(),
+                state.games.len()
+            );
+        }
+
+        let resume_until = Local::now() + chrono::Duration::seconds(RESUME_GRACE_SECONDS);
+        for game in state.games {
+            let game = Game::from(game);
+            let game_session = GameSession {
+                resume_until: Some(resume_until),
+                ..GameSession::new(game, None)
+            };
+            self.sessions
+                .insert(game_session.game.id().to_string(), game_session);
+        }
+        for player in state.players {
+            self.restored.insert(player.id.clone(), player);
         }
     }
 
-    pub fn run(&mut self) {
+    /// Players who did not come back yet are kept for the next run, bot
+    /// games are not, the bot does not survive a restart.
+    fn save_snapshot(&self) {
+        let snapshot = match &self.snapshot {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+
+        let games: Vec<GameSnapshot> = self
+            .sessions
+            .values()
+            .filter(|game_session| game_session.bot.is_none())
+            .map(|game_session| GameSnapshot::from(&game_session.game))
+            .collect();
+        let kept = |game_id: &Option<String>| {
+            game_id
+                .clone()
+                .filter(|game_id| games.iter().any(|game| &game.id == game_id))
+        };
+        let players: Vec<PlayerState> = self
+            .players
+            .values()
+            .map(|session| PlayerState {
+                id: session.player.id.clone(),
+                name: session.player.name.clone(),
+                game_id: kept(&session.game_id),
+                token: Some(session.token.clone()),
+            })
+            .chain(self.restored.values().map(|player| PlayerState {
+                game_id: kept(&player.game_id),
+                ..player.clone()
+            }))
+            .collect();
+
+        let state = ServerState { players, games };
+        match snapshot.save(&state) {
+            Ok(_) => println!(
+                "Server state saved, {} players and {} games",
+                state.players.len(),
+                state.games.len()
+            ),
+            Err(error) => println!("Server state not saved: {}", error),
+        }
+    }
+
+    /// Fails when a listener cannot be started, like when its port is taken.
+    pub fn run(&mut self) -> Result<(), String> {
         let (main_tx, main_rx) = channel::<ServerCommand>();
-        self.start_server(main_tx);
+        self.main_tx = Some(main_tx.clone());
+        self.start_server(main_tx.clone())?;
+        self.start_websocket_server(main_tx.clone())?;
+        // Only once the server is up, a failed start keeps the snapshot for the next one
+        if let Some(snapshot) = &self.snapshot {
+            if let Err(error) = snapshot.set_restored() {
+                println!("Snapshot not set aside: {}", error);
+            }
+        }
+        self.start_ticker(main_tx.clone());
+        self.start_shutdown_handler(main_tx);
         self.listen_server_commands(main_rx);
+        Ok(())
     }
 
-    fn start_server(&self, main_tx: Sender<ServerCommand>) {
+    fn start_server(&self, main_tx: Sender<ServerCommand>) -> Result<(), String> {
+        let listener = TcpListener::bind("127.0.0.1:4242")
+            .map_err(|error| format!("Tcp listener not started: {}", error))?;
         thread::spawn(move || {
-            let listener = TcpListener::bind("127.0.0.1:4242").unwrap();
-            for stream in listener.incoming() {
-                let stream = stream.unwrap().try_clone().unwrap();
-                main_tx
-                    .send(ServerCommand::AcceptedConnection(
-                        stream,
-                        box main_tx.clone(),
-                    ))
-                    .unwrap();
+            accept_connections(listener, main_tx, ServerCommand::AcceptedConnection)
+        });
+        Ok(())
+    }
+
+    /// Browser clients connect here, frames travel as binary WebSocket messages.
+    fn start_websocket_server(&self, main_tx: Sender<ServerCommand>) -> Result<(), String> {
+        let listener = TcpListener::bind("127.0.0.1:4243")
+            .map_err(|error| format!("WebSocket listener not started: {}", error))?;
+        thread::spawn(move || {
+            accept_connections(listener, main_tx, ServerCommand::AcceptedWebSocket)
+        });
+        Ok(())
+    }
+
+    /// Wakes the command loop every second so turn timers can be enforced.
+    fn start_ticker(&self, main_tx: Sender<ServerCommand>) {
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(1));
+            if main_tx.send(ServerCommand::Tick).is_err() {
+                break;
             }
         });
     }
 
+    /// Ctrl-C and termination requests stop the server from the command
+    /// loop, after it saved its state.
+    fn start_shutdown_handler(&self, main_tx: Sender<ServerCommand>) {
+        let result = ctrlc::set_handler(move || {
+            main_tx.send(ServerCommand::Shutdown).ok();
+        });
+
+        if let Err(error) = result {
+            println!("Graceful shutdown not available: {}", error);
+        }
+    }
+
     fn listen_server_commands(&mut self, receiver: Receiver<ServerCommand>) {
         println!("Server started, waiting for connections");
 
         for server_command in receiver {
-            match server_command {
-                ServerCommand::AcceptedConnection(stream, main_tx) => {
-                    match self.start_connection(stream, *main_tx) {
-                        Ok(_) => println!("Successfully negotiated"),
-                        Err(err_message) => println!("Negotiation error: {}", err_message),
+            if !self.handle_command(server_command) {
+                break;
+            }
+        }
+        println!("Server stopped");
+    }
+
+    /// Returns false once the server must stop.
+    pub fn handle_command(&mut self, server_command: ServerCommand) -> bool {
+        match server_command {
+            ServerCommand::AcceptedConnection(stream, main_tx) => {
+                self.start_connection(stream, *main_tx)
+            }
+            ServerCommand::AcceptedWebSocket(stream, main_tx) => {
+                self.start_websocket_connection(stream, *main_tx)
+            }
+            ServerCommand::ConnectionClosed(ip) => self.release(ip),
+            ServerCommand::PlayerRegistered(user, outbox, reply) => {
+                reply.send(self.sign_up(user, outbox)).ok();
+            }
+            ServerCommand::PlayerResumed(user, outbox, reply) => {
+                reply.send(self.resume_player(user, outbox)).ok();
+            }
+            ServerCommand::PlayerFrame(player_id, frame) => {
+                if let Err(err_message) = self.handle_frame(&player_id, frame) {
+                    self.send(&player_id, Frame::text(ERROR, &err_message));
+                }
+            }
+            ServerCommand::PlayerDisconnected(player_id) => self.disconnect_player(&player_id),
+            ServerCommand::BotTurn(game_id) => self.play_bot_turn(&game_id),
+            ServerCommand::Tick => {
+                let now = Local::now();
+                self.expire_turns(now);
+                self.match_players(now);
+            }
+            ServerCommand::Message(message) => println!("{}", message),
+            ServerCommand::Shutdown => {
+                self.save_snapshot();
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Connections over the limits get no thread, they are told why without
+    /// waiting on them and closed right away.
+    fn start_connection(&mut self, stream: TcpStream, main_tx: Sender<ServerCommand>) {
+        let slot = match self.admit_stream(&stream, &main_tx) {
+            Ok(slot) => slot,
+            Err(reason) => {
+                let result = match &self.tls {
+                    // Nothing can be said before the TLS handshake
+                    Some(_) => {
+                        stream.shutdown(Shutdown::Both).ok();
+                        Err(format!("Connection closed - {}", reason))
                     }
+                    None => stream
+                        .set_nonblocking(true)
+                        .map_err(|error| format!("{}", error))
+                        .and_then(|_| Connection::new(stream, main_tx).reject(&reason)),
+                };
+                if let Err(err_message) = result {
+                    println!("Connection error: {}", err_message);
                 }
-                ServerCommand::Message(message) => println!("{}", message),
+                return;
             }
+        };
+        let handshake_timeout = self.handshake_timeout();
+
+        match &self.tls {
+            Some(config) => match ServerConnection::new(Arc::clone(config)) {
+                Ok(tls) => spawn_connection(
+                    TlsStream::new(tls, stream),
+                    main_tx,
+                    slot,
+                    handshake_timeout,
+                ),
+                Err(error) => println!("Connection error: {}", error),
+            },
+            None => spawn_connection(stream, main_tx, slot, handshake_timeout),
         }
     }
 
-    fn start_connection(
+    /// The upgrade handshake waits on the client, it runs on the connection
+    /// thread within the handshake timeout. Connections over the limits get
+    /// a plain HTTP answer instead, without a thread.
+    fn start_websocket_connection(
         &mut self,
-        stream: TcpStream,
+        mut stream: TcpStream,
         main_tx: Sender<ServerCommand>,
-    ) -> Result<(), String> {
-        let mut connection = Connection::new(stream, main_tx);
-        connection.start()
+    ) {
+        let slot = match self.admit_stream(&stream, &main_tx) {
+            Ok(slot) => slot,
+            Err(reason) => {
+                let response = format!(
+                    "HTTP/1.1 503 Service Unavailable\r\nContent-Length: {}\r\n\r\n{}",
+                    reason.len(),
+                    reason
+                );
+                stream.set_nonblocking(true).ok();
+                stream.write_all(response.as_bytes()).ok();
+                stream.shutdown(Shutdown::Both).ok();
+                println!("Connection error: Connection closed - {}", reason);
+                return;
+            }
+        };
+        let handshake_timeout = self.handshake_timeout();
+
+        thread::spawn(move || {
+            stream.set_read_timeout(Some(handshake_timeout)).ok();
+            match WebSocketStream::accept(stream) {
+                Ok(websocket) => run_connection(websocket, main_tx, slot, handshake_timeout),
+                Err(error) => println!("Connection error: {}", error),
+            }
+        });
+    }
+
+    fn handshake_timeout(&self) -> Duration {
+        Duration::from_secs(self.connection_limits.handshake_seconds)
+    }
+
+    fn admit_stream(&mut self, stream: &TcpStream, main_tx: &Sender<ServerCommand>) -> Admission {
+        let address = stream.peer_addr().map_err(|error| format!("{}", error))?;
+        self.admit(address.ip(), main_tx)
+    }
+
+    /// Takes a slot for a connection from `ip` if the limits leave one.
+    fn admit(&mut self, ip: IpAddr, main_tx: &Sender<ServerCommand>) -> Admission {
+        let open_connections: usize = self.connections.values().sum();
+        if open_connections >= self.connection_limits.max_connections {
+            return Err(String::from("Server is full"));
+        }
+
+        let ip_connections = self.connections.entry(ip).or_insert(0);
+        if *ip_connections >= self.connection_limits.max_connections_per_ip {
+            return Err(String::from("Too many connections from your address"));
+        }
+
+        *ip_connections += 1;
+        Ok(ConnectionSlot {
+            ip,
+            main_tx: main_tx.clone(),
+        })
+    }
+
+    fn release(&mut self, ip: IpAddr) {
+        if let Some(ip_connections) = self.connections.get_mut(&ip) {
+            *ip_connections -= 1;
+            if *ip_connections == 0 {
+                self.connections.remove(&ip);
+            }
+        }
+    }
+
+    /// New players take their name for good, they come back to it by
+    /// resuming with the token they got.
+    fn sign_up(&mut self, user: UserInfo, outbox: Sender<Frame>) -> Result<(), String> {
+        let account = Account {
+            id: user.id.clone(),
+            name: user.name.clone(),
+            token: Uuid::new_v4().to_string(),
+        };
+        self.accounts.add(account.clone())?;
+
+        self.register_player(
+            UserInfo {
+                token: Some(account.token),
+                ..user
+            },
+            outbox,
+        );
+        Ok(())
+    }
+
+    fn register_player(&mut self, user: UserInfo, outbox: Sender<Frame>) {
+        println!("Player {} registered as {}", user.id, user.name);
+
+        let player = Player {
+            id: user.id.clone(),
+            name: user.name.clone(),
+            registered_at: Local::now(),
+        };
+        // Resumed players keep their token, new ones get theirs
+        let token = user
+            .token
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let user = UserInfo {
+            stats: self.player_stats(&player),
+            token: Some(token.clone()),
+            ..user
+        };
+        let constraints = ConnectionConstraints::default();
+        let chat_limiter = RateLimiter::new(
+            constraints.max_chat_messages as usize,
+            chrono::Duration::seconds(constraints.chat_window_seconds as i64),
+        );
+
+        self.players.insert(
+            user.id.clone(),
+            PlayerSession {
+                player,
+                token,
+                outbox,
+                game_id: None,
+                watching: None,
+                series_id: None,
+                tournament_id: None,
+                chat_limiter,
+            },
+        );
+        self.send(&user.id, Frame::json(USER, &user));
+    }
+
+    /// Players come back to their account, and players from the last run
+    /// to their game too if it is still on.
+    fn resume_player(&mut self, user: UserInfo, outbox: Sender<Frame>) -> Result<(), String> {
+        if self.players.contains_key(&user.id) {
+            return Err(String::from("Player is already connected"));
+        }
+
+        // Player ids are public, only the token tells the player came back
+        let account_name = self
+            .accounts
+            .get(&user.id)
+            .filter(|account| Some(&account.token) == user.token.as_ref())
+            .map(|account| account.name.clone());
+        let restored = match self.restored.remove(&user.id) {
+            Some(restored) if restored.token.is_some() && restored.token == user.token => {
+                Some(restored)
+            }
+            Some(restored) => {
+                self.restored.insert(user.id.clone(), restored);
+                None
+            }
+            None => None,
+        };
+        let name = match (&restored, account_name) {
+            (Some(restored), _) => restored.name.clone(),
+            (None, Some(account_name)) => account_name,
+            (None, None) => return Err(String::from("Nothing to resume for this player")),
+        };
+
+        let player_id = user.id.clone();
+        self.register_player(UserInfo { name, ..user }, outbox);
+
+        let game_id = match restored
+            .and_then(|restored| restored.game_id)
+            .filter(|game_id| self.sessions.contains_key(game_id))
+        {
+            Some(game_id) => game_id,
+            None => return Ok(()),
+        };
+        if let Some(session) = self.players.get_mut(&player_id) {
+            session.game_id = Some(game_id.clone());
+        }
+
+        let game = &self.sessions[&game_id].game;
+        self.send(&player_id, Frame::json(GAME, &GameInfo::from(game)));
+        if game.turn_player().map(|player| &player.id) == Some(&player_id) {
+            self.send(&player_id, Frame::empty(TURN));
+        }
+        Ok(())
+    }
+
+    fn player_stats(&self, player: &Player) -> Option<PlayerStats> {
+        let records = self.history.as_ref()?.records();
+
+        match records {
+            Ok(records) => Some(PlayerStats {
+                rating: Some(self.ratings.rating(&player.id).round() as u16),
+                ..stats::player_stats(&records, &player.id)
+            }),
+            Err(error) => {
+                println!("History not available: {}", error);
+                None
+            }
+        }
+    }
+
+    fn send_leaderboard(&self, player_id: &str, period: Period) -> Result<(), String> {
+        let records = match &self.history {
+            Some(history) => history.records()?,
+            None => Vec::new(),
+        };
+
+        self.send(
+            player_id,
+            Frame::json(
+                LEADERBOARD_ENTRIES,
+                &stats::leaderboard(&records, period, Local::now()),
+            ),
+        );
+        Ok(())
+    }
+
+    fn disconnect_player(&mut self, player_id: &str) {
+        let session = match self.players.remove(player_id) {
+            Some(session) => session,
+            None => return,
+        };
+        println!("Player {} disconnected", session.player.name);
+
+        self.matchmaker.leave(player_id);
+
+        if let Some(series_id) = session.series_id {
+            self.end_series(&series_id);
+        }
+
+        if let Some(tournament_id) = session.tournament_id {
+            self.forfeit_tournament(&tournament_id, player_id);
+        }
+
+        if let Some(game_id) = session.watching {
+            self.remove_spectator(&game_id, player_id);
+        }
+
+        if let Some(game_id) = session.game_id {
+            self.leave_game(&game_id, player_id);
+        }
+    }
+
+    fn leave_game(&mut self, game_id: &str, player_id: &str) {
+        let (finished, turn_changed, player_ids, walkover) = match self.sessions.get_mut(game_id) {
+            Some(game_session) => {
+                let game = &mut game_session.game;
+                let turn_player = game.turn_player().cloned();
+
+                if let Some(player) = game.player(player_id).cloned() {
+                    game.leave(&player).ok();
+                }
+
+                let player_ids: Vec<String> = game
+                    .players()
+                    .iter()
+                    .map(|player| player.id.clone())
+                    .collect();
+                (
+                    *game.mode() == GameMode::Finished,
+                    game.turn_player() != turn_player.as_ref(),
+                    player_ids,
+                    game_session.tournament_id.is_some() && *game.mode() != GameMode::InProgress,
+                )
+            }
+            None => return,
+        };
+
+        if finished {
+            self.announce_result(game_id);
+            self.finish_game(game_id);
+        } else if walkover {
+            self.send_game(game_id);
+            self.finish_game(game_id);
+        } else if player_ids.iter().all(|id| !self.players.contains_key(id)) {
+            self.finish_game(game_id);
+        } else if turn_changed {
+            self.notify_turn(game_id);
+        }
+    }
+
+    fn handle_frame(&mut self, player_id: &str, frame: Frame) -> Result<(), String> {
+        match frame.method() {
+            GAME_CREATE => self.create_game(player_id, frame.json_payload()?),
+            GAME_LIST => self.list_games(player_id),
+            GAME_JOIN => self.join_game(player_id, &frame.text_payload()?),
+            GAME_AGAINST_HOUSE => self.play_against_house(player_id, frame.json_payload()?),
+            GAME_AGAINST_BOT => self.play_against_bot(player_id, frame.json_payload()?),
+            SECRET_NUMBER => self.set_secret_number(player_id, frame.number_payload()?),
+            GUESS => self.guess_number(
+                player_id,
+                Guess {
+                    target: None,
+                    number: frame.number_payload()?,
+                },
+            ),
+            GUESS_AT => self.guess_number(player_id, frame.json_payload()?),
+            WATCH => self.watch_game(player_id, &frame.text_payload()?),
+            UNWATCH => self.unwatch_game(player_id),
+            CHAT_LOBBY => self.chat(player_id, ChatChannel::Lobby, &frame.text_payload()?),
+            CHAT_GAME => self.chat(player_id, ChatChannel::Game, &frame.text_payload()?),
+            REMATCH => self.offer_rematch(player_id),
+            MATCH_CREATE => self.create_match(player_id, frame.json_payload()?),
+            TOURNAMENT_CREATE => self.create_tournament(player_id, frame.json_payload()?),
+            TOURNAMENT_LIST => self.list_tournaments(player_id),
+            TOURNAMENT_JOIN => self.join_tournament(player_id, &frame.text_payload()?),
+            TOURNAMENT_LEAVE => self.leave_tournament(player_id),
+            TOURNAMENT_STATE => self.send_tournament(player_id, &frame.text_payload()?),
+            MATCHMAKING => self.queue_for_match(player_id),
+            MATCHMAKING_CANCEL => self.cancel_match(player_id),
+            LEADERBOARD => self.send_leaderboard(player_id, frame.json_payload()?),
+            HEARTBEAT => {
+                self.send(player_id, Frame::empty(HEARTBEAT_OK));
+                Ok(())
+            }
+            (class, method) => Err(format!("Unexpected method {}.{}", class, method)),
+        }
+    }
+
+    fn send(&self, player_id: &str, frame: Frame) {
+        if let Some(session) = self.players.get(player_id) {
+            session.outbox.send(frame).ok();
+        }
+    }
+
+    fn send_game(&self, game_id: &str) {
+        if let Some(game_session) = self.sessions.get(game_id) {
+            let game_info = Frame::json(GAME, &GameInfo::from(&game_session.game));
+
+            for player in game_session.game.players() {
+                self.send(&player.id, game_info.clone());
+            }
+            for spectator_id in game_session.spectators.iter() {
+                self.send(spectator_id, game_info.clone());
+            }
+        }
+    }
+
+    fn send_spectators(&self, game_id: &str, event: GameEvent) {
+        if let Some(game_session) = self.sessions.get(game_id) {
+            let event = Frame::json(GAME_EVENT, &event);
+
+            for spectator_id in game_session.spectators.iter() {
+                self.send(spectator_id, event.clone());
+            }
+        }
+    }
+
+    /// Pushes every guess made since the last report to the spectators.
+    fn report_guesses(&mut self, game_id: &str) {
+        let events: Vec<GameEvent> = match self.sessions.get_mut(game_id) {
+            Some(game_session) => {
+                let guesses = game_session.game.guesses();
+                let events = guesses[game_session.reported_guesses..]
+                    .iter()
+                    .map(|guess| GameEvent::from(*guess))
+                    .collect();
+                game_session.reported_guesses = guesses.len();
+                events
+            }
+            None => return,
+        };
+
+        for event in events {
+            self.send_spectators(game_id, event);
+        }
+    }
+
+    fn chat(&mut self, player_id: &str, channel: ChatChannel, text: &str) -> Result<(), String> {
+        let max_message_size = ConnectionConstraints::default().max_message_size as usize;
+        if text.trim().is_empty() || text.len() > max_message_size {
+            return Err(format!("Message must have 1 to {} bytes", max_message_size));
+        }
+
+        let allowed = match self.players.get_mut(player_id) {
+            Some(session) => session.chat_limiter.allow(Local::now()),
+            None => return Err(String::from("Player is not registered")),
+        };
+        if !allowed {
+            return Err(String::from("Too many messages, slow down"));
+        }
+
+        let session = &self.players[player_id];
+        let recipients: Vec<String> = match channel {
+            ChatChannel::Lobby => self.players.keys().cloned().collect(),
+            ChatChannel::Game => {
+                let game_id = session
+                    .game_id
+                    .as_ref()
+                    .or(session.watching.as_ref())
+                    .ok_or_else(|| String::from("Player is not in a game"))?;
+                let game_session = self
+                    .sessions
+                    .get(game_id)
+                    .ok_or_else(|| String::from("Game not found"))?;
+
+                game_session
+                    .game
+                    .players()
+                    .iter()
+                    .map(|player| player.id.clone())
+                    .chain(game_session.spectators.iter().cloned())
+                    .collect()
+            }
+        };
+
+        let message = ChatMessage {
+            channel,
+            from: UserInfo {
+                id: session.player.id.clone(),
+                name: session.player.name.clone(),
+                stats: None,
+                token: None,
+            },
+            text: self.chat_filter.filter(text)?,
+        };
+        let frame = Frame::json(CHAT_MESSAGE, &message);
+
+        for recipient_id in recipients {
+            self.send(&recipient_id, frame.clone());
+        }
+        Ok(())
+    }
+
+    fn watch_game(&mut self, player_id: &str, game_id: &str) -> Result<(), String> {
+        match self.players.get(player_id) {
+            Some(session) if session.game_id.is_some() => {
+                return Err(String::from("Player is already in a game"))
+            }
+            Some(session) if session.watching.is_some() => {
+                return Err(String::from("Player is already watching a game"))
+            }
+            Some(_) => {}
+            None => return Err(String::from("Player is not registered")),
+        }
+
+        let game_session = self
+            .sessions
+            .get_mut(game_id)
+            .ok_or_else(|| String::from("Game not found"))?;
+
+        if game_session.spectators.len() >= MAX_SPECTATORS {
+            return Err(String::from("This game has too many spectators"));
+        }
+        game_session.spectators.push(player_id.to_string());
+        self.matchmaker.leave(player_id);
+
+        if let Some(session) = self.players.get_mut(player_id) {
+            session.watching = Some(game_id.to_string());
+        }
+        self.send(
+            player_id,
+            Frame::json(GAME, &GameInfo::from(&self.sessions[game_id].game)),
+        );
+        Ok(())
+    }
+
+    fn unwatch_game(&mut self, player_id: &str) -> Result<(), String> {
+        let game_id = self
+            .players
+            .get_mut(player_id)
+            .and_then(|session| session.watching.take())
+            .ok_or_else(|| String::from("Player is not watching a game"))?;
+
+        self.remove_spectator(&game_id, player_id);
+        Ok(())
+    }
+
+    fn remove_spectator(&mut self, game_id: &str, player_id: &str) {
+        if let Some(game_session) = self.sessions.get_mut(game_id) {
+            game_session
+                .spectators
+                .retain(|spectator_id| spectator_id != player_id);
+        }
+    }
+
+    fn game_player(&self, player_id: &str) -> Result<crate::game::Player, String> {
+        match self.players.get(player_id) {
+            Some(session) if session.game_id.is_some() => {
+                Err(String::from("Player is already in a game"))
+            }
+            Some(session) if session.watching.is_some() => {
+                Err(String::from("Player is watching a game"))
+            }
+            Some(session) if session.tournament_id.is_some() => {
+                Err(String::from("Player is registered in a tournament"))
+            }
+            Some(session) => Ok(crate::game::Player::with_id(
+                session.player.id.clone(),
+                session.player.name.clone(),
+            )),
+            None => Err(String::from("Player is not registered")),
+        }
+    }
+
+    fn game_session(&mut self, player_id: &str) -> Result<&mut GameSession, String> {
+        let game_id = self
+            .players
+            .get(player_id)
+            .and_then(|session| session.game_id.clone())
+            .ok_or_else(|| String::from("Player is not in a game"))?;
+
+        self.sessions
+            .get_mut(&game_id)
+            .ok_or_else(|| String::from("Game not found"))
+    }
+
+    fn add_game(&mut self, player_id: &str, game: Game, bot: Option<Bot>) -> String {
+        let game_id = game.id().to_string();
+        self.matchmaker.leave(player_id);
+
+        if let Some(session) = self.players.get_mut(player_id) {
+            session.game_id = Some(game_id.clone());
+        }
+        self.sessions
+            .insert(game_id.clone(), GameSession::new(game, bot));
+        game_id
+    }
+
+    fn offer_rematch(&mut self, player_id: &str) -> Result<(), String> {
+        let series_id = self
+            .players
+            .get(player_id)
+            .and_then(|session| session.series_id.clone())
+            .ok_or_else(|| String::from("No game to rematch"))?;
+        let player = self.game_player(player_id)?;
+        let series = self
+            .series
+            .get_mut(&series_id)
+            .ok_or_else(|| String::from("No game to rematch"))?;
+
+        let everyone_agreed = series.offer_rematch(player_id)?;
+        let player_ids: Vec<String> = series.player_ids().into_iter().map(String::from).collect();
+
+        if !everyone_agreed {
+            for opponent_id in player_ids.iter().filter(|id| *id != player_id) {
+                self.send(opponent_id, Frame::text(REMATCH_OFFER, &player.name()));
+            }
+            return Ok(());
+        }
+
+        self.start_series_game(&series_id)
+    }
+
+    /// Next game of a series, with the same players and rules.
+    fn start_series_game(&mut self, series_id: &str) -> Result<(), String> {
+        let series = self
+            .series
+            .get(series_id)
+            .ok_or_else(|| String::from("Series not found"))?;
+        let player_ids: Vec<String> = series.player_ids().into_iter().map(String::from).collect();
+        let rules = series.rules().clone();
+        let first_player = series.first_player().to_string();
+
+        // A rematch never pulls a player out of a game still going on
+        for player_id in player_ids.iter() {
+            let game_id = self
+                .players
+                .get(player_id)
+                .and_then(|session| session.game_id.as_ref());
+            if let Some(game_session) = game_id.and_then(|game_id| self.sessions.get(game_id)) {
+                if *game_session.game.mode() != GameMode::Finished {
+                    return Err(String::from("Player is in another game"));
+                }
+            }
+        }
+        let players = player_ids
+            .iter()
+            .map(|id| self.game_player(id))
+            .collect::<Result<Vec<crate::game::Player>, String>>()?;
+
+        let mut game = Game::new(&players[0], rules)?;
+        for opponent in players.iter().skip(1) {
+            game.accept_challenge(opponent)?;
+        }
+        if let Some(first_player) = players.iter().find(|player| player.id == first_player) {
+            game.set_first_player(first_player)?;
+        }
+
+        let game_id = self.add_game(&player_ids[0], game, None);
+        for opponent_id in player_ids.iter().skip(1) {
+            if let Some(session) = self.players.get_mut(opponent_id) {
+                session.game_id = Some(game_id.clone());
+            }
+        }
+        if let Some(game_session) = self.sessions.get_mut(&game_id) {
+            game_session.series_id = Some(series_id.to_string());
+        }
+        self.send_game(&game_id);
+        Ok(())
+    }
+
+    /// Finished games between players still connected carry on as a series,
+    /// so they can ask for a rematch.
+    fn record_series(&mut self, game_session: &GameSession) {
+        let game = &game_session.game;
+        if *game.mode() != GameMode::Finished
+            || game.is_against_house()
+            || game_session.bot.is_some()
+            || game_session.tournament_id.is_some()
+        {
+            return;
+        }
+
+        let everyone_connected = game
+            .players()
+            .iter()
+            .all(|player| self.players.contains_key(&player.id));
+        if !everyone_connected {
+            if let Some(series_id) = &game_session.series_id {
+                self.end_series(series_id);
+            }
+            return;
+        }
+
+        let series_id = match &game_session.series_id {
+            Some(series_id) if self.series.contains_key(series_id) => series_id.clone(),
+            _ => {
+                let series = match game_session.best_of {
+                    Some(best_of) => Series::best_of(game, best_of),
+                    None => Series::new(game),
+                };
+                let series_id = series.id().to_string();
+                self.series.insert(series_id.clone(), series);
+                series_id
+            }
+        };
+
+        let (player_ids, series_info, is_match, match_winner) =
+            match self.series.get_mut(&series_id) {
+                Some(series) => {
+                    series.record(game);
+                    let player_ids: Vec<String> =
+                        series.player_ids().into_iter().map(String::from).collect();
+                    (
+                        player_ids,
+                        SeriesInfo::from(&*series),
+                        series.is_match(),
+                        series.winner(),
+                    )
+                }
+                None => return,
+            };
+
+        let mut replaced = Vec::new();
+        for player_id in player_ids.iter() {
+            if let Some(session) = self.players.get_mut(player_id) {
+                replaced.extend(
+                    session
+                        .series_id
+                        .replace(series_id.clone())
+                        .filter(|previous| *previous != series_id),
+                );
+            }
+            self.send(player_id, Frame::json(SERIES, &series_info));
+        }
+        for previous in replaced {
+            self.drop_unused_series(&previous);
+        }
+
+        if !is_match {
+            return;
+        }
+
+        match match_winner {
+            Some(winner) => {
+                let match_result = Frame::json(
+                    MATCH_RESULT,
+                    &MatchResult {
+                        winner,
+                        series: series_info,
+                    },
+                );
+                for player_id in player_ids.iter() {
+                    self.send(player_id, match_result.clone());
+                }
+                self.end_series(&series_id);
+            }
+            None => {
+                if let Err(error) = self.start_series_game(&series_id) {
+                    println!("Match {} can not go on: {}", series_id, error);
+                    self.end_series(&series_id);
+                }
+            }
+        }
+    }
+
+    /// Starting another game gives up the series a player was in, for
+    /// everyone in it.
+    fn leave_series(&mut self, player_id: &str) {
+        let series_id = self
+            .players
+            .get(player_id)
+            .and_then(|session| session.series_id.clone());
+        if let Some(series_id) = series_id {
+            self.end_series(&series_id);
+        }
+    }
+
+    fn drop_unused_series(&mut self, series_id: &str) {
+        let used = self
+            .players
+            .values()
+            .any(|session| session.series_id.as_deref() == Some(series_id));
+        if !used {
+            self.series.remove(series_id);
+        }
+    }
+
+    fn end_series(&mut self, series_id: &str) {
+        if let Some(series) = self.series.remove(series_id) {
+            for player_id in series.player_ids() {
+                if let Some(session) = self.players.get_mut(player_id) {
+                    session.series_id = None;
+                }
+            }
+        }
+    }
+
+    fn create_tournament(&mut self, player_id: &str, rules: TournamentRules) -> Result<(), String> {
+        let player = self.game_player(player_id)?;
+        let mut tournament = Tournament::new(rules)?;
+        tournament.register(player_id, &player.name())?;
+
+        let tournament_id = tournament.id().to_string();
+        self.tournaments.insert(tournament_id.clone(), tournament);
+        self.matchmaker.leave(player_id);
+        if let Some(session) = self.players.get_mut(player_id) {
+            session.tournament_id = Some(tournament_id.clone());
+        }
+        self.progress_tournament(&tournament_id);
+        Ok(())
+    }
+
+    fn list_tournaments(&self, player_id: &str) -> Result<(), String> {
+        let tournaments: Vec<&Tournament> = self
+            .tournaments
+            .values()
+            .filter(|tournament| tournament.state() == TournamentState::Registering)
+            .collect();
+
+        self.send(player_id, Frame::json(TOURNAMENTS, &tournaments));
+        Ok(())
+    }
+
+    fn join_tournament(&mut self, player_id: &str, tournament_id: &str) -> Result<(), String> {
+        let player = self.game_player(player_id)?;
+        let tournament = self
+            .tournaments
+            .get_mut(tournament_id)
+            .ok_or_else(|| String::from("Tournament not found"))?;
+        tournament.register(player_id, &player.name())?;
+
+        self.matchmaker.leave(player_id);
+        if let Some(session) = self.players.get_mut(player_id) {
+            session.tournament_id = Some(tournament_id.to_string());
+        }
+        self.progress_tournament(tournament_id);
+        Ok(())
+    }
+
+    fn leave_tournament(&mut self, player_id: &str) -> Result<(), String> {
+        let tournament_id = self
+            .players
+            .get_mut(player_id)
+            .and_then(|session| session.tournament_id.take())
+            .ok_or_else(|| String::from("Player is not registered in a tournament"))?;
+
+        self.forfeit_tournament(&tournament_id, player_id);
+        Ok(())
+    }
+
+    fn forfeit_tournament(&mut self, tournament_id: &str, player_id: &str) {
+        if let Some(tournament) = self.tournaments.get_mut(tournament_id) {
+            tournament.leave(player_id);
+            if tournament.player_ids().is_empty() {
+                self.tournaments.remove(tournament_id);
+                return;
+            }
+        }
+        self.progress_tournament(tournament_id);
+    }
+
+    fn send_tournament(&self, player_id: &str, tournament_id: &str) -> Result<(), String> {
+        let tournament = self
+            .tournaments
+            .get(tournament_id)
+            .ok_or_else(|| String::from("Tournament not found"))?;
+
+        self.send(player_id, Frame::json(TOURNAMENT, tournament));
+        Ok(())
+    }
+
+    /// Starts the tournament once full, then the games of each round, and
+    /// pushes the bracket to every player after any change.
+    fn progress_tournament(&mut self, tournament_id: &str) {
+        let tournament = match self.tournaments.get_mut(tournament_id) {
+            Some(tournament) => tournament,
+            None => return,
+        };
+        if tournament.state() == TournamentState::Registering && tournament.is_full() {
+            tournament.start().ok();
+        }
+
+        for (pairing, player_id, opponent_id) in tournament.games_to_start() {
+            match self.start_tournament_game(tournament_id, &[player_id, opponent_id]) {
+                Ok(game_id) => {
+                    if let Some(tournament) = self.tournaments.get_mut(tournament_id) {
+                        tournament.game_started(pairing, &game_id);
+                    }
+                }
+                Err(error) => {
+                    println!("Tournament {} game not started: {}", tournament_id, error)
+                }
+            }
+        }
+
+        let tournament = &self.tournaments[tournament_id];
+        let frame = Frame::json(TOURNAMENT, tournament);
+        let finished = tournament.state() == TournamentState::Finished;
+        let player_ids: Vec<String> = tournament
+            .player_ids()
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        for player_id in player_ids.iter() {
+            self.send(player_id, frame.clone());
+            if finished {
+                if let Some(session) = self.players.get_mut(player_id) {
+                    session.tournament_id = None;
+                }
+            }
+        }
+    }
+
+    fn start_tournament_game(
+        &mut self,
+        tournament_id: &str,
+        player_ids: &[String],
+    ) -> Result<String, String> {
+        let rules = self.tournaments[tournament_id].game_rules().clone();
+        let players = player_ids
+            .iter()
+            .map(|id| match self.players.get(id) {
+                Some(session) => Ok(crate::game::Player::with_id(
+                    session.player.id.clone(),
+                    session.player.name.clone(),
+                )),
+                None => Err(String::from("Player is not registered")),
+            })
+            .collect::<Result<Vec<crate::game::Player>, String>>()?;
+
+        let mut game = Game::new(&players[0], rules)?;
+        for opponent in players.iter().skip(1) {
+            game.accept_challenge(opponent)?;
+        }
+
+        let game_id = self.add_game(&player_ids[0], game, None);
+        for opponent_id in player_ids.iter().skip(1) {
+            if let Some(session) = self.players.get_mut(opponent_id) {
+                session.game_id = Some(game_id.clone());
+            }
+        }
+        if let Some(game_session) = self.sessions.get_mut(&game_id) {
+            game_session.tournament_id = Some(tournament_id.to_string());
+        }
+        self.send_game(&game_id);
+        Ok(game_id)
+    }
+
+    fn queue_for_match(&mut self, player_id: &str) -> Result<(), String> {
+        self.game_player(player_id)?;
+        let rating = self.ratings.rating(player_id);
+
+        self.matchmaker.join(player_id, rating, Local::now())?;
+        self.leave_series(player_id);
+        Ok(())
+    }
+
+    fn cancel_match(&mut self, player_id: &str) -> Result<(), String> {
+        if self.matchmaker.leave(player_id) {
+            Ok(())
+        } else {
+            Err(String::from("Player is not waiting for an opponent"))
+        }
+    }
+
+    /// Matched players get a game with the default rules, same as a created
+    /// and joined one.
+    fn match_players(&mut self, now: DateTime<Local>) {
+        for (player_id, opponent_id) in self.matchmaker.find_matches(now) {
+            let matched = self
+                .create_game(&player_id, GameRules::default())
+                .and_then(|_| {
+                    let game_id = self.players[&player_id].game_id.clone().unwrap_or_default();
+                    self.join_game(&opponent_id, &game_id)
+                });
+
+            if let Err(error) = matched {
+                println!("Matchmaking error: {}", error);
+            }
+        }
+    }
+
+    fn create_game(&mut self, player_id: &str, rules: GameRules) -> Result<(), String> {
+        let player = self.game_player(player_id)?;
+        let game = Game::new(&player, rules)?;
+
+        self.leave_series(player_id);
+        let game_id = self.add_game(player_id, game, None);
+        self.send_game(&game_id);
+        Ok(())
+    }
+
+    fn create_match(&mut self, player_id: &str, match_rules: MatchRules) -> Result<(), String> {
+        match_rules.validate()?;
+        let player = self.game_player(player_id)?;
+        let game = Game::new(&player, match_rules.rules)?;
+
+        self.leave_series(player_id);
+        let game_id = self.add_game(player_id, game, None);
+        if let Some(game_session) = self.sessions.get_mut(&game_id) {
+            game_session.best_of = Some(match_rules.best_of);
+        }
+        self.send_game(&game_id);
+        Ok(())
+    }
+
+    fn list_games(&self, player_id: &str) -> Result<(), String> {
+        let games: Vec<GameInfo> = self
+            .sessions
+            .values()
+            .filter(|game_session| *game_session.game.mode() == GameMode::WaitingForPlayer)
+            .map(|game_session| GameInfo::from(&game_session.game))
+            .collect();
+
+        self.send(player_id, Frame::json(GAMES, &games));
+        Ok(())
+    }
+
+    fn join_game(&mut self, player_id: &str, game_id: &str) -> Result<(), String> {
+        let player = self.game_player(player_id)?;
+        let game_session = self
+            .sessions
+            .get_mut(game_id)
+            .ok_or_else(|| String::from("Game not found"))?;
+
+        game_session.game.accept_challenge(&player)?;
+        self.matchmaker.leave(player_id);
+        self.leave_series(player_id);
+
+        if let Some(session) = self.players.get_mut(player_id) {
+            session.game_id = Some(game_id.to_string());
+        }
+        self.send_game(game_id);
+        Ok(())
+    }
+
+    fn play_against_house(&mut self, player_id: &str, rules: GameRules) -> Result<(), String> {
+        let player = self.game_player(player_id)?;
+        let mut game = Game::against_house(&player, rules, &mut self.rng)?;
+        game.start()?;
+
+        self.leave_series(player_id);
+        let game_id = self.add_game(player_id, game, None);
+        self.send_game(&game_id);
+        self.notify_turn(&game_id);
+        Ok(())
+    }
+
+    fn play_against_bot(&mut self, player_id: &str, challenge: BotChallenge) -> Result<(), String> {
+        let player = self.game_player(player_id)?;
+        let bot = Bot::new(challenge.strategy, &challenge.rules, self.rng.gen());
+
+        let mut game = Game::new(&player, challenge.rules)?;
+        game.accept_challenge(&bot.player)?;
+        bot.player.pick_secret_number(game.rules(), &mut self.rng)?;
+
+        self.leave_series(player_id);
+        let game_id = self.add_game(player_id, game, Some(bot));
+        self.send_game(&game_id);
+        Ok(())
+    }
+
+    fn set_secret_number(&mut self, player_id: &str, secret_number: u16) -> Result<(), String> {
+        let game_session = self.game_session(player_id)?;
+        let game = &mut game_session.game;
+        let player = game
+            .player(player_id)
+            .cloned()
+            .ok_or_else(|| String::from("Player is not part of this game"))?;
+
+        game.set_secret_number(&player, secret_number)?;
+
+        if game.set_ready().is_ok() {
+            game.start()?;
+            let game_id = game.id().to_string();
+            self.send_game(&game_id);
+            self.notify_turn(&game_id);
+        }
+        Ok(())
+    }
+
+    fn guess_number(&mut self, player_id: &str, guess: Guess) -> Result<(), String> {
+        let game_session = self.game_session(player_id)?;
+        let game = &mut game_session.game;
+        let player = game
+            .player(player_id)
+            .cloned()
+            .ok_or_else(|| String::from("Player is not part of this game"))?;
+
+        let message = match guess.target {
+            Some(target) => game.guess_number_at(&player, &target, guess.number)?,
+            None => game.guess_number(&player, guess.number)?,
+        };
+        let game_id = game.id().to_string();
+        self.dispatch_game_message(&game_id, player_id, message)
+    }
+
+    fn dispatch_game_message(
+        &mut self,
+        game_id: &str,
+        player_id: &str,
+        message: GameMessages,
+    ) -> Result<(), String> {
+        self.report_guesses(game_id);
+
+        let (finished, target_name, many_players) = match self.sessions.get(game_id) {
+            Some(game_session) => {
+                let game = &game_session.game;
+                let player = game
+                    .player(player_id)
+                    .ok_or_else(|| String::from("Player is not part of this game"))?;
+                (
+                    *game.mode() == GameMode::Finished,
+                    game.last_target(player).map(|target| target.name()),
+                    game.players().len() > 2,
+                )
+            }
+            None => return Err(String::from("Game not found")),
+        };
+
+        match message {
+            GameMessages::NotYourTurn => {
+                self.send(player_id, Frame::empty(NOT_YOUR_TURN));
+                return Ok(());
+            }
+            GameMessages::WrongAnswer(hint) => {
+                let hint = match target_name {
+                    Some(target_name) if many_players => format!("{}: {}", target_name, hint),
+                    _ => hint.to_string(),
+                };
+                self.send(player_id, Frame::text(WRONG_ANSWER, &hint));
+            }
+            GameMessages::Cracked(_) => {
+                self.send(
+                    player_id,
+                    Frame::text(CRACKED, &target_name.unwrap_or_default()),
+                );
+            }
+            GameMessages::YouLose => self.send(player_id, Frame::empty(YOU_LOSE)),
+            GameMessages::YouWin => {}
+            GameMessages::UnexpectedError(err_message) => return Err(err_message),
+        }
+
+        if finished {
+            self.announce_result(game_id);
+            self.finish_game(game_id);
+        } else {
+            self.notify_turn(game_id);
+        }
+        Ok(())
+    }
+
+    /// Eliminated players were already told they lost.
+    fn announce_result(&self, game_id: &str) {
+        if let Some(game_session) = self.sessions.get(game_id) {
+            let game_info = Frame::json(GAME, &GameInfo::from(&game_session.game));
+            for spectator_id in game_session.spectators.iter() {
+                self.send(spectator_id, game_info.clone());
+            }
+
+            let game = &game_session.game;
+            for player in game.players() {
+                if game.winner() == Some(player) {
+                    self.send(&player.id, Frame::empty(YOU_WIN));
+                } else if player.life() > 0 {
+                    self.send(&player.id, Frame::empty(YOU_LOSE));
+                }
+            }
+        }
+    }
+
+    fn notify_turn(&mut self, game_id: &str) {
+        let game_session = match self.sessions.get(game_id) {
+            Some(game_session) => game_session,
+            None => return,
+        };
+
+        let turn_player = match game_session.game.turn_player() {
+            Some(turn_player) => turn_player.id.clone(),
+            None => return,
+        };
+
+        self.send_spectators(
+            game_id,
+            GameEvent::Turn {
+                player: turn_player.clone(),
+            },
+        );
+
+        let game_session = &self.sessions[game_id];
+        match &game_session.bot {
+            Some(bot) if bot.player.id == turn_player => {
+                let think_time = Bot::think_time(game_session.game.rules());
+                self.schedule_bot_turn(game_id, think_time);
+            }
+            _ => self.send(&turn_player, Frame::empty(TURN)),
+        }
+    }
+
+    fn schedule_bot_turn(&self, game_id: &str, think_time: Duration) {
+        if let Some(main_tx) = self.main_tx.clone() {
+            let game_id = game_id.to_string();
+            thread::spawn(move || {
+                thread::sleep(think_time);
+                main_tx.send(ServerCommand::BotTurn(game_id)).ok();
+            });
+        }
+    }
+
+    fn play_bot_turn(&mut self, game_id: &str) {
+        let game_session = match self.sessions.get_mut(game_id) {
+            Some(game_session) => game_session,
+            None => return,
+        };
+
+        if let Some(bot) = game_session.bot.as_mut() {
+            let bot_id = bot.player.id.clone();
+            match bot.play_turn(&mut game_session.game) {
+                Ok(message) => {
+                    if let Err(err_message) = self.dispatch_game_message(game_id, &bot_id, message)
+                    {
+                        println!("Bot error: {}", err_message);
+                    }
+                }
+                Err(err_message) => println!("Bot error: {}", err_message),
+            }
+        }
+    }
+
+    fn expire_turns(&mut self, now: DateTime<Local>) {
+        let mut expired = Vec::new();
+        let restored = &self.restored;
+
+        for (game_id, game_session) in self.sessions.iter_mut() {
+            // Absent players of a restored game do not lose lives before they can resume
+            if let Some(resume_until) = game_session.resume_until {
+                let awaited = restored.values().any(|player| {
+                    player.token.is_some() && player.game_id.as_ref() == Some(game_id)
+                });
+                if awaited && now < resume_until {
+                    continue;
+                }
+                game_session.resume_until = None;
+                game_session.game.restart_turn_clock(now);
+            }
+
+            let turn_player = match game_session.game.turn_player() {
+                Some(turn_player) => turn_player.id.clone(),
+                None => continue,
+            };
+
+            if let Some(message) = game_session.game.expire_turn(now) {
+                expired.push((game_id.clone(), turn_player, message));
+            }
+        }
+
+        for (game_id, player_id, message) in expired {
+            if let Err(err_message) = self.dispatch_game_message(&game_id, &player_id, message) {
+                println!("Turn timer error: {}", err_message);
+            }
+        }
+    }
+
+    fn finish_game(&mut self, game_id: &str) {
+        if let Some(game_session) = self.sessions.remove(game_id) {
+            if *game_session.game.mode() == GameMode::Finished {
+                let record = GameRecord::from(&game_session.game);
+                self.ratings.record(&record);
+
+                if let Some(history) = &self.history {
+                    if let Err(error) = history.save(&record) {
+                        println!("Game {} not recorded: {}", game_id, error);
+                    }
+                }
+            }
+
+            for player in game_session.game.players() {
+                if let Some(session) = self.players.get_mut(&player.id) {
+                    session.game_id = None;
+                }
+            }
+            for spectator_id in game_session.spectators.iter() {
+                if let Some(session) = self.players.get_mut(spectator_id) {
+                    session.watching = None;
+                }
+            }
+            self.record_series(&game_session);
+
+            if let Some(tournament_id) = &game_session.tournament_id {
+                if let Some(tournament) = self.tournaments.get_mut(tournament_id) {
+                    // Leaving before the game started hands it to the opponent.
+                    let game = &game_session.game;
+                    let winner = match (game.mode(), game.players().as_slice()) {
+                        (GameMode::Finished, _) => game.winner(),
+                        (_, [opponent]) => Some(*opponent),
+                        _ => None,
+                    }
+                    .map(|winner| winner.id.as_str());
+                    tournament.game_finished(game_id, winner);
+                }
+                self.progress_tournament(tournament_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accept_connections, Server, RESUME_GRACE_SECONDS};
+    use crate::bot::{BotChallenge, StrategyKind};
+    use crate::chat::Blocklist;
+    use crate::game::GameRules;
+    use crate::history::History;
+    use crate::messages::ServerCommand;
+    use crate::protocol::{
+        ChatChannel, ChatMessage, ConnectionConstraints, ConnectionLimits, Frame, GameEvent,
+        GameInfo, Guess, UserInfo, CHAT_GAME, CHAT_LOBBY, CHAT_MESSAGE, CRACKED, ERROR, GAME,
+        GAMES, GAME_AGAINST_BOT, GAME_AGAINST_HOUSE, GAME_CREATE, GAME_EVENT, GAME_JOIN, GAME_LIST,
+        GUESS, GUESS_AT, HEARTBEAT, HEARTBEAT_OK, LEADERBOARD, MATCHMAKING, MATCHMAKING_CANCEL,
+        MATCH_CREATE, MATCH_RESULT, NOT_YOUR_TURN, REMATCH, REMATCH_OFFER, SECRET_NUMBER, SERIES,
+        TOURNAMENT, TOURNAMENTS, TOURNAMENT_CREATE, TOURNAMENT_JOIN, TOURNAMENT_LEAVE,
+        TOURNAMENT_LIST, TURN, UNWATCH, USER, WATCH, WRONG_ANSWER, YOU_LOSE, YOU_WIN,
+    };
+    use crate::rating::INITIAL_RATING;
+    use crate::series::{MatchResult, MatchRules, SeriesInfo};
+    use crate::snapshot::Snapshot;
+    use crate::stats::{LeaderboardEntry, Period};
+    use crate::tournament::{TournamentFormat, TournamentRules};
+    use chrono::{Duration, Local};
+    use std::collections::HashMap;
+    use std::env;
+    use std::fs;
+    use std::net::{IpAddr, TcpListener, TcpStream};
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+
+    fn register(server: &mut Server, name: &str) -> (String, Receiver<Frame>) {
+        let (outbox, inbox) = channel::<Frame>();
+        let user = UserInfo {
+            id: name.to_lowercase(),
+            name: String::from(name),
+            stats: None,
+            token: None,
+        };
+        let id = user.id.clone();
+        server.register_player(user, outbox);
+        assert_eq!(USER, inbox.try_recv().unwrap().method());
+        (id, inbox)
+    }
+
+    fn methods(inbox: &Receiver<Frame>) -> Vec<(u8, u8)> {
+        inbox.try_iter().map(|frame| frame.method()).collect()
+    }
+
+    fn started_game(server: &mut Server) -> (Receiver<Frame>, Receiver<Frame>) {
+        let (chico, chico_inbox) = register(server, "Chico");
+        let (paloma, paloma_inbox) = register(server, "Paloma");
+
+        server
+            .handle_frame(&chico, Frame::json(GAME_CREATE, &GameRules::default()))
+            .unwrap();
+        let game_id = server.players["chico"].game_id.clone().unwrap();
+        server
+            .handle_frame(&paloma, Frame::text(GAME_JOIN, &game_id))
+            .unwrap();
+        server
+            .handle_frame(&chico, Frame::number(SECRET_NUMBER, 43))
+            .unwrap();
+        server
+            .handle_frame(&paloma, Frame::number(SECRET_NUMBER, 42))
+            .unwrap();
+
+        (chico_inbox, paloma_inbox)
+    }
+
+    #[test]
+    fn test_create_and_list_games() {
+        let mut server = Server::new();
+        let (chico, chico_inbox) = register(&mut server, "Chico");
+        let (paloma, paloma_inbox) = register(&mut server, "Paloma");
+
+        server
+            .handle_frame(&chico, Frame::json(GAME_CREATE, &GameRules::default()))
+            .unwrap();
+        server
+            .handle_frame(&paloma, Frame::empty(GAME_LIST))
+            .unwrap();
+
+        assert_eq!(vec![GAME], methods(&chico_inbox));
+
+        let games = paloma_inbox.try_recv().unwrap();
+        assert_eq!(GAMES, games.method());
+        assert_eq!(
+            1,
+            games
+                .json_payload::<Vec<serde_json::Value>>()
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_accept_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let (main_tx, main_rx) = channel::<ServerCommand>();
+        let acceptor = thread::spawn(move || {
+            accept_connections(listener, main_tx, ServerCommand::AcceptedConnection)
+        });
+
+        let _client = TcpStream::connect(address).unwrap();
+        match main_rx.recv().unwrap() {
+            ServerCommand::AcceptedConnection(..) => {}
+            _ => panic!("Accepted connection expected"),
+        }
+
+        // The command loop is gone, the accept thread ends without panicking
+        drop(main_rx);
+        let _client = TcpStream::connect(address).unwrap();
+        acceptor.join().unwrap();
+    }
+
+    #[test]
+    fn test_connection_limits() {
+        let mut server = Server::new().with_connection_limits(ConnectionLimits {
+            max_connections: 2,
+            max_connections_per_ip: 1,
+            handshake_seconds: 1,
+        });
+        let (main_tx, main_rx) = channel::<ServerCommand>();
+        let chico: IpAddr = "10.0.0.1".parse().unwrap();
+        let harpo: IpAddr = "10.0.0.2".parse().unwrap();
+        let groucho: IpAddr = "10.0.0.3".parse().unwrap();
+
+        let chico_slot = server.admit(chico, &main_tx).unwrap();
+        assert_eq!(
+            Some(String::from("Too many connections from your address")),
+            server.admit(chico, &main_tx).err()
+        );
+
+        let _harpo_slot = server.admit(harpo, &main_tx).unwrap();
+        assert_eq!(
+            Some(String::from("Server is full")),
+            server.admit(groucho, &main_tx).err()
+        );
+
+        // A connection ending gives its slot back
+        drop(chico_slot);
+        assert!(server.handle_command(main_rx.recv().unwrap()));
+        assert!(server.admit(groucho, &main_tx).is_ok());
+    }
+
+    #[test]
+    fn test_connection_over_limits_turned_down() {
+        let mut server = Server::new().with_connection_limits(ConnectionLimits {
+            max_connections: 0,
+            ..ConnectionLimits::default()
+        });
+        let (main_tx, main_rx) = channel::<ServerCommand>();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        // Turned down on the spot, the client has not sent its header
+        let accepted = ServerCommand::AcceptedConnection(stream, box main_tx.clone());
+        assert!(server.handle_command(accepted));
+        assert_eq!(
+            Frame::text(ERROR, "Server is full"),
+            Frame::read_from(&mut client).unwrap()
+        );
+        assert!(main_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_heartbeat() {
+        let mut server = Server::new();
+        let (chico, chico_inbox) = register(&mut server, "Chico");
+
+        server
+            .handle_frame(&chico, Frame::empty(HEARTBEAT))
+            .unwrap();
+        assert_eq!(vec![HEARTBEAT_OK], methods(&chico_inbox));
+    }
+
+    #[test]
+    fn test_create_game_invalid_rules() {
+        let mut server = Server::new();
+        let (chico, _) = register(&mut server, "Chico");
+        let rules = GameRules {
+            lives: 0,
+            ..GameRules::default()
+        };
+
+        assert_eq!(
+            Err(String::from("Players must start with at least one life")),
+            server.handle_frame(&chico, Frame::json(GAME_CREATE, &rules))
+        );
+    }
+
+    #[test]
+    fn test_game_over_the_wire() {
+        let mut server = Server::new();
+        let (chico_inbox, paloma_inbox) = started_game(&mut server);
+
+        assert_eq!(vec![GAME, GAME, GAME, TURN], methods(&chico_inbox));
+        assert_eq!(vec![GAME, GAME], methods(&paloma_inbox));
+
+        server
+            .handle_frame("paloma", Frame::number(GUESS, 43))
+            .unwrap();
+        assert_eq!(vec![NOT_YOUR_TURN], methods(&paloma_inbox));
+
+        server
+            .handle_frame("chico", Frame::number(GUESS, 1))
+            .unwrap();
+        assert_eq!(vec![WRONG_ANSWER], methods(&chico_inbox));
+        assert_eq!(vec![TURN], methods(&paloma_inbox));
+
+        server
+            .handle_frame("paloma", Frame::number(GUESS, 43))
+            .unwrap();
+        assert_eq!(vec![YOU_WIN, SERIES], methods(&paloma_inbox));
+        assert_eq!(vec![YOU_LOSE, SERIES], methods(&chico_inbox));
+        assert!(server.sessions.is_empty());
+    }
+
+    #[test]
+    fn test_rematch() {
+        let mut server = Server::new();
+        let (chico_inbox, paloma_inbox) = started_game(&mut server);
+        server
+            .handle_frame("chico", Frame::number(GUESS, 42))
+            .unwrap();
+        chico_inbox.try_iter().count();
+        paloma_inbox.try_iter().count();
+
+        server
+            .handle_frame("paloma", Frame::empty(REMATCH))
+            .unwrap();
+        let offer = chico_inbox.try_recv().unwrap();
+        assert_eq!(REMATCH_OFFER, offer.method());
+        assert_eq!(Ok(String::from("Paloma")), offer.text_payload());
+
+        server.handle_frame("chico", Frame::empty(REMATCH)).unwrap();
+        assert_eq!(vec![GAME], methods(&chico_inbox));
+        assert_eq!(vec![GAME], methods(&paloma_inbox));
+
+        server
+            .handle_frame("chico", Frame::number(SECRET_NUMBER, 43))
+            .unwrap();
+        server
+            .handle_frame("paloma", Frame::number(SECRET_NUMBER, 42))
+            .unwrap();
+        assert_eq!(vec![GAME, TURN], methods(&paloma_inbox));
+        chico_inbox.try_iter().count();
+
+        server
+            .handle_frame("paloma", Frame::number(GUESS, 43))
+            .unwrap();
+        let series: SeriesInfo = paloma_inbox
+            .try_iter()
+            .find(|frame| frame.method() == SERIES)
+            .unwrap()
+            .json_payload()
+            .unwrap();
+        assert_eq!(2, series.games);
+        assert_eq!(1, series.scores[0].wins);
+        assert_eq!(1, series.scores[1].wins);
+    }
+
+    #[test]
+    fn test_best_of_three_match() {
+        let mut server = Server::new();
+        let (chico, chico_inbox) = register(&mut server, "Chico");
+        let (paloma, paloma_inbox) = register(&mut server, "Paloma");
+        let match_rules = |best_of| MatchRules {
+            best_of,
+            rules: GameRules::default(),
+        };
+
+        assert_eq!(
+            Err(String::from("Matches are best of 3, 5 or 7")),
+            server.handle_frame(&chico, Frame::json(MATCH_CREATE, &match_rules(2)))
+        );
+        server
+            .handle_frame(&chico, Frame::json(MATCH_CREATE, &match_rules(3)))
+            .unwrap();
+        let game_id = server.players["chico"].game_id.clone().unwrap();
+        server
+            .handle_frame(&paloma, Frame::text(GAME_JOIN, &game_id))
+            .unwrap();
+
+        for first_player in [&chico, &paloma] {
+            server
+                .handle_frame(&chico, Frame::number(SECRET_NUMBER, 43))
+                .unwrap();
+            server
+                .handle_frame(&paloma, Frame::number(SECRET_NUMBER, 42))
+                .unwrap();
+            if first_player == &paloma {
+                server
+                    .handle_frame(&paloma, Frame::number(GUESS, 1))
+                    .unwrap();
+            }
+            server
+                .handle_frame(&chico, Frame::number(GUESS, 42))
+                .unwrap();
+        }
+
+        assert!(server.sessions.is_empty());
+        assert!(server.series.is_empty());
+        assert_eq!(
+            vec![YOU_WIN, SERIES, YOU_WIN, SERIES, MATCH_RESULT],
+            methods(&chico_inbox)
+                .into_iter()
+                .filter(|method| [YOU_WIN, SERIES, MATCH_RESULT].contains(method))
+                .collect::<Vec<(u8, u8)>>()
+        );
+        let result: MatchResult = paloma_inbox
+            .try_iter()
+            .last()
+            .unwrap()
+            .json_payload()
+            .unwrap();
+        assert_eq!("Chico", result.winner.name);
+        assert_eq!(Some(3), result.series.best_of);
+    }
+
+    #[test]
+    fn test_rematch_after_opponent_left() {
+        let mut server = Server::new();
+        started_game(&mut server);
+        server
+            .handle_frame("chico", Frame::number(GUESS, 42))
+            .unwrap();
+        server.disconnect_player("paloma");
+
+        assert_eq!(
+            Err(String::from("No game to rematch")),
+            server.handle_frame("chico", Frame::empty(REMATCH))
+        );
+    }
+
+    #[test]
+    fn test_rematch_after_joining_another_game() {
+        let mut server = Server::new();
+        started_game(&mut server);
+        server
+            .handle_frame("chico", Frame::number(GUESS, 42))
+            .unwrap();
+        let series_id = server.players["chico"].series_id.clone().unwrap();
+        let (allan, _) = register(&mut server, "Allan");
+        server
+            .handle_frame(&allan, Frame::json(GAME_CREATE, &GameRules::default()))
+            .unwrap();
+        let game_id = server.players["allan"].game_id.clone().unwrap();
+
+        // A series game can not take a player out of a game going on
+        server.players.get_mut("chico").unwrap().game_id = Some(game_id.clone());
+        assert_eq!(
+            Err(String::from("Player is in another game")),
+            server.start_series_game(&series_id)
+        );
+        server.players.get_mut("chico").unwrap().game_id = None;
+
+        server
+            .handle_frame("paloma", Frame::empty(REMATCH))
+            .unwrap();
+        server
+            .handle_frame("chico", Frame::text(GAME_JOIN, &game_id))
+            .unwrap();
+        assert!(server.series.is_empty());
+        assert_eq!(
+            Err(String::from("No game to rematch")),
+            server.handle_frame("paloma", Frame::empty(REMATCH))
+        );
+        assert_eq!(Some(game_id), server.players["chico"].game_id);
+    }
+
+    #[test]
+    fn test_finished_games_are_recorded() {
+        let path = env::temp_dir().join(format!("history-{}.jsonl", uuid::Uuid::new_v4()));
+        let mut server = Server::new().with_history(History::open(&path));
+        started_game(&mut server);
+
+        server
+            .handle_frame("chico", Frame::number(GUESS, 42))
+            .unwrap();
+
+        let records = History::open(&path).records().unwrap();
+        assert_eq!(1, records.len());
+        assert_eq!(Some(String::from("chico")), records[0].winner);
+        assert_eq!(Ok(()), records[0].replay());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_stats_and_leaderboard() {
+        let path = env::temp_dir().join(format!("history-{}.jsonl", uuid::Uuid::new_v4()));
+        let mut server = Server::new().with_history(History::open(&path));
+        let (_, paloma_inbox) = started_game(&mut server);
+        server
+            .handle_frame("chico", Frame::number(GUESS, 42))
+            .unwrap();
+        paloma_inbox.try_iter().count();
+
+        // Stats follow the player id, another Chico starts from scratch
+        let wins = |server: &mut Server, id: &str| {
+            let (outbox, inbox) = channel::<Frame>();
+            let user = UserInfo {
+                id: String::from(id),
+                name: String::from("Chico"),
+                stats: None,
+                token: None,
+            };
+            server.register_player(user, outbox);
+            let user: UserInfo = inbox.try_recv().unwrap().json_payload().unwrap();
+            user.stats.unwrap().wins
+        };
+        assert_eq!(0, wins(&mut server, "impostor"));
+        server.disconnect_player("chico");
+        assert_eq!(1, wins(&mut server, "chico"));
+
+        server
+            .handle_frame("paloma", Frame::json(LEADERBOARD, &Period::LastWeek))
+            .unwrap();
+        let entries: Vec<LeaderboardEntry> =
+            paloma_inbox.try_recv().unwrap().json_payload().unwrap();
+        let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(vec!["Chico", "Paloma"], names);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_accounts() {
+        let mut server = Server::new();
+        let sign_up = |server: &mut Server, id: &str, name: &str| {
+            let (outbox, inbox) = channel::<Frame>();
+            let user = UserInfo {
+                id: String::from(id),
+                name: String::from(name),
+                stats: None,
+                token: None,
+            };
+            server.sign_up(user, outbox).map(|_| inbox)
+        };
+        let resume = |server: &mut Server, id: &str, token: Option<String>| {
+            let (outbox, inbox) = channel::<Frame>();
+            let user = UserInfo {
+                id: String::from(id),
+                name: String::from("Someone"),
+                stats: None,
+                token,
+            };
+            server.resume_player(user, outbox).map(|_| inbox)
+        };
+
+        let chico_inbox = sign_up(&mut server, "chico", "Chico").unwrap();
+        let token = chico_inbox
+            .try_recv()
+            .unwrap()
+            .json_payload::<UserInfo>()
+            .unwrap()
+            .token;
+        assert_eq!(
+            Err(String::from("Name is taken, resume with its token")),
+            sign_up(&mut server, "impostor", "chico").map(|_| ())
+        );
+        assert_eq!(
+            Err(String::from("Player is already connected")),
+            resume(&mut server, "chico", token.clone()).map(|_| ())
+        );
+
+        // The account brings the player back with the same id and name
+        server.disconnect_player("chico");
+        assert_eq!(
+            Err(String::from("Nothing to resume for this player")),
+            resume(&mut server, "chico", None).map(|_| ())
+        );
+        let chico_inbox = resume(&mut server, "chico", token.clone()).unwrap();
+        let user: UserInfo = chico_inbox.try_recv().unwrap().json_payload().unwrap();
+        assert_eq!(("chico", "Chico"), (user.id.as_str(), user.name.as_str()));
+        assert_eq!(token, user.token);
+    }
+
+    #[test]
+    fn test_matchmaking() {
+        let mut server = Server::new();
+        let (chico, chico_inbox) = register(&mut server, "Chico");
+        let (paloma, paloma_inbox) = register(&mut server, "Paloma");
+        let (allan, _) = register(&mut server, "Allan");
+
+        server
+            .handle_frame(&chico, Frame::empty(MATCHMAKING))
+            .unwrap();
+        assert_eq!(
+            Err(String::from("Player is already waiting for an opponent")),
+            server.handle_frame(&chico, Frame::empty(MATCHMAKING))
+        );
+        server
+            .handle_frame(&allan, Frame::empty(MATCHMAKING))
+            .unwrap();
+        server
+            .handle_frame(&allan, Frame::empty(MATCHMAKING_CANCEL))
+            .unwrap();
+        server
+            .handle_frame(&paloma, Frame::empty(MATCHMAKING))
+            .unwrap();
+
+        server.match_players(Local::now());
+        assert_eq!(vec![GAME, GAME], methods(&chico_inbox));
+        assert_eq!(vec![GAME], methods(&paloma_inbox));
+        assert_eq!(
+            server.players["chico"].game_id,
+            server.players["paloma"].game_id
+        );
+        assert_eq!(None, server.players["allan"].game_id);
+    }
+
+    #[test]
+    fn test_finished_games_update_ratings() {
+        let mut server = Server::new();
+        started_game(&mut server);
+        server
+            .handle_frame("chico", Frame::number(GUESS, 42))
+            .unwrap();
+
+        assert!(server.ratings.rating("chico") > server.ratings.rating("paloma"));
+    }
+
+    #[test]
+    fn test_bot_games_are_not_rated() {
+        let mut server = Server::new();
+        let (main_tx, _main_rx) = channel::<ServerCommand>();
+        server.main_tx = Some(main_tx);
+        let (chico, _chico_inbox) = register(&mut server, "Chico");
+        let challenge = BotChallenge {
+            rules: GameRules::default(),
+            strategy: StrategyKind::Weak,
+        };
+        server
+            .handle_frame(&chico, Frame::json(GAME_AGAINST_BOT, &challenge))
+            .unwrap();
+        let game_id = server.players["chico"].game_id.clone().unwrap();
+        let game = &server.sessions[&game_id].game;
+        game.players()[1]
+            .set_secret_number(42, game.rules())
+            .unwrap();
+
+        // Beating the bot is easy, it does not raise the rating
+        server
+            .handle_frame(&chico, Frame::number(SECRET_NUMBER, 43))
+            .unwrap();
+        server
+            .handle_frame(&chico, Frame::number(GUESS, 42))
+            .unwrap();
+        assert!(!server.sessions.contains_key(&game_id));
+        assert_eq!(INITIAL_RATING, server.ratings.rating("chico"));
+    }
+
+    #[test]
+    fn test_turn_timer() {
+        let mut server = Server::new();
+        let (chico_inbox, paloma_inbox) = started_game(&mut server);
+        chico_inbox.try_iter().count();
+        paloma_inbox.try_iter().count();
+
+        server.expire_turns(Local::now() + Duration::seconds(30));
+
+        let hint = chico_inbox.try_recv().unwrap();
+        assert_eq!(WRONG_ANSWER, hint.method());
+        assert_eq!(Ok(String::from("Turn time is over")), hint.text_payload());
+        assert_eq!(vec![TURN], methods(&paloma_inbox));
+    }
+
+    #[test]
+    fn test_against_house() {
+        let mut server = Server::new();
+        let (chico, chico_inbox) = register(&mut server, "Chico");
+
+        server
+            .handle_frame(
+                &chico,
+                Frame::json(GAME_AGAINST_HOUSE, &GameRules::default()),
+            )
+            .unwrap();
+
+        assert_eq!(vec![GAME, TURN], methods(&chico_inbox));
+    }
+
+    #[test]
+    fn test_against_bot() {
+        let mut server = Server::new();
+        let (main_tx, main_rx) = channel::<ServerCommand>();
+        server.main_tx = Some(main_tx);
+        let (chico, chico_inbox) = register(&mut server, "Chico");
+        let challenge = BotChallenge {
+            rules: GameRules {
+                turn_seconds: 1,
+                ..GameRules::default()
+            },
+            strategy: StrategyKind::Weak,
+        };
+
+        server
+            .handle_frame(&chico, Frame::json(GAME_AGAINST_BOT, &challenge))
+            .unwrap();
+        // Clients can tell the bot from a human opponent
+        let game: GameInfo = chico_inbox.try_recv().unwrap().json_payload().unwrap();
+        assert!(!game.players[0].bot);
+        assert!(game.players[1].bot);
+        assert!(game.players[1].id.starts_with("bot:Weak:"));
+        server
+            .handle_frame(&chico, Frame::number(SECRET_NUMBER, 100))
+            .unwrap();
+        server.expire_turns(Local::now() + Duration::seconds(1));
+        chico_inbox.try_iter().count();
+
+        let game_id = match main_rx.recv().unwrap() {
+            ServerCommand::BotTurn(game_id) => game_id,
+            _ => panic!("Bot turn expected"),
+        };
+        server.play_bot_turn(&game_id);
+
+        assert_eq!(vec![TURN], methods(&chico_inbox));
+    }
+
+    #[test]
+    fn test_already_in_a_game() {
+        let mut server = Server::new();
+        let (chico, _) = register(&mut server, "Chico");
+
+        server
+            .handle_frame(&chico, Frame::json(GAME_CREATE, &GameRules::default()))
+            .unwrap();
+
+        assert_eq!(
+            Err(String::from("Player is already in a game")),
+            server.handle_frame(&chico, Frame::json(GAME_CREATE, &GameRules::default()))
+        );
+    }
+
+    #[test]
+    fn test_multiplayer_game() {
+        let mut server = Server::new();
+        let (chico, chico_inbox) = register(&mut server, "Chico");
+        let (paloma, _) = register(&mut server, "Paloma");
+        let (allan, allan_inbox) = register(&mut server, "Allan");
+        let rules = GameRules {
+            max_players: 3,
+            ..GameRules::default()
+        };
+
+        server
+            .handle_frame(&chico, Frame::json(GAME_CREATE, &rules))
+            .unwrap();
+        let game_id = server.players["chico"].game_id.clone().unwrap();
+        for (player_id, secret_number) in [(&paloma, 11), (&allan, 12), (&chico, 10)] {
+            server
+                .handle_frame(player_id, Frame::text(GAME_JOIN, &game_id))
+                .ok();
+            server
+                .handle_frame(player_id, Frame::number(SECRET_NUMBER, secret_number))
+                .unwrap();
+        }
+        chico_inbox.try_iter().count();
+        allan_inbox.try_iter().count();
+
+        let guess = Guess {
+            target: Some(allan.clone()),
+            number: 12,
+        };
+        server
+            .handle_frame(&chico, Frame::json(GUESS_AT, &guess))
+            .unwrap();
+
+        let cracked = chico_inbox.try_recv().unwrap();
+        assert_eq!(CRACKED, cracked.method());
+        assert_eq!(Ok(String::from("Allan")), cracked.text_payload());
+
+        server
+            .handle_frame(&paloma, Frame::number(GUESS, 1))
+            .unwrap();
+        server
+            .handle_frame(&allan, Frame::number(GUESS, 1))
+            .unwrap();
+
+        let hint = allan_inbox
+            .try_iter()
+            .find(|frame| frame.method() == WRONG_ANSWER)
+            .unwrap();
+        assert_eq!(
+            Ok(String::from("Chico: Secret number is greater than 1")),
+            hint.text_payload()
+        );
+    }
+
+    #[test]
+    fn test_spectator() {
+        let mut server = Server::new();
+        let (chico, _) = register(&mut server, "Chico");
+        let (paloma, _) = register(&mut server, "Paloma");
+        let (allan, allan_inbox) = register(&mut server, "Allan");
+
+        server
+            .handle_frame(&chico, Frame::json(GAME_CREATE, &GameRules::default()))
+            .unwrap();
+        let game_id = server.players["chico"].game_id.clone().unwrap();
+        server
+            .handle_frame(&allan, Frame::text(WATCH, &game_id))
+            .unwrap();
+        assert_eq!(vec![GAME], methods(&allan_inbox));
+
+        server
+            .handle_frame(&paloma, Frame::text(GAME_JOIN, &game_id))
+            .unwrap();
+        server
+            .handle_frame(&chico, Frame::number(SECRET_NUMBER, 43))
+            .unwrap();
+        server
+            .handle_frame(&paloma, Frame::number(SECRET_NUMBER, 42))
+            .unwrap();
+        server
+            .handle_frame(&chico, Frame::number(GUESS, 1))
+            .unwrap();
+
+        let frames: Vec<Frame> = allan_inbox.try_iter().collect();
+        let methods: Vec<(u8, u8)> = frames.iter().map(|frame| frame.method()).collect();
+        assert_eq!(
+            vec![GAME, GAME, GAME_EVENT, GAME_EVENT, GAME_EVENT],
+            methods
+        );
+        assert_eq!(
+            GameEvent::Guess {
+                player: chico.clone(),
+                target: paloma.clone(),
+                number: 1,
+                hint: Some(String::from("Secret number is greater than 1")),
+            },
+            frames[3].json_payload().unwrap()
+        );
+
+        let game_info: serde_json::Value = frames[1].json_payload().unwrap();
+        assert_eq!(
+            serde_json::Value::Null,
+            game_info["players"][0]["secret_number"]
+        );
+
+        server
+            .handle_frame(&paloma, Frame::number(GUESS, 43))
+            .unwrap();
+        let game_info: serde_json::Value = allan_inbox
+            .try_iter()
+            .filter(|frame| frame.method() == GAME)
+            .last()
+            .unwrap()
+            .json_payload()
+            .unwrap();
+        assert_eq!(43, game_info["players"][0]["secret_number"]);
+        assert_eq!(None, server.players["allan"].watching);
+    }
+
+    #[test]
+    fn test_spectators_cap() {
+        let mut server = Server::new();
+        let (chico, _) = register(&mut server, "Chico");
+        server
+            .handle_frame(&chico, Frame::json(GAME_CREATE, &GameRules::default()))
+            .unwrap();
+        let game_id = server.players["chico"].game_id.clone().unwrap();
+
+        for spectator in 0..super::MAX_SPECTATORS {
+            let (spectator_id, _) = register(&mut server, &format!("S{}", spectator));
+            server
+                .handle_frame(&spectator_id, Frame::text(WATCH, &game_id))
+                .unwrap();
+        }
+
+        let (allan, _) = register(&mut server, "Allan");
+        assert_eq!(
+            Err(String::from("This game has too many spectators")),
+            server.handle_frame(&allan, Frame::text(WATCH, &game_id))
+        );
+
+        server.handle_frame("s0", Frame::empty(UNWATCH)).unwrap();
+        assert_eq!(
+            Ok(()),
+            server.handle_frame(&allan, Frame::text(WATCH, &game_id))
+        );
+    }
+
+    #[test]
+    fn test_chat() {
+        let mut server = Server::new().with_chat_filter(Box::new(Blocklist::new(&["heck"])));
+        let (allan, allan_inbox) = register(&mut server, "Allan");
+
+        assert_eq!(
+            Err(String::from("Player is not in a game")),
+            server.handle_frame(&allan, Frame::text(CHAT_GAME, "Hi"))
+        );
+
+        let (chico_inbox, paloma_inbox) = started_game(&mut server);
+        chico_inbox.try_iter().count();
+        paloma_inbox.try_iter().count();
+
+        server
+            .handle_frame(&allan, Frame::text(CHAT_LOBBY, "What the heck"))
+            .unwrap();
+        for inbox in [&chico_inbox, &paloma_inbox, &allan_inbox] {
+            let message: ChatMessage = inbox.try_recv().unwrap().json_payload().unwrap();
+            assert_eq!(ChatChannel::Lobby, message.channel);
+            assert_eq!("Allan", message.from.name);
+            assert_eq!("What the ****", message.text);
+        }
+
+        server
+            .handle_frame("chico", Frame::text(CHAT_GAME, "Good luck"))
+            .unwrap();
+        assert_eq!(vec![CHAT_MESSAGE], methods(&paloma_inbox));
+        assert_eq!(vec![CHAT_MESSAGE], methods(&chico_inbox));
+        assert!(methods(&allan_inbox).is_empty());
+    }
+
+    #[test]
+    fn test_chat_limits() {
+        let mut server = Server::new();
+        let (chico, _) = register(&mut server, "Chico");
+        let constraints = ConnectionConstraints::default();
+
+        assert_eq!(
+            Err(String::from("Message must have 1 to 200 bytes")),
+            server.handle_frame(&chico, Frame::text(CHAT_LOBBY, &"a".repeat(201)))
+        );
+
+        for _ in 0..constraints.max_chat_messages {
+            server
+                .handle_frame(&chico, Frame::text(CHAT_LOBBY, "Anyone?"))
+                .unwrap();
+        }
+        assert_eq!(
+            Err(String::from("Too many messages, slow down")),
+            server.handle_frame(&chico, Frame::text(CHAT_LOBBY, "Anyone?"))
+        );
+    }
+
+    #[test]
+    fn test_disconnect_gives_opponent_the_win() {
+        let mut server = Server::new();
+        let (_, paloma_inbox) = started_game(&mut server);
+        paloma_inbox.try_iter().count();
+
+        server.disconnect_player("chico");
+
+        assert_eq!(vec![YOU_WIN], methods(&paloma_inbox));
+        assert!(server.sessions.is_empty());
+    }
+
+    fn tournament_rules(players: u8) -> TournamentRules {
+        TournamentRules {
+            format: TournamentFormat::SingleElimination,
+            players,
+            rules: GameRules::default(),
+        }
+    }
+
+    fn last_tournament(inbox: &Receiver<Frame>) -> serde_json::Value {
+        inbox
+            .try_iter()
+            .filter(|frame| frame.method() == TOURNAMENT)
+            .last()
+            .unwrap()
+            .json_payload()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_tournament() {
+        let mut server = Server::new();
+        let (chico, chico_inbox) = register(&mut server, "Chico");
+        let (paloma, _) = register(&mut server, "Paloma");
+        let (allan, allan_inbox) = register(&mut server, "Allan");
+
+        server
+            .handle_frame(&chico, Frame::json(TOURNAMENT_CREATE, &tournament_rules(3)))
+            .unwrap();
+        assert_eq!(
+            Err(String::from("Player is registered in a tournament")),
+            server.handle_frame(&chico, Frame::json(GAME_CREATE, &GameRules::default()))
+        );
+        server
+            .handle_frame(&allan, Frame::empty(TOURNAMENT_LIST))
+            .unwrap();
+        let tournaments = allan_inbox.try_recv().unwrap();
+        assert_eq!(TOURNAMENTS, tournaments.method());
+        let tournament_id = tournaments
+            .json_payload::<Vec<serde_json::Value>>()
+            .unwrap()[0]["id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        for player_id in [&paloma, &allan] {
+            server
+                .handle_frame(player_id, Frame::text(TOURNAMENT_JOIN, &tournament_id))
+                .unwrap();
+        }
+        assert!(server.players["allan"].game_id.is_none());
+        assert_eq!(
+            server.players["chico"].game_id,
+            server.players["paloma"].game_id
+        );
+
+        server
+            .handle_frame(&chico, Frame::number(SECRET_NUMBER, 43))
+            .unwrap();
+        server
+            .handle_frame(&paloma, Frame::number(SECRET_NUMBER, 42))
+            .unwrap();
+        server
+            .handle_frame(&chico, Frame::number(GUESS, 42))
+            .unwrap();
+
+        assert!(server.players["paloma"].game_id.is_none());
+        assert_eq!(
+            server.players["chico"].game_id,
+            server.players["allan"].game_id
+        );
+        assert_eq!("InProgress", last_tournament(&allan_inbox)["state"]);
+
+        server.disconnect_player(&allan);
+        let tournament = last_tournament(&chico_inbox);
+        assert_eq!("Finished", tournament["state"]);
+        assert_eq!("chico", tournament["winner"]);
+        assert!(server.players["chico"].tournament_id.is_none());
+        assert!(server.sessions.is_empty());
+    }
+
+    #[test]
+    fn test_leave_tournament() {
+        let mut server = Server::new();
+        let (chico, _) = register(&mut server, "Chico");
+
+        server
+            .handle_frame(&chico, Frame::json(TOURNAMENT_CREATE, &tournament_rules(4)))
+            .unwrap();
+        server
+            .handle_frame(&chico, Frame::empty(TOURNAMENT_LEAVE))
+            .unwrap();
+
+        assert!(server.tournaments.is_empty());
+        assert_eq!(
+            Err(String::from("Player is not registered in a tournament")),
+            server.handle_frame(&chico, Frame::empty(TOURNAMENT_LEAVE))
+        );
+    }
+
+    #[test]
+    fn test_snapshot_and_resume() {
+        let path = env::temp_dir().join(format!("snapshot-{}.json", uuid::Uuid::new_v4()));
+        let mut server = Server::new().with_snapshot(Snapshot::open(&path));
+        started_game(&mut server);
+        server
+            .handle_frame("chico", Frame::number(GUESS, 1))
+            .unwrap();
+        let token = |server: &Server, id: &str| Some(server.players[id].token.clone());
+        let tokens = HashMap::from([
+            ("chico", token(&server, "chico")),
+            ("paloma", token(&server, "paloma")),
+        ]);
+        server.save_snapshot();
+
+        let mut server = Server::new().with_snapshot(Snapshot::open(&path));
+        // Kept until the server listens, a failed start restores it again
+        assert!(path.exists());
+        fs::remove_file(&path).unwrap();
+        let resume = |server: &mut Server, id: &str, token: Option<String>| {
+            let (outbox, inbox) = channel::<Frame>();
+            let user = UserInfo {
+                id: String::from(id),
+                name: String::from("Someone"),
+                stats: None,
+                token,
+            };
+            server.resume_player(user, outbox).map(|_| inbox)
+        };
+
+        assert_eq!(
+            Err(String::from("Nothing to resume for this player")),
+            resume(&mut server, "allan", None).map(|_| ())
+        );
+        // Player ids are public, taking over a seat needs the token
+        assert_eq!(
+            Err(String::from("Nothing to resume for this player")),
+            resume(&mut server, "paloma", tokens["chico"].clone()).map(|_| ())
+        );
+        assert!(resume(&mut server, "paloma", None).is_err());
+
+        let paloma_inbox = resume(&mut server, "paloma", tokens["paloma"].clone()).unwrap();
+        let user: UserInfo = paloma_inbox.try_recv().unwrap().json_payload().unwrap();
+        assert_eq!("Paloma", user.name);
+        assert_eq!(tokens["paloma"], user.token);
+        assert_eq!(vec![GAME, TURN], methods(&paloma_inbox));
+
+        let chico_inbox = resume(&mut server, "chico", tokens["chico"].clone()).unwrap();
+        assert_eq!(vec![USER, GAME], methods(&chico_inbox));
+        server
+            .handle_frame("paloma", Frame::number(GUESS, 43))
+            .unwrap();
+        assert_eq!(vec![YOU_WIN, SERIES], methods(&paloma_inbox));
+        assert!(resume(&mut server, "paloma", tokens["paloma"].clone()).is_err());
+    }
+
+    #[test]
+    fn test_restored_game_waits_for_its_players() {
+        let path = env::temp_dir().join(format!("snapshot-{}.json", uuid::Uuid::new_v4()));
+        let mut server = Server::new().with_snapshot(Snapshot::open(&path));
+        started_game(&mut server);
+        let game_id = server.players["chico"].game_id.clone().unwrap();
+        let paloma_token = Some(server.players["paloma"].token.clone());
+        server.save_snapshot();
+
+        let mut server = Server::new().with_snapshot(Snapshot::open(&path));
+        fs::remove_file(&path).unwrap();
+        let lives = |server: &Server| {
+            let game = &server.sessions[&game_id].game;
+            game.player("chico").unwrap().life()
+        };
+        let now = Local::now();
+
+        // Chico can not play before resuming, the turn does not run out
+        server.expire_turns(now + Duration::seconds(31));
+        let (outbox, _paloma_inbox) = channel::<Frame>();
+        let paloma = UserInfo {
+            id: String::from("paloma"),
+            name: String::from("Paloma"),
+            stats: None,
+            token: paloma_token,
+        };
+        server.resume_player(paloma, outbox).unwrap();
+        server.expire_turns(now + Duration::seconds(61));
+        assert_eq!(3, lives(&server));
+
+        // Past the grace period the clock starts over without Chico
+        let resume_until = now + Duration::seconds(RESUME_GRACE_SECONDS + 1);
+        server.expire_turns(resume_until);
+        assert_eq!(3, lives(&server));
+        server.expire_turns(resume_until + Duration::seconds(30));
+        assert_eq!(2, lives(&server));
     }
 }
 
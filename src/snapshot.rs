@@ -0,0 +1,103 @@
+use crate::game::GameSnapshot;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerState {
+    pub id: String,
+    pub name: String,
+    pub game_id: Option<String>,
+    /// Resume token the player got, players saved without one cannot resume.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Players and running games, kept across a server restart.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ServerState {
+    pub players: Vec<PlayerState>,
+    pub games: Vec<GameSnapshot>,
+}
+
+/// Server state saved on shutdown, a single Json file read once on start.
+pub struct Snapshot {
+    path: PathBuf,
+}
+
+impl Snapshot {
+    pub fn open<P: Into<PathBuf>>(path: P) -> Snapshot {
+        Snapshot { path: path.into() }
+    }
+
+    /// Writes a temporary file first so a failed save never leaves half a snapshot.
+    pub fn save(&self, state: &ServerState) -> Result<(), String> {
+        let json = serde_json::to_string(state).map_err(|error| format!("{}", error))?;
+        let temporary_path = self.path.with_extension("tmp");
+
+        fs::write(&temporary_path, json)
+            .and_then(|_| fs::rename(&temporary_path, &self.path))
+            .map_err(|error| format!("{}", error))
+    }
+
+    /// Reads the saved state, it stays in place until `set_restored`.
+    pub fn read(&self) -> Result<Option<ServerState>, String> {
+        let json = match fs::read_to_string(&self.path) {
+            Ok(json) => json,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(format!("{}", error)),
+        };
+
+        let state = serde_json::from_str(&json).map_err(|error| format!("{}", error))?;
+        Ok(Some(state))
+    }
+
+    /// Moves the snapshot aside to a `.restored` file, a snapshot is restored
+    /// only once.
+    pub fn set_restored(&self) -> Result<(), String> {
+        match fs::rename(&self.path, self.path.with_extension("restored")) {
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            result => result.map_err(|error| format!("{}", error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Game, GameRules, GameSnapshot, Player};
+    use crate::snapshot::{PlayerState, ServerState, Snapshot};
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn test_snapshot() {
+        let path = env::temp_dir().join(format!("snapshot-{}.json", uuid::Uuid::new_v4()));
+        let snapshot = Snapshot::open(&path);
+        let player = Player::new(String::from("Chico"));
+        let game = Game::new(&player, GameRules::default()).unwrap();
+        let state = ServerState {
+            players: vec![PlayerState {
+                id: player.id.clone(),
+                name: player.name(),
+                game_id: Some(game.id().to_string()),
+                token: Some(String::from("secret")),
+            }],
+            games: vec![GameSnapshot::from(&game)],
+        };
+
+        assert_eq!(Ok(None), snapshot.read());
+        assert_eq!(Ok(()), snapshot.set_restored());
+        snapshot.save(&state).unwrap();
+        assert_eq!(Ok(Some(state.clone())), snapshot.read());
+        assert_eq!(Ok(Some(state.clone())), snapshot.read());
+
+        assert_eq!(Ok(()), snapshot.set_restored());
+        assert_eq!(Ok(None), snapshot.read());
+        assert_eq!(
+            Ok(Some(state)),
+            Snapshot::open(path.with_extension("restored")).read()
+        );
+        fs::remove_file(path.with_extension("restored")).unwrap();
+    }
+}
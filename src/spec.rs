@@ -0,0 +1,412 @@
+use serde::Deserialize;
+
+/// The protocol specification, source of truth for classes, methods and
+/// payload schemas.
+const SPEC: &str = include_str!("../protocol.json");
+
+/// README sections between these markers are generated, `<!-- spec:class:2 -->`
+/// for the methods of a class and `<!-- spec:schemas -->` for the payloads.
+const BLOCK_START: &str = "<!-- spec:";
+const BLOCK_END: &str = "<!-- spec:end -->";
+
+#[derive(Deserialize, Debug)]
+enum Peer {
+    Client,
+    Server,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+enum Layout {
+    Empty,
+    ShortString,
+    Number,
+    Json,
+}
+
+#[derive(Deserialize, Debug)]
+struct MethodSpec {
+    id: u8,
+    name: String,
+    constant: String,
+    peer: Peer,
+    payload: Layout,
+    /// Rust type of Json payloads.
+    #[serde(rename = "type", default)]
+    payload_type: Option<String>,
+    description: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ClassSpec {
+    id: u8,
+    name: String,
+    methods: Vec<MethodSpec>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Field {
+    name: String,
+    #[serde(rename = "type")]
+    field_type: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Schema {
+    name: String,
+    fields: Vec<Field>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Variant {
+    name: String,
+    /// Unit variants are a Json string, the others an object.
+    #[serde(default)]
+    fields: Vec<Field>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EnumSpec {
+    name: String,
+    variants: Vec<Variant>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Spec {
+    header: String,
+    text_header: String,
+    frame_end: u8,
+    classes: Vec<ClassSpec>,
+    schemas: Vec<Schema>,
+    enums: Vec<EnumSpec>,
+}
+
+/// Method name in the text format, `Registration.Ok` is `registration.ok`
+/// and `SecretNumber` is `secret_number`.
+fn text_name(name: &str) -> String {
+    let mut text_name = String::new();
+    for (index, character) in name.char_indices() {
+        let after_dot = name[..index].ends_with('.');
+        if character.is_uppercase() && index > 0 && !after_dot {
+            text_name.push('_');
+        }
+        text_name.extend(character.to_lowercase());
+    }
+    text_name
+}
+
+fn inner_type<'a>(type_name: &'a str, wrapper: &str) -> Option<&'a str> {
+    type_name
+        .strip_prefix(wrapper)
+        .and_then(|inner| inner.strip_prefix('<'))
+        .and_then(|inner| inner.strip_suffix('>'))
+}
+
+/// How a type reads in the README, `Vec<T>` is `[T]`.
+fn doc_type(type_name: &str) -> String {
+    if let Some(inner) = inner_type(type_name, "Option") {
+        return format!("{} or null", doc_type(inner));
+    }
+    if let Some(inner) = inner_type(type_name, "Vec") {
+        return format!("[{}]", doc_type(inner));
+    }
+    type_name.to_string()
+}
+
+fn doc_fields(fields: &[Field]) -> String {
+    let fields: Vec<String> = fields
+        .iter()
+        .map(|field| format!("\"{}\": {}", field.name, doc_type(&field.field_type)))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+impl Spec {
+    pub fn load() -> Result<Spec, String> {
+        serde_json::from_str(SPEC).map_err(|error| format!("Invalid protocol spec: {}", error))
+    }
+
+    fn methods(&self) -> impl Iterator<Item = (&ClassSpec, &MethodSpec)> {
+        self.classes
+            .iter()
+            .flat_map(|class| class.methods.iter().map(move |method| (class, method)))
+    }
+
+    /// Constants and per method tables included by the protocol module.
+    pub fn rust(&self) -> String {
+        let mut code = String::from(
+            "// Generated from protocol.json by `UPDATE_SPEC=1 cargo test spec`, do not edit.\n\n",
+        );
+        code += &format!("pub const PROTOCOL_HEADER: &str = \"{}\";\n", self.header);
+        code += &format!("pub const TEXT_HEADER: &str = \"{}\";\n", self.text_header);
+        code += &format!("pub const FRAME_END: u8 = {:#x};\n", self.frame_end);
+
+        for class in self.classes.iter() {
+            code += "\n";
+            for method in class.methods.iter() {
+                code += &format!(
+                    "pub const {}: Method = ({}, {});\n",
+                    method.constant, class.id, method.id
+                );
+            }
+        }
+
+        code += "\nfn payload_kind(method: Method) -> Result<PayloadKind, String> {\n    match method {\n";
+        for (_, method) in self.methods() {
+            let kind = match method.payload {
+                Layout::Empty => "Empty",
+                Layout::ShortString => "ShortString",
+                Layout::Number => "Number",
+                Layout::Json => "LongString",
+            };
+            code += &format!(
+                "        {} => Ok(PayloadKind::{}),\n",
+                method.constant, kind
+            );
+        }
+        code += "        (class, method) => Err(format!(\"Unknown method {}.{}\", class, method)),\n    }\n}\n";
+
+        code += "\n/// Class and method names of the text format.\nconst METHOD_NAMES: &[(Method, &str, &str)] = &[\n";
+        for (class, method) in self.methods() {
+            code += &format!(
+                "    ({}, \"{}\", \"{}\"),\n",
+                method.constant,
+                class.name.to_lowercase(),
+                text_name(&method.name)
+            );
+        }
+        code += "];\n";
+
+        code += "\n/// Encodes a payload with another codec, through the type its method carries,\n\
+                 /// not every codec can be read without knowing it.\n\
+                 fn transcode_payload(\n    method: Method,\n    content: &[u8],\n    from: Codec,\n    to: Codec,\n\
+                 ) -> Result<Vec<u8>, String> {\n    match method {\n";
+        for (_, method) in self.methods() {
+            if let Some(payload_type) = &method.payload_type {
+                code += &format!(
+                    "        {} => convert::<{}>(content, from, to),\n",
+                    method.constant, payload_type
+                );
+            }
+        }
+        code += "        (class, method) => Err(format!(\"Unknown method {}.{}\", class, method)),\n    }\n}\n";
+        code
+    }
+
+    fn class_table(&self, class_id: &str) -> Result<String, String> {
+        let class = self
+            .classes
+            .iter()
+            .find(|class| class.id.to_string() == class_id)
+            .ok_or_else(|| format!("Unknown class {}", class_id))?;
+
+        let mut table = String::from(
+            "|  class|method|peer |name |payload | description\n|--|--|--|--|--|--|\n",
+        );
+        for method in class.methods.iter() {
+            let peer = match method.peer {
+                Peer::Client => "C",
+                Peer::Server => "S",
+            };
+            let payload = match (&method.payload, &method.payload_type) {
+                (Layout::Empty, _) => String::from("-"),
+                (Layout::ShortString, _) => String::from("Short string"),
+                (Layout::Number, _) => String::from("Number"),
+                (Layout::Json, Some(payload_type)) => doc_type(payload_type),
+                (Layout::Json, None) => String::from("Json"),
+            };
+            table += &format!(
+                "| {} | {} | {} | {} | {} | {} |\n",
+                class.id, method.id, peer, method.name, payload, method.description
+            );
+        }
+        Ok(table)
+    }
+
+    fn schema_table(&self) -> String {
+        let mut table = String::from("| type | Json |\n|--|--|\n");
+        for schema in self.schemas.iter() {
+            table += &format!("| {} | `{}` |\n", schema.name, doc_fields(&schema.fields));
+        }
+        for enum_spec in self.enums.iter() {
+            let variants: Vec<String> = enum_spec
+                .variants
+                .iter()
+                .map(|variant| {
+                    if variant.fields.is_empty() {
+                        format!("`\"{}\"`", variant.name)
+                    } else {
+                        format!(
+                            "`{{\"{}\": {}}}`",
+                            variant.name,
+                            doc_fields(&variant.fields)
+                        )
+                    }
+                })
+                .collect();
+            table += &format!("| {} | {} |\n", enum_spec.name, variants.join(" or "));
+        }
+        table
+    }
+
+    fn block(&self, name: &str) -> Result<String, String> {
+        match name.split_once(':') {
+            Some(("class", class_id)) => self.class_table(class_id),
+            None if name == "schemas" => Ok(self.schema_table()),
+            _ => Err(format!("Unknown spec block {}", name)),
+        }
+    }
+
+    /// The README with its generated sections rendered again.
+    pub fn readme(&self, readme: &str) -> Result<String, String> {
+        let mut generated = String::new();
+        let mut rest = readme;
+
+        while let Some(start) = rest.find(BLOCK_START) {
+            let (before, block) = rest.split_at(start);
+            let name_end = block
+                .find(" -->")
+                .ok_or_else(|| String::from("Unclosed spec block marker"))?;
+            let name = &block[BLOCK_START.len()..name_end];
+            let end = block
+                .find(BLOCK_END)
+                .ok_or_else(|| format!("Spec block {} has no end", name))?;
+
+            generated += before;
+            generated += &block[..name_end + " -->".len()];
+            generated += "\n";
+            generated += &self.block(name)?;
+            generated += BLOCK_END;
+            rest = &block[end + BLOCK_END.len()..];
+        }
+
+        generated += rest;
+        Ok(generated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+    use crate::protocol::Frame;
+    use crate::spec::{text_name, Layout, Spec};
+    use serde_json::{json, Value};
+    use std::{env, fs};
+
+    const GENERATED_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/protocol_spec.rs");
+    const README_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/README.md");
+    const SPEC_OUTDATED: &str =
+        "Generated files are out of date, run `UPDATE_SPEC=1 cargo test spec` to update them";
+
+    /// Set when the generated files are to be written again instead of checked.
+    fn update_spec() -> bool {
+        env::var_os("UPDATE_SPEC").is_some()
+    }
+
+    impl Spec {
+        /// A Json value of the type, enums take their `variant`-th variant.
+        fn sample(&self, type_name: &str, variant: usize) -> Result<Value, String> {
+            if let Some(inner) = super::inner_type(type_name, "Option") {
+                return self.sample(inner, variant);
+            }
+            if let Some(inner) = super::inner_type(type_name, "Vec") {
+                return Ok(Value::Array(vec![self.sample(inner, variant)?]));
+            }
+
+            match type_name {
+                "String" => return Ok(json!("text")),
+                "bool" => return Ok(json!(true)),
+                "f64" => return Ok(json!(1.5)),
+                "u8" | "u16" | "u32" | "usize" | "i32" => return Ok(json!(7)),
+                _ => {}
+            }
+
+            if let Some(schema) = self.schemas.iter().find(|schema| schema.name == type_name) {
+                return self.sample_fields(&schema.fields, variant);
+            }
+            match self
+                .enums
+                .iter()
+                .find(|enum_spec| enum_spec.name == type_name)
+            {
+                Some(enum_spec) => {
+                    let variant_spec = &enum_spec.variants[variant % enum_spec.variants.len()];
+                    if variant_spec.fields.is_empty() {
+                        Ok(json!(variant_spec.name))
+                    } else {
+                        let fields = self.sample_fields(&variant_spec.fields, variant)?;
+                        Ok(json!({ variant_spec.name.clone(): fields }))
+                    }
+                }
+                None => Err(format!("Unknown type {}", type_name)),
+            }
+        }
+
+        fn sample_fields(&self, fields: &[super::Field], variant: usize) -> Result<Value, String> {
+            let mut object = serde_json::Map::new();
+            for field in fields {
+                object.insert(field.name.clone(), self.sample(&field.field_type, variant)?);
+            }
+            Ok(Value::Object(object))
+        }
+    }
+
+    #[test]
+    fn test_generated_code_is_current() {
+        let spec = Spec::load().unwrap();
+        if update_spec() {
+            fs::write(GENERATED_PATH, spec.rust()).unwrap();
+            return;
+        }
+
+        assert!(
+            spec.rust() == include_str!("protocol_spec.rs"),
+            "{}",
+            SPEC_OUTDATED
+        );
+    }
+
+    #[test]
+    fn test_readme_is_current() {
+        let spec = Spec::load().unwrap();
+        let readme = fs::read_to_string(README_PATH).unwrap();
+        if update_spec() {
+            fs::write(README_PATH, spec.readme(&readme).unwrap()).unwrap();
+            return;
+        }
+
+        assert!(spec.readme(&readme).unwrap() == readme, "{}", SPEC_OUTDATED);
+    }
+
+    /// Payloads built from the schemas go through the Rust types unchanged,
+    /// a field missing on either side or with another type fails.
+    #[test]
+    fn test_payload_schemas() {
+        let spec = Spec::load().unwrap();
+
+        for (class, method) in spec.methods() {
+            let payload_type = match (&method.payload, &method.payload_type) {
+                (Layout::Json, Some(payload_type)) => payload_type,
+                (Layout::Json, None) => panic!("{} has no payload type", method.name),
+                _ => continue,
+            };
+
+            for variant in 0..4 {
+                let payload = spec.sample(payload_type, variant).unwrap();
+                let frame = Frame::json((class.id, method.id), &payload);
+                let decoded = frame
+                    .transcode(Codec::Json, Codec::MessagePack)
+                    .and_then(|frame| frame.transcode(Codec::MessagePack, Codec::Json))
+                    .and_then(|frame| frame.json_payload::<Value>());
+
+                assert_eq!(Ok(payload), decoded, "{} payload drifted", method.name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_name() {
+        assert_eq!("registration.ok", text_name("Registration.Ok"));
+        assert_eq!("secret_number", text_name("SecretNumber"));
+        assert_eq!("leaderboard.entries", text_name("Leaderboard.Entries"));
+    }
+}
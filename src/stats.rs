@@ -0,0 +1,220 @@
+use crate::game::Move;
+use crate::history::GameRecord;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const LEADERBOARD_SIZE: usize = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PlayerStats {
+    pub wins: u32,
+    pub losses: u32,
+    pub average_guesses_to_win: Option<f64>,
+    /// Positive for a winning streak, negative for a losing one.
+    pub current_streak: i32,
+    pub best_streak: u32,
+    pub rating: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Period {
+    AllTime,
+    LastWeek,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LeaderboardEntry {
+    pub name: String,
+    pub stats: PlayerStats,
+}
+
+/// Result of one recorded game for one player, `None` if the player was not
+/// in it or played against a bot.
+fn game_result(record: &GameRecord, player_id: &str) -> Option<(bool, u32)> {
+    if record.has_bot() {
+        return None;
+    }
+    let player = record
+        .ranked_players()
+        .find(|player| player.id == player_id)?;
+
+    let guesses = record
+        .moves
+        .iter()
+        .filter(|game_move| match game_move {
+            Move::Guess(guess) => guess.player_id == player.id,
+            _ => false,
+        })
+        .count() as u32;
+
+    Some((record.winner.as_ref() == Some(&player.id), guesses))
+}
+
+/// Stats follow the player id, players keep it with their account while
+/// names could be reused.
+pub fn player_stats(records: &[GameRecord], player_id: &str) -> PlayerStats {
+    let mut stats = PlayerStats::default();
+    let mut guesses_to_win = 0;
+
+    for (won, guesses) in records
+        .iter()
+        .filter_map(|record| game_result(record, player_id))
+    {
+        if won {
+            stats.wins += 1;
+            guesses_to_win += guesses;
+            stats.current_streak = stats.current_streak.max(0) + 1;
+            stats.best_streak = stats.best_streak.max(stats.current_streak as u32);
+        } else {
+            stats.losses += 1;
+            stats.current_streak = stats.current_streak.min(0) - 1;
+        }
+    }
+
+    if stats.wins > 0 {
+        stats.average_guesses_to_win = Some(guesses_to_win as f64 / stats.wins as f64);
+    }
+    stats
+}
+
+/// Players with the most wins, ties go to the one with less losses.
+pub fn leaderboard(
+    records: &[GameRecord],
+    period: Period,
+    now: DateTime<Local>,
+) -> Vec<LeaderboardEntry> {
+    let records: Vec<GameRecord> = records
+        .iter()
+        .filter(|record| !record.has_bot())
+        .filter(|record| match period {
+            Period::AllTime => true,
+            Period::LastWeek => now - record.finished_at <= Duration::days(7),
+        })
+        .cloned()
+        .collect();
+
+    // Records are in the order games finished, the last name a player had wins
+    let names: HashMap<&str, &str> = records
+        .iter()
+        .flat_map(GameRecord::ranked_players)
+        .map(|player| (player.id.as_str(), player.name.as_str()))
+        .collect();
+
+    let mut entries: Vec<LeaderboardEntry> = names
+        .into_iter()
+        .map(|(player_id, name)| LeaderboardEntry {
+            name: name.to_string(),
+            stats: player_stats(&records, player_id),
+        })
+        .collect();
+
+    entries.sort_by(|one, other| {
+        other
+            .stats
+            .wins
+            .cmp(&one.stats.wins)
+            .then(one.stats.losses.cmp(&other.stats.losses))
+            .then(one.name.cmp(&other.name))
+    });
+    entries.truncate(LEADERBOARD_SIZE);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{Game, GameRules, Player};
+    use crate::history::GameRecord;
+    use crate::stats::{leaderboard, player_stats, Period, PlayerStats};
+    use chrono::{Duration, Local};
+
+    fn player(name: &str) -> Player {
+        Player::with_id(name.to_lowercase(), String::from(name))
+    }
+
+    /// Game where `winner` finds the secret number after `misses` wrong guesses.
+    fn record(winner: &str, loser: &str, misses: u16) -> GameRecord {
+        let winner = player(winner);
+        let loser = player(loser);
+        let rules = GameRules {
+            lives: 10,
+            ..GameRules::default()
+        };
+        let mut game = Game::new(&winner, rules).unwrap();
+        game.accept_challenge(&loser).unwrap();
+        game.set_secret_number(&winner, 43).unwrap();
+        game.set_secret_number(&loser, 42).unwrap();
+        game.set_ready().unwrap();
+        game.start().unwrap();
+
+        for miss in 1..=misses {
+            game.guess_number(&winner, miss).unwrap();
+            game.guess_number(&loser, miss).unwrap();
+        }
+        game.guess_number(&winner, 42).unwrap();
+        GameRecord::from(&game)
+    }
+
+    #[test]
+    fn test_player_stats() {
+        let records = vec![
+            record("Chico", "Paloma", 0),
+            record("Chico", "Paloma", 2),
+            record("Paloma", "Chico", 0),
+            record("Chico", "Allan", 4),
+        ];
+
+        assert_eq!(
+            PlayerStats {
+                wins: 3,
+                losses: 1,
+                average_guesses_to_win: Some(3.0),
+                current_streak: 1,
+                best_streak: 2,
+                rating: None,
+            },
+            player_stats(&records, "chico")
+        );
+        assert_eq!(-1, player_stats(&records, "allan").current_streak);
+        assert_eq!(PlayerStats::default(), player_stats(&records, "nobody"));
+    }
+
+    #[test]
+    fn test_leaderboard() {
+        let mut old_record = record("Allan", "Chico", 0);
+        old_record.finished_at = Local::now() - Duration::days(8);
+        let records = vec![
+            old_record.clone(),
+            old_record,
+            record("Chico", "Paloma", 0),
+            record("Paloma", "Allan", 0),
+        ];
+
+        let names = |period| -> Vec<String> {
+            leaderboard(&records, period, Local::now())
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect()
+        };
+        assert_eq!(vec!["Allan", "Paloma", "Chico"], names(Period::AllTime));
+        assert_eq!(vec!["Chico", "Paloma", "Allan"], names(Period::LastWeek));
+    }
+
+    #[test]
+    fn test_bot_games_are_left_out() {
+        let records = vec![record("Chico", "Paloma", 0)];
+        let mut with_bots = records.clone();
+        with_bots.push(record("Chico", "bot:Weak:1", 0));
+        with_bots.push(record("bot:BinarySearch:2", "Chico", 2));
+
+        assert_eq!(
+            player_stats(&records, "chico"),
+            player_stats(&with_bots, "chico")
+        );
+        let now = Local::now();
+        assert_eq!(
+            leaderboard(&records, Period::AllTime, now),
+            leaderboard(&with_bots, Period::AllTime, now)
+        );
+    }
+}
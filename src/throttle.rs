@@ -0,0 +1,105 @@
+use crate::protocol::FrameLimit;
+use std::time::Instant;
+
+/// Tokens come back at `per_second` up to `burst`, every frame takes one.
+struct TokenBucket {
+    class: Option<u8>,
+    per_second: f64,
+    burst: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.burst);
+        self.updated_at = now;
+    }
+}
+
+/// Frame limits of a connection, a bucket for every limit. A frame must
+/// find a token in each bucket of its class and in the ones for every class.
+pub struct Throttle {
+    buckets: Vec<TokenBucket>,
+}
+
+impl Throttle {
+    pub fn new(frame_limits: &[FrameLimit], now: Instant) -> Throttle {
+        let buckets = frame_limits
+            .iter()
+            .map(|frame_limit| TokenBucket {
+                class: frame_limit.class,
+                per_second: f64::from(frame_limit.per_second),
+                burst: f64::from(frame_limit.burst),
+                tokens: f64::from(frame_limit.burst),
+                updated_at: now,
+            })
+            .collect();
+
+        Throttle { buckets }
+    }
+
+    /// Takes the tokens of a frame of `class`, none are taken when one of its
+    /// buckets is empty.
+    pub fn allow(&mut self, class: u8, now: Instant) -> bool {
+        let mut buckets: Vec<&mut TokenBucket> = self
+            .buckets
+            .iter_mut()
+            .filter(|bucket| bucket.class.is_none() || bucket.class == Some(class))
+            .collect();
+
+        for bucket in buckets.iter_mut() {
+            bucket.refill(now);
+        }
+        if buckets.iter().any(|bucket| bucket.tokens < 1.0) {
+            return false;
+        }
+
+        for bucket in buckets.iter_mut() {
+            bucket.tokens -= 1.0;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::FrameLimit;
+    use crate::throttle::Throttle;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_throttle() {
+        let limits = vec![
+            FrameLimit {
+                class: None,
+                per_second: 10,
+                burst: 3,
+            },
+            FrameLimit {
+                class: Some(2),
+                per_second: 1,
+                burst: 2,
+            },
+        ];
+        let now = Instant::now();
+        let mut throttle = Throttle::new(&limits, now);
+
+        assert!(throttle.allow(2, now));
+        assert!(throttle.allow(2, now));
+        assert!(!throttle.allow(2, now));
+
+        // Throttled frames take no token, the bucket for every class has one left
+        assert!(throttle.allow(3, now));
+        assert!(!throttle.allow(3, now));
+
+        let later = now + Duration::from_millis(500);
+        assert!(!throttle.allow(2, later));
+        assert!(throttle.allow(3, later));
+
+        let later = now + Duration::from_secs(1);
+        assert!(throttle.allow(2, later));
+        assert!(!throttle.allow(2, later));
+    }
+}
@@ -0,0 +1,215 @@
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, Connection, RootCertStore, ServerConfig};
+use std::convert::TryFrom;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+const READ_SIZE: usize = 4096;
+
+/// Server side settings from the Pem files of the certificate chain and its key.
+pub fn server_config(cert_path: &str, key_path: &str) -> Result<Arc<ServerConfig>, String> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<CertificateDer>, _>>())
+        .map_err(|error| format!("Invalid certificate {}: {}", cert_path, error))?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|error| format!("Invalid key {}: {}", key_path, error))?;
+
+    let config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|error| format!("{}", error))?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|error| format!("{}", error))?;
+    Ok(Arc::new(config))
+}
+
+/// Client side settings trusting the certificates of a Pem file, servers
+/// usually run with a self signed one.
+pub fn client_config(ca_path: &str) -> Result<Arc<ClientConfig>, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_file_iter(ca_path)
+        .map_err(|error| format!("Invalid certificate {}: {}", ca_path, error))?
+    {
+        let cert = cert.map_err(|error| format!("Invalid certificate {}: {}", ca_path, error))?;
+        roots.add(cert).map_err(|error| format!("{}", error))?;
+    }
+
+    let config =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|error| format!("{}", error))?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+pub fn connect(
+    address: &str,
+    server_name: &str,
+    config: Arc<ClientConfig>,
+) -> Result<TlsStream, String> {
+    let server_name =
+        ServerName::try_from(server_name.to_string()).map_err(|error| format!("{}", error))?;
+    let tls = ClientConnection::new(config, server_name).map_err(|error| format!("{}", error))?;
+    let tcp = TcpStream::connect(address).map_err(|error| format!("{}", error))?;
+
+    Ok(TlsStream::new(tls, tcp))
+}
+
+/// Tls over a Tcp stream, clones share the session so one of them can keep
+/// reading while another writes, like with a plain `TcpStream`.
+pub struct TlsStream {
+    tls: Arc<Mutex<Connection>>,
+    tcp: TcpStream,
+}
+
+impl TlsStream {
+    pub fn new<C: Into<Connection>>(tls: C, tcp: TcpStream) -> TlsStream {
+        TlsStream {
+            tls: Arc::new(Mutex::new(tls.into())),
+            tcp,
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<TlsStream> {
+        Ok(TlsStream {
+            tls: Arc::clone(&self.tls),
+            tcp: self.tcp.try_clone()?,
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp.set_read_timeout(timeout)
+    }
+
+    pub fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        let mut tls = lock(&self.tls)?;
+        tls.send_close_notify();
+        write_tls(&mut tls, &mut self.tcp).ok();
+        self.tcp.shutdown(how)
+    }
+}
+
+fn lock(tls: &Mutex<Connection>) -> io::Result<MutexGuard<'_, Connection>> {
+    tls.lock()
+        .map_err(|_| io::Error::other("Tls session poisoned"))
+}
+
+/// Sends whatever the session has to send, handshake messages included.
+fn write_tls(tls: &mut Connection, tcp: &mut TcpStream) -> io::Result<()> {
+    while tls.wants_write() {
+        tls.write_tls(tcp)?;
+    }
+    Ok(())
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            {
+                let mut tls = lock(&self.tls)?;
+                match tls.reader().read(buf) {
+                    Err(error) if error.kind() == ErrorKind::WouldBlock => {}
+                    result => return result,
+                }
+                write_tls(&mut tls, &mut self.tcp)?;
+            }
+
+            // Waiting for the peer must not hold the session, writers need it
+            let mut received = [0; READ_SIZE];
+            let size = self.tcp.read(&mut received)?;
+            let mut received = &received[..size];
+
+            let mut tls = lock(&self.tls)?;
+            loop {
+                tls.read_tls(&mut received)?;
+                tls.process_new_packets()
+                    .map_err(|error| io::Error::new(ErrorKind::InvalidData, error))?;
+                if received.is_empty() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut tls = lock(&self.tls)?;
+        let size = tls.writer().write(buf)?;
+        write_tls(&mut tls, &mut self.tcp)?;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut tls = lock(&self.tls)?;
+        tls.writer().flush()?;
+        write_tls(&mut tls, &mut self.tcp)?;
+        self.tcp.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{Frame, CHAT_GAME, CHAT_LOBBY};
+    use crate::tls::{client_config, connect, server_config, TlsStream};
+    use rustls::ServerConnection;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const CERT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/certs/localhost.pem");
+    const KEY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/certs/localhost.key");
+
+    #[test]
+    fn test_tls_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let config = server_config(CERT_PATH, KEY_PATH).unwrap();
+
+        let server = thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut reader = TlsStream::new(ServerConnection::new(config).unwrap(), tcp);
+            let mut writer = reader.try_clone().unwrap();
+
+            let frame = Frame::read_from(&mut reader).unwrap();
+            let answer = Frame::text(CHAT_GAME, &frame.text_payload().unwrap());
+            writer.write_all(&Vec::<u8>::from(answer)).unwrap();
+            Frame::read_from(&mut reader).is_err()
+        });
+
+        let mut client = connect(&address, "localhost", client_config(CERT_PATH).unwrap()).unwrap();
+        client
+            .write_all(&Vec::<u8>::from(Frame::text(CHAT_LOBBY, "Hello")))
+            .unwrap();
+        let answer = Frame::read_from(&mut client).unwrap();
+        client.shutdown(std::net::Shutdown::Both).unwrap();
+
+        assert_eq!(Frame::text(CHAT_GAME, "Hello"), answer);
+        assert!(server.join().unwrap(), "Closed stream must not read frames");
+    }
+
+    #[test]
+    fn test_untrusted_certificate() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let config = server_config(CERT_PATH, KEY_PATH).unwrap();
+
+        thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut stream = TlsStream::new(ServerConnection::new(config).unwrap(), tcp);
+            Frame::read_from(&mut stream).ok();
+        });
+
+        let mut client =
+            connect(&address, "example.com", client_config(CERT_PATH).unwrap()).unwrap();
+        client
+            .write_all(&Vec::<u8>::from(Frame::text(CHAT_LOBBY, "Hello")))
+            .unwrap();
+        assert!(Frame::read_from(&mut client).is_err());
+    }
+}
@@ -0,0 +1,462 @@
+use crate::game::GameRules;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use uuid::Uuid;
+
+const MAX_PLAYERS: u8 = 16;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TournamentFormat {
+    SingleElimination,
+    RoundRobin,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TournamentRules {
+    pub format: TournamentFormat,
+    /// The tournament starts once this many players registered.
+    pub players: u8,
+    #[serde(default)]
+    pub rules: GameRules,
+}
+
+impl TournamentRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.players < 2 || self.players > MAX_PLAYERS {
+            return Err(format!("Tournaments need 2 to {} players", MAX_PLAYERS));
+        }
+        self.rules.validate()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum TournamentState {
+    Registering,
+    InProgress,
+    Finished,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TournamentPlayer {
+    pub id: String,
+    pub name: String,
+    pub wins: u32,
+    pub forfeited: bool,
+}
+
+/// Players meeting in a round, a single player is a bye.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Pairing {
+    pub players: Vec<String>,
+    pub game_id: Option<String>,
+    pub finished: bool,
+    pub winner: Option<String>,
+}
+
+impl Pairing {
+    fn new(players: Vec<String>) -> Pairing {
+        Pairing {
+            players,
+            game_id: None,
+            finished: false,
+            winner: None,
+        }
+    }
+}
+
+/// Also the bracket state sent to clients.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Tournament {
+    id: String,
+    rules: TournamentRules,
+    state: TournamentState,
+    players: Vec<TournamentPlayer>,
+    rounds: Vec<Vec<Pairing>>,
+    round: usize,
+    winner: Option<String>,
+}
+
+impl Tournament {
+    pub fn new(rules: TournamentRules) -> Result<Tournament, String> {
+        rules.validate()?;
+
+        Ok(Tournament {
+            id: Uuid::new_v4().to_string(),
+            rules: TournamentRules {
+                rules: GameRules {
+                    max_players: 2,
+                    ..rules.rules
+                },
+                ..rules
+            },
+            state: TournamentState::Registering,
+            players: Vec::new(),
+            rounds: Vec::new(),
+            round: 0,
+            winner: None,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn game_rules(&self) -> &GameRules {
+        &self.rules.rules
+    }
+
+    pub fn state(&self) -> TournamentState {
+        self.state
+    }
+
+    pub fn player_ids(&self) -> Vec<&str> {
+        self.players
+            .iter()
+            .map(|player| player.id.as_str())
+            .collect()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.players.len() >= self.rules.players as usize
+    }
+
+    pub fn register(&mut self, player_id: &str, name: &str) -> Result<(), String> {
+        if self.state != TournamentState::Registering || self.is_full() {
+            return Err(String::from("Tournament registration is closed"));
+        }
+        if self.players.iter().any(|player| player.id == player_id) {
+            return Err(String::from("Player already registered"));
+        }
+
+        self.players.push(TournamentPlayer {
+            id: player_id.to_string(),
+            name: name.to_string(),
+            wins: 0,
+            forfeited: false,
+        });
+        Ok(())
+    }
+
+    /// Players leaving before the start just free their place, after that
+    /// they forfeit every game left.
+    pub fn leave(&mut self, player_id: &str) {
+        match self.state {
+            TournamentState::Registering => {
+                self.players.retain(|player| player.id != player_id);
+            }
+            TournamentState::InProgress => {
+                if let Some(player) = self.player_mut(player_id) {
+                    player.forfeited = true;
+                }
+                self.settle();
+            }
+            TournamentState::Finished => {}
+        }
+    }
+
+    pub fn start(&mut self) -> Result<(), String> {
+        if self.state != TournamentState::Registering || !self.is_full() {
+            return Err(String::from("Tournament is not ready to start"));
+        }
+
+        let player_ids: Vec<String> = self
+            .players
+            .iter()
+            .map(|player| player.id.clone())
+            .collect();
+        self.rounds = match self.rules.format {
+            TournamentFormat::SingleElimination => vec![pair_up(&player_ids)],
+            TournamentFormat::RoundRobin => round_robin(&player_ids),
+        };
+        self.state = TournamentState::InProgress;
+        self.settle();
+        Ok(())
+    }
+
+    /// Pairings of the current round still waiting for their game.
+    pub fn games_to_start(&self) -> Vec<(usize, String, String)> {
+        match self.rounds.get(self.round) {
+            Some(pairings) if self.state == TournamentState::InProgress => pairings
+                .iter()
+                .enumerate()
+                .filter(|(_, pairing)| !pairing.finished && pairing.game_id.is_none())
+                .filter_map(|(index, pairing)| match pairing.players.as_slice() {
+                    [player, opponent] => Some((index, player.clone(), opponent.clone())),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn game_started(&mut self, pairing: usize, game_id: &str) {
+        if let Some(pairing) = self
+            .rounds
+            .get_mut(self.round)
+            .and_then(|pairings| pairings.get_mut(pairing))
+        {
+            pairing.game_id = Some(game_id.to_string());
+        }
+    }
+
+    pub fn game_finished(&mut self, game_id: &str, winner: Option<&str>) {
+        let finished = self.rounds.get_mut(self.round).and_then(|pairings| {
+            pairings
+                .iter_mut()
+                .find(|pairing| pairing.game_id.as_deref() == Some(game_id))
+        });
+
+        match finished {
+            Some(pairing) => {
+                pairing.finished = true;
+                pairing.winner = winner.map(String::from);
+            }
+            None => return,
+        }
+
+        if let Some(winner) = winner.and_then(|winner| self.player_mut(winner)) {
+            winner.wins += 1;
+        }
+        self.settle();
+    }
+
+    fn player_mut(&mut self, player_id: &str) -> Option<&mut TournamentPlayer> {
+        self.players
+            .iter_mut()
+            .find(|player| player.id == player_id)
+    }
+
+    fn forfeited(&self, player_id: &str) -> bool {
+        self.players
+            .iter()
+            .any(|player| player.id == player_id && player.forfeited)
+    }
+
+    /// Closes pairings without a game to play, byes and walkovers, and moves
+    /// to the next round once every pairing is finished.
+    fn settle(&mut self) {
+        while self.state == TournamentState::InProgress {
+            let walkovers: Vec<(usize, Option<String>)> = self.rounds[self.round]
+                .iter()
+                .enumerate()
+                .filter(|(_, pairing)| !pairing.finished && pairing.game_id.is_none())
+                .filter_map(|(index, pairing)| {
+                    let present: Vec<&String> = pairing
+                        .players
+                        .iter()
+                        .filter(|player| !self.forfeited(player))
+                        .collect();
+
+                    match (pairing.players.len(), present.as_slice()) {
+                        (2, [_, _]) => None,
+                        (_, [winner]) => Some((index, Some((*winner).clone()))),
+                        _ => Some((index, None)),
+                    }
+                })
+                .collect();
+
+            for (index, winner) in walkovers {
+                let is_bye = self.rounds[self.round][index].players.len() == 1;
+                if let Some(player) = winner.as_deref().and_then(|winner| self.player_mut(winner)) {
+                    if !is_bye {
+                        player.wins += 1;
+                    }
+                }
+                let pairing = &mut self.rounds[self.round][index];
+                pairing.finished = true;
+                pairing.winner = winner;
+            }
+
+            if !self.rounds[self.round]
+                .iter()
+                .all(|pairing| pairing.finished)
+            {
+                return;
+            }
+            self.next_round();
+        }
+    }
+
+    fn next_round(&mut self) {
+        match self.rules.format {
+            TournamentFormat::SingleElimination => {
+                let winners: Vec<String> = self.rounds[self.round]
+                    .iter()
+                    .filter_map(|pairing| pairing.winner.clone())
+                    .collect();
+
+                if winners.len() > 1 {
+                    self.rounds.push(pair_up(&winners));
+                    self.round += 1;
+                } else {
+                    self.finish(winners.into_iter().next());
+                }
+            }
+            TournamentFormat::RoundRobin => {
+                if self.round + 1 < self.rounds.len() {
+                    self.round += 1;
+                } else {
+                    self.finish(self.standings().first().map(|player| player.id.clone()));
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, winner: Option<String>) {
+        self.state = TournamentState::Finished;
+        self.winner = winner;
+    }
+
+    /// Most wins first, ties go to the player that registered first.
+    pub fn standings(&self) -> Vec<&TournamentPlayer> {
+        let mut standings: Vec<&TournamentPlayer> = self.players.iter().collect();
+        standings.sort_by_key(|player| Reverse(player.wins));
+        standings
+    }
+}
+
+/// Players in order, the last one gets a bye when they are odd.
+fn pair_up(player_ids: &[String]) -> Vec<Pairing> {
+    player_ids
+        .chunks(2)
+        .map(|players| Pairing::new(players.to_vec()))
+        .collect()
+}
+
+/// Every player meets every other once, with the circle method.
+fn round_robin(player_ids: &[String]) -> Vec<Vec<Pairing>> {
+    let mut seats: Vec<Option<&String>> = player_ids.iter().map(Some).collect();
+    if seats.len() % 2 == 1 {
+        seats.push(None);
+    }
+
+    (1..seats.len())
+        .map(|_| {
+            let round = (0..seats.len() / 2)
+                .filter_map(|seat| match (seats[seat], seats[seats.len() - 1 - seat]) {
+                    (Some(player), Some(opponent)) => {
+                        Some(Pairing::new(vec![player.clone(), opponent.clone()]))
+                    }
+                    _ => None,
+                })
+                .collect();
+            seats[1..].rotate_right(1);
+            round
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::GameRules;
+    use crate::tournament::{Tournament, TournamentFormat, TournamentRules, TournamentState};
+
+    fn tournament(format: TournamentFormat, players: &[&str]) -> Tournament {
+        let mut tournament = Tournament::new(TournamentRules {
+            format,
+            players: players.len() as u8,
+            rules: GameRules::default(),
+        })
+        .unwrap();
+
+        for player in players {
+            tournament.register(player, player).unwrap();
+        }
+        tournament.start().unwrap();
+        tournament
+    }
+
+    /// Plays every game of the current round, the first player always wins.
+    fn play_round(tournament: &mut Tournament) {
+        for (pairing, player, _) in tournament.games_to_start() {
+            let game_id = format!("{}-{}", tournament.round, pairing);
+            tournament.game_started(pairing, &game_id);
+            tournament.game_finished(&game_id, Some(&player));
+        }
+    }
+
+    #[test]
+    fn test_tournament_rules() {
+        let rules = |players| TournamentRules {
+            format: TournamentFormat::RoundRobin,
+            players,
+            rules: GameRules::default(),
+        };
+
+        assert!(rules(4).validate().is_ok());
+        assert_eq!(
+            Err(String::from("Tournaments need 2 to 16 players")),
+            rules(1).validate()
+        );
+    }
+
+    #[test]
+    fn test_registration() {
+        let mut tournament = Tournament::new(TournamentRules {
+            format: TournamentFormat::SingleElimination,
+            players: 2,
+            rules: GameRules::default(),
+        })
+        .unwrap();
+
+        tournament.register("chico", "Chico").unwrap();
+        assert!(tournament.register("chico", "Chico").is_err());
+        assert!(tournament.start().is_err());
+        tournament.register("paloma", "Paloma").unwrap();
+        assert!(tournament.register("allan", "Allan").is_err());
+        assert_eq!(Ok(()), tournament.start());
+        assert_eq!(2, tournament.game_rules().max_players);
+    }
+
+    #[test]
+    fn test_single_elimination_with_bye() {
+        let mut tournament = tournament(
+            TournamentFormat::SingleElimination,
+            &["chico", "paloma", "allan"],
+        );
+
+        assert_eq!(1, tournament.games_to_start().len());
+        play_round(&mut tournament);
+
+        let games = tournament.games_to_start();
+        assert_eq!(1, tournament.round);
+        assert_eq!((0, String::from("chico"), String::from("allan")), games[0]);
+
+        play_round(&mut tournament);
+        assert_eq!(TournamentState::Finished, tournament.state());
+        assert_eq!(Some(String::from("chico")), tournament.winner);
+    }
+
+    #[test]
+    fn test_single_elimination_forfeit() {
+        let mut tournament = tournament(
+            TournamentFormat::SingleElimination,
+            &["chico", "paloma", "allan", "lucas"],
+        );
+
+        tournament.leave("lucas");
+        assert_eq!(1, tournament.games_to_start().len());
+        play_round(&mut tournament);
+
+        assert_eq!(
+            vec![String::from("chico"), String::from("allan")],
+            tournament.rounds[1][0].players
+        );
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut tournament =
+            tournament(TournamentFormat::RoundRobin, &["chico", "paloma", "allan"]);
+        assert_eq!(3, tournament.rounds.len());
+
+        while tournament.state() == TournamentState::InProgress {
+            play_round(&mut tournament);
+        }
+
+        let games: usize = tournament.rounds.iter().map(|round| round.len()).sum();
+        assert_eq!(3, games);
+        assert_eq!(Some(String::from("chico")), tournament.winner);
+        assert_eq!(2, tournament.standings()[0].wins);
+    }
+}
@@ -0,0 +1,185 @@
+use crate::tls::TlsStream;
+use crate::websocket::WebSocketStream;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+
+/// Byte stream a `Connection` runs over. Clones share the stream so one of
+/// them can keep reading while another writes, closing any of them closes
+/// it for both ends.
+pub trait Transport: Read + Write + Send + Sized + 'static {
+    fn try_clone(&self) -> io::Result<Self>;
+    fn close(&mut self) -> io::Result<()>;
+    /// Reads waiting longer fail with `WouldBlock` or `TimedOut`, `None`
+    /// waits for ever.
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Transport for TcpStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        TcpStream::try_clone(self)
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Transport for TlsStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        TlsStream::try_clone(self)
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TlsStream::set_read_timeout(self, timeout)
+    }
+}
+
+impl Transport for WebSocketStream {
+    fn try_clone(&self) -> io::Result<Self> {
+        WebSocketStream::try_clone(self)
+    }
+
+    fn close(&mut self) -> io::Result<()> {
+        self.shutdown(Shutdown::Both)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        WebSocketStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(test)]
+pub use duplex::{duplex, Duplex};
+
+/// In memory transport, the protocol runs over it without sockets.
+#[cfg(test)]
+mod duplex {
+    use super::Transport;
+    use std::collections::VecDeque;
+    use std::io::{self, ErrorKind, Read, Write};
+    use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+    use std::time::{Duration, Instant};
+
+    #[derive(Default)]
+    struct Buffer {
+        bytes: VecDeque<u8>,
+        closed: bool,
+        read_timeout: Option<Duration>,
+    }
+
+    #[derive(Default)]
+    struct Pipe {
+        buffer: Mutex<Buffer>,
+        readable: Condvar,
+    }
+
+    impl Pipe {
+        fn lock(&self) -> io::Result<MutexGuard<'_, Buffer>> {
+            self.buffer
+                .lock()
+                .map_err(|_| io::Error::other("Pipe poisoned"))
+        }
+
+        fn close(&self) -> io::Result<()> {
+            self.lock()?.closed = true;
+            self.readable.notify_all();
+            Ok(())
+        }
+    }
+
+    /// One end of an in memory pipe pair, what one end writes the other reads.
+    pub struct Duplex {
+        incoming: Arc<Pipe>,
+        outgoing: Arc<Pipe>,
+    }
+
+    pub fn duplex() -> (Duplex, Duplex) {
+        let one = Arc::new(Pipe::default());
+        let other = Arc::new(Pipe::default());
+
+        (
+            Duplex {
+                incoming: Arc::clone(&one),
+                outgoing: Arc::clone(&other),
+            },
+            Duplex {
+                incoming: other,
+                outgoing: one,
+            },
+        )
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut buffer = self.incoming.lock()?;
+            let deadline = buffer.read_timeout.map(|timeout| Instant::now() + timeout);
+            while buffer.bytes.is_empty() && !buffer.closed {
+                let readable = &self.incoming.readable;
+                buffer = match deadline {
+                    Some(deadline) => {
+                        let left = deadline
+                            .checked_duration_since(Instant::now())
+                            .ok_or_else(|| io::Error::new(ErrorKind::WouldBlock, "Timed out"))?;
+                        readable
+                            .wait_timeout(buffer, left)
+                            .map(|(buffer, _)| buffer)
+                            .ok()
+                    }
+                    None => readable.wait(buffer).ok(),
+                }
+                .ok_or_else(|| io::Error::other("Pipe poisoned"))?;
+            }
+
+            let size = buf.len().min(buffer.bytes.len());
+            for (byte, received) in buf.iter_mut().zip(buffer.bytes.drain(..size)) {
+                *byte = received;
+            }
+            Ok(size)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut buffer = self.outgoing.lock()?;
+            if buffer.closed {
+                return Err(io::Error::new(ErrorKind::BrokenPipe, "Pipe closed"));
+            }
+
+            buffer.bytes.extend(buf);
+            self.outgoing.readable.notify_all();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Duplex {
+        fn try_clone(&self) -> io::Result<Self> {
+            Ok(Duplex {
+                incoming: Arc::clone(&self.incoming),
+                outgoing: Arc::clone(&self.outgoing),
+            })
+        }
+
+        fn close(&mut self) -> io::Result<()> {
+            self.incoming.close()?;
+            self.outgoing.close()
+        }
+
+        fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+            self.incoming.lock()?.read_timeout = timeout;
+            Ok(())
+        }
+    }
+}
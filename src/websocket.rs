@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tungstenite::{Error, Message, WebSocket};
+
+const READ_SIZE: usize = 4096;
+
+/// Socket under the WebSocket session. Once the handshake is done it only
+/// reads bytes already received, so the session never waits for the peer
+/// while holding its lock.
+struct Socket {
+    received: VecDeque<u8>,
+    tcp: TcpStream,
+    blocking: bool,
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.received.is_empty() {
+            return self.received.read(buf);
+        }
+
+        if self.blocking {
+            self.tcp.read(buf)
+        } else {
+            Err(io::Error::from(ErrorKind::WouldBlock))
+        }
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tcp.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.tcp.flush()
+    }
+}
+
+struct Session {
+    websocket: WebSocket<Socket>,
+    message: VecDeque<u8>,
+}
+
+/// Binary WebSocket messages read and written as one byte stream, frames
+/// may span messages. Clones share the session like a plain `TcpStream`.
+pub struct WebSocketStream {
+    session: Arc<Mutex<Session>>,
+    tcp: TcpStream,
+}
+
+impl WebSocketStream {
+    /// Runs the server side of the Http upgrade.
+    pub fn accept(tcp: TcpStream) -> Result<WebSocketStream, String> {
+        let socket = Socket {
+            received: VecDeque::new(),
+            tcp: tcp.try_clone().map_err(|error| format!("{}", error))?,
+            blocking: true,
+        };
+        let mut websocket = tungstenite::accept(socket).map_err(|error| format!("{}", error))?;
+        websocket.get_mut().blocking = false;
+
+        Ok(WebSocketStream {
+            session: Arc::new(Mutex::new(Session {
+                websocket,
+                message: VecDeque::new(),
+            })),
+            tcp,
+        })
+    }
+
+    pub fn try_clone(&self) -> io::Result<WebSocketStream> {
+        Ok(WebSocketStream {
+            session: Arc::clone(&self.session),
+            tcp: self.tcp.try_clone()?,
+        })
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.tcp.set_read_timeout(timeout)
+    }
+
+    pub fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        let mut session = lock(&self.session)?;
+        session.websocket.close(None).ok();
+        session.websocket.flush().ok();
+        self.tcp.shutdown(how)
+    }
+}
+
+fn lock(session: &Mutex<Session>) -> io::Result<MutexGuard<'_, Session>> {
+    session
+        .lock()
+        .map_err(|_| io::Error::other("WebSocket session poisoned"))
+}
+
+fn io_error(error: Error) -> io::Error {
+    match error {
+        Error::Io(error) => error,
+        Error::ConnectionClosed | Error::AlreadyClosed => {
+            io::Error::new(ErrorKind::BrokenPipe, "WebSocket closed")
+        }
+        error => io::Error::new(ErrorKind::InvalidData, error),
+    }
+}
+
+impl Read for WebSocketStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            {
+                let mut session = lock(&self.session)?;
+                loop {
+                    if !session.message.is_empty() {
+                        return session.message.read(buf);
+                    }
+
+                    match session.websocket.read() {
+                        Ok(Message::Binary(data)) => session.message.extend(data),
+                        // Pings are answered and closes acknowledged on flush
+                        Ok(Message::Close(_)) => {
+                            session.websocket.flush().ok();
+                            return Ok(0);
+                        }
+                        Ok(_) => session.websocket.flush().map_err(io_error)?,
+                        Err(Error::Io(error)) if error.kind() == ErrorKind::WouldBlock => break,
+                        Err(Error::ConnectionClosed) | Err(Error::AlreadyClosed) => return Ok(0),
+                        Err(error) => return Err(io_error(error)),
+                    }
+                }
+            }
+
+            // Waiting for the peer must not hold the session, writers need it
+            let mut received = [0; READ_SIZE];
+            let size = self.tcp.read(&mut received)?;
+            if size == 0 {
+                return Ok(0);
+            }
+
+            let mut session = lock(&self.session)?;
+            session
+                .websocket
+                .get_mut()
+                .received
+                .extend(&received[..size]);
+        }
+    }
+}
+
+impl Write for WebSocketStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut session = lock(&self.session)?;
+        session
+            .websocket
+            .send(Message::Binary(buf.to_vec()))
+            .map_err(io_error)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut session = lock(&self.session)?;
+        session.websocket.flush().map_err(io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protocol::{Frame, CHAT_GAME, CHAT_LOBBY};
+    use crate::websocket::WebSocketStream;
+    use std::io::Write;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use tungstenite::Message;
+
+    #[test]
+    fn test_websocket_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            let mut reader = WebSocketStream::accept(tcp).unwrap();
+            let mut writer = reader.try_clone().unwrap();
+
+            let frame = Frame::read_from(&mut reader).unwrap();
+            let answer = Frame::text(CHAT_GAME, &frame.text_payload().unwrap());
+            writer.write_all(&Vec::<u8>::from(answer)).unwrap();
+            Frame::read_from(&mut reader).is_err()
+        });
+
+        let tcp = TcpStream::connect(address).unwrap();
+        let (mut client, _) = tungstenite::client(format!("ws://{}/", address), tcp).unwrap();
+
+        // A frame split over two messages, with a ping in between
+        let frame = Vec::<u8>::from(Frame::text(CHAT_LOBBY, "Hello"));
+        client.send(Message::Binary(frame[..3].to_vec())).unwrap();
+        client.send(Message::Ping(vec![1])).unwrap();
+        client.send(Message::Binary(frame[3..].to_vec())).unwrap();
+
+        let mut answer = Vec::new();
+        while answer.is_empty() {
+            match client.read().unwrap() {
+                Message::Binary(data) => answer = data,
+                Message::Pong(data) => assert_eq!(vec![1], data),
+                message => panic!("Unexpected message {:?}", message),
+            }
+        }
+        client.close(None).unwrap();
+
+        let answer = Frame::read_from(&mut &answer[..]).unwrap();
+        assert_eq!(Frame::text(CHAT_GAME, "Hello"), answer);
+        assert!(server.join().unwrap(), "Closed stream must not read frames");
+    }
+}